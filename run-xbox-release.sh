@@ -5,4 +5,4 @@ if [ ! -e true-ev-stream.pipe ]; then
 fi
 DEV=/dev/input/by-id/usb-Microsoft_Controller_3039373030333732303232323132-event-joystick
 intercept -g "$DEV" > true-ev-stream.pipe &
-stdbuf -i 0 -o 0 -- ./target/release/analogstick2wheel < true-ev-stream.pipe | uinput -c xbox.yaml
+stdbuf -i 0 -o 0 -- ./target/release/analogstick2wheel --layout xbox < true-ev-stream.pipe | uinput -c xbox.yaml
@@ -0,0 +1,193 @@
+use std::{
+    f64::consts::TAU,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::config_file::ConfigFile;
+
+// maximum reachable raw magnitude per angular sector, for sticks whose gate
+// isn't circular (square/octagonal gates reach further on the diagonals)
+#[derive(Clone, Debug, PartialEq)]
+pub struct GateTable {
+    sectors: Vec<f64>,
+}
+
+impl GateTable {
+    pub fn new(sectors: usize, fallback: f64) -> Self {
+        Self { sectors: vec![fallback; sectors.max(1)] }
+    }
+
+    pub fn from_values(values: Vec<f64>) -> Self {
+        Self { sectors: values }
+    }
+
+    pub fn values(&self) -> &[f64] {
+        &self.sectors
+    }
+
+    fn sector_width(&self) -> f64 {
+        TAU / self.sectors.len() as f64
+    }
+
+    fn sector_of(&self, angle: f64) -> usize {
+        ((angle.rem_euclid(TAU) / self.sector_width()) as usize).min(self.sectors.len() - 1)
+    }
+
+    // linearly interpolated between sector centers so the normalization
+    // doesn't step when crossing a sector boundary
+    pub fn max_magnitude(&self, angle: f64) -> f64 {
+        self.at(angle)
+    }
+
+    // the table holds any per-sector quantity, e.g. grip thresholds
+    pub fn at(&self, angle: f64) -> f64 {
+        let n = self.sectors.len();
+        let pos = angle.rem_euclid(TAU) / self.sector_width() - 0.5;
+        let lower = pos.floor();
+        let t = pos - lower;
+        let i0 = (lower as isize).rem_euclid(n as isize) as usize;
+        let i1 = (i0 + 1) % n;
+        (1.0 - t) * self.sectors[i0] + t * self.sectors[i1]
+    }
+
+    // returns whether the table changed
+    pub fn observe(&mut self, angle: f64, magnitude: f64) -> bool {
+        let i = self.sector_of(angle);
+        if magnitude > self.sectors[i] {
+            self.sectors[i] = magnitude;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn to_arg(&self) -> String {
+        self.sectors.iter()
+            .map(|v| format!("{v:.0}"))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+// where a released stick comes to rest over a session, fitted against time
+// so a stick that slowly wanders off center shows up as a drift rate
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DriftLog {
+    samples: f64,
+    sum_t: f64,
+    sum_m: f64,
+    sum_tm: f64,
+    sum_tt: f64,
+}
+
+impl DriftLog {
+    // `t` in seconds, `magnitude` normalized
+    pub fn observe(&mut self, t: f64, magnitude: f64) {
+        self.samples += 1.0;
+        self.sum_t += t;
+        self.sum_m += magnitude;
+        self.sum_tm += t * magnitude;
+        self.sum_tt += t * t;
+    }
+
+    pub fn mean_offset(&self) -> Option<f64> {
+        (self.samples > 0.0).then(|| self.sum_m / self.samples)
+    }
+
+    // least squares slope of the resting magnitude, per second
+    pub fn rate(&self) -> Option<f64> {
+        let spread = self.samples * self.sum_tt - self.sum_t * self.sum_t;
+        (self.samples > 1.0 && spread > 0.0)
+            .then(|| (self.samples * self.sum_tm - self.sum_t * self.sum_m) / spread)
+    }
+
+    pub fn report(&self, warn_above: f64) {
+        let (Some(mean), Some(rate)) = (self.mean_offset(), self.rate()) else {
+            eprintln!("drift: not enough resting samples");
+            return;
+        };
+        eprintln!("drift: resting stick offset {:.2}% of full deflection, changing by {:+.3}%/min over {} samples",
+                  mean * 100.0, rate * 60.0 * 100.0, self.samples);
+        if mean > warn_above {
+            eprintln!("warning: resting stick offset exceeds {:.2}%, consider recalibrating or raising --deadzone",
+                      warn_above * 100.0);
+        }
+    }
+}
+
+// calibration kept apart from the config, one file per profile, so a shared
+// profile doesn't carry someone else's stick along with it
+pub struct CalibrationStore {
+    dir: PathBuf,
+}
+
+impl CalibrationStore {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+
+    // running without --profile has a calibration of its own too
+    pub fn path(&self, profile: Option<&str>) -> PathBuf {
+        self.dir.join(format!("{}.toml", profile.unwrap_or("default")))
+    }
+
+    // None until one was saved for the profile
+    pub fn load(&self, profile: Option<&str>) -> Result<Option<ConfigFile>, String> {
+        let path = self.path(profile);
+        if !path.exists() {
+            return Ok(None);
+        }
+        ConfigFile::load(&path).map(Some)
+    }
+
+    pub fn save(&self, profile: Option<&str>, text: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(profile), text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gate_table_interpolates_between_sector_centers() {
+        let gate = GateTable::from_values(vec![100.0, 200.0, 300.0, 400.0]);
+        let quarter = TAU / 4.0;
+        assert_eq!(gate.max_magnitude(quarter * 0.5), 100.0);
+        assert_eq!(gate.max_magnitude(quarter * 1.5), 200.0);
+        assert_eq!(gate.max_magnitude(quarter), 150.0);
+        // wraps around from the last sector to the first
+        assert_eq!(gate.max_magnitude(0.0), 250.0);
+        assert_eq!(gate.max_magnitude(-quarter * 0.5), 400.0);
+    }
+
+    #[test]
+    fn gate_table_learns_only_larger_magnitudes() {
+        let mut gate = GateTable::new(4, 1000.0);
+        assert!(!gate.observe(0.1, 900.0));
+        assert!(gate.observe(0.1, 1200.0));
+        assert!(!gate.observe(0.2, 1100.0));
+        assert_eq!(gate.values(), [1200.0, 1000.0, 1000.0, 1000.0]);
+        assert_eq!(gate.to_arg(), "1200,1000,1000,1000");
+    }
+
+    #[test]
+    fn the_drift_log_fits_offset_and_rate() {
+        let mut drift = DriftLog::default();
+        assert_eq!((drift.mean_offset(), drift.rate()), (None, None));
+        drift.observe(0.0, 0.01);
+        assert_eq!(drift.rate(), None);
+        for t in 1..=10 {
+            drift.observe(t as f64, 0.01 + 0.001 * t as f64);
+        }
+        assert!((drift.mean_offset().unwrap() - 0.015).abs() < 1e-12);
+        assert!((drift.rate().unwrap() - 0.001).abs() < 1e-12);
+        // samples all at the same moment have no slope
+        let mut still = DriftLog::default();
+        still.observe(2.0, 0.01);
+        still.observe(2.0, 0.03);
+        assert_eq!(still.rate(), None);
+    }
+}
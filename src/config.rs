@@ -0,0 +1,1722 @@
+use clap::{parser::ValueSource, error::ErrorKind, Arg, ArgAction, ArgMatches, Command};
+use input_linux::{AbsoluteAxis, Key, RelativeAxis};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use crate::{State, calibration::{CalibrationStore, GateTable}, config_file::{CONFIG_VERSION, ConfigFile}, control::ControlCommand, curve::{PiecewiseGamma, ResponseTable, SmoothingCurve, SpeedGain}, input::EventLayout, output::Backpressure, rotate::Rotation, trace::{DebugField, DebugStyle, Trigger}};
+
+// what a stick between the deadzone and the grip threshold does
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MidBand {
+    // ease back to center as if released
+    Ignore,
+    // follow stick rotation at a reduced gain
+    Track,
+    // nudge the wheel by the stick's sideways deflection
+    Fine,
+}
+
+// where the timestamps of emitted events come from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeSource {
+    // keep the input's time, synthesized reports use the monotonic clock
+    Input,
+    // realtime at startup advanced by the monotonic clock, never steps backwards
+    Monotonic,
+    // the wall clock when the event goes out
+    Realtime,
+}
+
+// the virtual device the output is meant for, as declared to interception-tools' uinput
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    // desc.yaml, an unsigned 0 to 65535 wheel axis
+    Default,
+    // xbox.yaml, an Xbox 360 pad with signed sticks that SDL maps out of the box
+    Xbox,
+}
+
+impl Layout {
+    pub fn description(self) -> &'static str {
+        match self {
+            Layout::Default => include_str!("../desc.yaml"),
+            Layout::Xbox => include_str!("../xbox.yaml"),
+        }
+    }
+
+    // (minimum, maximum) of a wheel axis with `resolution` steps, the
+    // default layout is unsigned and xbox centers it on zero
+    pub fn extent(self, resolution: i32) -> (i32, i32) {
+        match self {
+            Layout::Default => (0, resolution),
+            Layout::Xbox => (-resolution/2 - 1, resolution/2),
+        }
+    }
+
+    // output axis value of a centered wheel
+    pub fn center(self, resolution: i32) -> i32 {
+        match self {
+            Layout::Default => resolution/2,
+            Layout::Xbox => 0,
+        }
+    }
+}
+
+// what --steering-rumble follows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RumbleSource {
+    // how far the stick is pushed while gripped
+    Magnitude,
+    // how fast the wheel turns, full strength at one turn per second
+    Speed,
+}
+
+// what --gyro-axis does to the wheel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GyroMode {
+    // the integrated rate alone turns the wheel, the stick is ignored
+    Replace,
+    // rotation from the gyro adds on top of the stick's steering
+    Fuse,
+}
+
+// what --axis-skew does about stick axes reported too far apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkewAction {
+    Warn,
+    // carry the older axis forward along its last slope to the newer one's time
+    Interpolate,
+}
+
+// how the stick drives the wheel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SteeringMode {
+    // gripping and rotating the stick turns the wheel
+    Wheel,
+    // the stick's sideways deflection is the wheel position, fully right is full right lock
+    Push,
+}
+
+// what a fresh grip measures stick rotation against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GripReference {
+    // pick up wherever the wheel is, only rotation during the grip counts
+    Wheel,
+    // stick straight up is center, grabbing turns the wheel to where the stick points
+    Stick,
+}
+
+// everything Config::resolve took, except the profile picked
+#[derive(Debug)]
+struct Layers {
+    file: Option<ConfigFile>,
+    env: Vec<(String, String)>,
+    cmd: Command,
+    matches: ArgMatches,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub settings: Vec<(String, String)>,
+}
+
+// when the timer thread keeps easing the wheel without fresh input
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutoCenter {
+    // at or below this the wheel counts as centered and is left to rest
+    pub min_angle: f64,
+    // the stick states of the last frame that let the wheel drift back
+    pub states: Vec<State>,
+    // spacing between synthesized reports
+    pub interval: Duration,
+}
+
+impl Default for AutoCenter {
+    fn default() -> Self {
+        Self {
+            min_angle: crate::REST_ANGLE,
+            states: vec![State::Freewheel],
+            interval: Duration::from_millis(4),
+        }
+    }
+}
+
+impl AutoCenter {
+    // `strength` is the centering the tick would apply, `stalled_at` where the
+    // last synthesized tick left the wheel when it didn't move it
+    pub fn triggers(&self, wheel_angle: f64, state: State, since_report: Duration, strength: f64, stalled_at: Option<f64>) -> bool {
+        wheel_angle.abs() > self.min_angle
+            && strength > 0.0
+            && stalled_at != Some(wheel_angle)
+            && self.states.contains(&state)
+            && since_report >= self.interval
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub profiles: Vec<Profile>,
+    pub profile: Option<String>,
+    // what the config was resolved from, kept to switch profiles at runtime
+    layers: Option<Arc<Layers>>,
+    pub steering_stop: f64,
+    pub invert_x: bool,
+    pub invert_y: bool,
+    pub deadzone: f64,
+    pub saturation: Option<f64>,
+    pub max_magnitude_x: f64,
+    pub max_magnitude_y: f64,
+    pub clamp_input: bool,
+    pub grip_smoothing: Option<f64>,
+    pub grip_angle_fallback: bool,
+    pub stick_smoothing: Option<SmoothingCurve>,
+    pub release_smoothing: Option<f64>,
+    pub reanchor_on_grip: bool,
+    pub gate: Option<GateTable>,
+    pub grip_thresholds: Option<GateTable>,
+    // (from, to) clockwise from straight up, in radians
+    pub steering_sector: Option<(f64, f64)>,
+    pub calibrate: bool,
+    pub calibrate_duration: Option<Duration>,
+    pub hold_while_calibrating: bool,
+    pub diagnose: Option<PathBuf>,
+    pub drift_log: bool,
+    pub drift_warn: f64,
+    pub drift_correction: Option<f64>,
+    pub drift_limit: f64,
+    // resting stick position learned by --drift-correction, in MAX_MAGNITUDE units
+    pub center_offset: (f64, f64),
+    pub calibration_dir: Option<PathBuf>,
+    pub max_dt: Duration,
+    pub mode: SteeringMode,
+    pub ratchet: bool,
+    pub grip_blend: f64,
+    pub grip_reference: GripReference,
+    pub min_grip: f64,
+    pub flick_threshold: Option<f64>,
+    pub reversal_damping: f64,
+    pub speed_gain: Option<SpeedGain>,
+    pub reversal_window: f64,
+    pub mid_band: MidBand,
+    pub mid_band_gain: f64,
+    pub mid_band_rate: f64,
+    pub inertia: bool,
+    pub inertia_scale: f64,
+    pub friction: f64,
+    pub speed_axis: Option<AbsoluteAxis>,
+    pub speed_axis_max: f64,
+    pub fine_axis: Option<AbsoluteAxis>,
+    pub fine_range: f64,
+    pub gyro_axis: Option<AbsoluteAxis>,
+    pub gyro_resolution: f64,
+    pub invert_gyro: bool,
+    pub gyro_mode: GyroMode,
+    pub freewheel_scroll: Option<f64>,
+    pub scroll_axis: RelativeAxis,
+    pub spring_threshold: f64,
+    pub spring_gain: f64,
+    // share of the distance to center the released wheel closes per second
+    pub centering_gain: f64,
+    pub state_socket: Option<PathBuf>,
+    pub shm: Option<String>,
+    pub response_table: Option<ResponseTable>,
+    pub gamma: PiecewiseGamma,
+    pub game_gamma: Option<f64>,
+    pub max_steer_accel: Option<f64>,
+    pub interpolate: Option<f64>,
+    pub max_runtime: Option<Duration>,
+    pub coalesce_axes: bool,
+    pub require_both_axes: bool,
+    pub input_layout: EventLayout,
+    pub coalesce_timeout: Duration,
+    pub split_regrip: bool,
+    pub axis_skew: Option<Duration>,
+    pub axis_skew_action: SkewAction,
+    pub time_source: TimeSource,
+    pub tee: Option<PathBuf>,
+    pub expect: Option<PathBuf>,
+    pub log_max_size: Option<u64>,
+    pub log_keep: usize,
+    pub debug_triggers: Vec<Trigger>,
+    pub debug_log: Option<PathBuf>,
+    pub debug_format: DebugStyle,
+    pub log_transitions: bool,
+    pub debug_fields: Vec<DebugField>,
+    pub keep_running: bool,
+    pub msc_timestamp: bool,
+    pub output_queue: Option<usize>,
+    pub batch_delay: Option<Duration>,
+    pub batch_size: usize,
+    pub backpressure: Backpressure,
+    pub writer_watchdog: Option<Duration>,
+    pub stall_timeout: Option<Duration>,
+    pub control: Option<PathBuf>,
+    pub bindings: Vec<(Key, ControlCommand)>,
+    pub hold_button: Option<Key>,
+    pub export_profile: Option<PathBuf>,
+    // every setting applied so far as it was given, the latest value of each key,
+    // so the live configuration can be written back out
+    pub settings: Vec<(String, String)>,
+    pub initial_report: bool,
+    // wheel travel per second of the startup sweep, in radians
+    pub startup_sweep: Option<f64>,
+    pub heartbeat: Option<Duration>,
+    pub sync_on_change: bool,
+    pub rate_limit: Option<f64>,
+    pub output_rate: Option<f64>,
+    pub rate_burst: f64,
+    pub center_indicator: Option<PathBuf>,
+    pub center_band: f64,
+    pub center_snap_width: i32,
+    pub center_snap_strength: f64,
+    pub center_settle: Duration,
+    pub auto_center: AutoCenter,
+    pub output_margin: i32,
+    pub output_center: Option<i32>,
+    pub output_min: Option<i32>,
+    // largest value of the wheel axis under the default layout, xbox splits it around zero
+    pub output_resolution: i32,
+    pub output_max: Option<i32>,
+    pub layout: Layout,
+    pub print_layout: bool,
+    pub verbose: bool,
+    pub virtual_device: bool,
+    pub ff_device: Option<PathBuf>,
+    pub steering_rumble: Option<RumbleSource>,
+    pub rumble_gamma: f64,
+    pub rumble_strength: f64,
+    pub output_hysteresis: i32,
+    pub levels: Option<usize>,
+    pub level_hysteresis: f64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            profiles: Vec::new(),
+            profile: None,
+            layers: None,
+            steering_stop: crate::STEERING_STOP,
+            invert_x: false,
+            invert_y: false,
+            deadzone: 0.05,
+            saturation: None,
+            max_magnitude_x: crate::MAX_MAGNITUDE,
+            max_magnitude_y: crate::MAX_MAGNITUDE,
+            clamp_input: false,
+            grip_smoothing: None,
+            grip_angle_fallback: false,
+            stick_smoothing: None,
+            release_smoothing: None,
+            reanchor_on_grip: false,
+            gate: None,
+            grip_thresholds: None,
+            steering_sector: None,
+            calibrate: false,
+            calibrate_duration: None,
+            hold_while_calibrating: false,
+            diagnose: None,
+            drift_log: false,
+            drift_warn: 0.03,
+            drift_correction: None,
+            drift_limit: 0.1,
+            center_offset: (0.0, 0.0),
+            calibration_dir: None,
+            max_dt: Duration::from_millis(100),
+            mode: SteeringMode::Wheel,
+            ratchet: false,
+            grip_blend: 0.0,
+            grip_reference: GripReference::Wheel,
+            min_grip: 0.0,
+            flick_threshold: None,
+            reversal_damping: 0.0,
+            speed_gain: None,
+            reversal_window: 0.05,
+            mid_band: MidBand::Ignore,
+            mid_band_gain: 0.25,
+            mid_band_rate: 90f64.to_radians(),
+            inertia: false,
+            inertia_scale: 1.0,
+            friction: 4.0,
+            speed_axis: None,
+            speed_axis_max: 255.0,
+            fine_axis: None,
+            fine_range: 10f64.to_radians(),
+            gyro_axis: None,
+            // what hid-sony reports for the DualShock 4 and DualSense
+            gyro_resolution: 1024.0,
+            invert_gyro: false,
+            gyro_mode: GyroMode::Replace,
+            freewheel_scroll: None,
+            scroll_axis: RelativeAxis::Wheel,
+            spring_threshold: 0.2,
+            spring_gain: 4.0,
+            centering_gain: std::f64::consts::TAU/4.0,
+            state_socket: None,
+            shm: None,
+            response_table: None,
+            gamma: Default::default(),
+            game_gamma: None,
+            max_steer_accel: None,
+            interpolate: None,
+            max_runtime: None,
+            coalesce_axes: false,
+            require_both_axes: false,
+            input_layout: EventLayout::native(),
+            coalesce_timeout: Duration::from_millis(8),
+            split_regrip: false,
+            axis_skew: None,
+            axis_skew_action: SkewAction::Warn,
+            time_source: TimeSource::Input,
+            tee: None,
+            expect: None,
+            log_max_size: None,
+            log_keep: 3,
+            debug_triggers: Vec::new(),
+            debug_log: None,
+            debug_format: DebugStyle::Compact,
+            log_transitions: false,
+            debug_fields: DebugField::ALL.to_vec(),
+            keep_running: false,
+            msc_timestamp: false,
+            output_queue: None,
+            batch_delay: None,
+            batch_size: 4096,
+            backpressure: Backpressure::DropOldest,
+            writer_watchdog: None,
+            stall_timeout: None,
+            control: None,
+            bindings: Vec::new(),
+            hold_button: None,
+            export_profile: None,
+            settings: Vec::new(),
+            initial_report: false,
+            startup_sweep: None,
+            heartbeat: None,
+            sync_on_change: false,
+            rate_limit: None,
+            output_rate: None,
+            rate_burst: 4.0,
+            center_indicator: None,
+            center_band: 2f64.to_radians(),
+            center_snap_width: 0,
+            center_snap_strength: 0.5,
+            center_settle: Duration::ZERO,
+            auto_center: Default::default(),
+            output_margin: 0,
+            output_center: None,
+            output_min: None,
+            output_resolution: u16::MAX as i32,
+            output_max: None,
+            layout: Layout::Default,
+            print_layout: false,
+            verbose: false,
+            virtual_device: false,
+            ff_device: None,
+            steering_rumble: None,
+            rumble_gamma: 1.0,
+            rumble_strength: 0.5,
+            output_hysteresis: 0,
+            levels: None,
+            level_hysteresis: 0.1,
+        }
+    }
+}
+
+impl Config {
+    pub fn command() -> Command {
+        Command::new("analogstick2wheel")
+            .about("Turns analog stick rotation into a steering wheel axis")
+            .after_help("Settings can also come from ANALOGSTICK2WHEEL_<SETTING> environment variables, \
+                         e.g. ANALOGSTICK2WHEEL_STEERING_RANGE=900, which override --config and are overridden by the command line")
+            .arg(Arg::new("config").long("config").value_name("PATH")
+                 .help("Settings file of 'setting = value' lines, overridden by the environment and the command line"))
+            .arg(Arg::new("profile").long("profile").value_name("NAME")
+                 .help("Apply the settings of the config file's [profile.NAME] section"))
+            .arg(Arg::new("steering-range").long("steering-range").value_name("DEGREES")
+                 .help("Lock to lock rotation of the emulated wheel"))
+            .arg(Arg::new("invert-x").long("invert-x").action(ArgAction::SetTrue)
+                 .help("Negate the raw stick X axis, for controllers reporting it backwards"))
+            .arg(Arg::new("invert-y").long("invert-y").action(ArgAction::SetTrue)
+                 .help("Negate the raw stick Y axis, for controllers reporting it backwards"))
+            .arg(Arg::new("deadzone").long("deadzone").value_name("FRACTION")
+                 .help("Normalized stick magnitude below which the stick has no direction"))
+            .arg(Arg::new("saturation").long("saturation").value_name("FRACTION")
+                 .help("Normalized stick magnitude that already counts as full deflection, the range from the deadzone up to it is stretched to cover 0 to 1"))
+            .arg(Arg::new("max-magnitude-x").long("max-magnitude-x").value_name("UNITS")
+                 .help("Full deflection of the stick's X axis, for controllers whose axes reach different extents"))
+            .arg(Arg::new("max-magnitude-y").long("max-magnitude-y").value_name("UNITS")
+                 .help("Full deflection of the stick's Y axis"))
+            .arg(Arg::new("clamp-input").long("clamp-input").action(ArgAction::SetTrue)
+                 .help("Clamp stick values reaching past the axis' full deflection instead of taking them as is"))
+            .arg(Arg::new("stick-smoothing").long("stick-smoothing").value_name("SLOW_HZ,FAST_HZ,SPEED")
+                 .help("Low-pass the raw stick at SLOW_HZ when still, opening up to FAST_HZ at SPEED full deflections per second"))
+            .arg(Arg::new("grip-smoothing").long("grip-smoothing").value_name("MILLISECONDS")
+                 .help("Time constant of a low-pass on the magnitude used for grip detection, the angle stays unfiltered"))
+            .arg(Arg::new("grip-angle-fallback").long("grip-angle-fallback").action(ArgAction::SetTrue)
+                 .help("Give a grip whose stick has no direction of its own the last one it had, so its first rotation isn't dropped"))
+            .arg(Arg::new("release-smoothing").long("release-smoothing").value_name("MILLISECONDS")
+                 .help("Time constant of a low-pass on the wheel while it isn't gripped, gripped steering stays unfiltered"))
+            .arg(Arg::new("reanchor-on-grip").long("reanchor-on-grip").action(ArgAction::SetTrue)
+                 .help("Line the wheel up with the axis value the game last got whenever a grip starts, dropping whatever fraction of a unit accumulated"))
+            .arg(Arg::new("gate-sectors").long("gate-sectors").value_name("COUNT")
+                 .help("Number of angular sectors used for stick gate calibration"))
+            .arg(Arg::new("gate-table").long("gate-table").value_name("MAX,...")
+                 .help("Comma separated maximum raw magnitude per sector, as printed by --calibrate"))
+            .arg(Arg::new("grip-thresholds").long("grip-thresholds").value_name("FRACTION,...")
+                 .help("Comma separated grip threshold per sector starting along +X, interpolated in between"))
+            .arg(Arg::new("steering-sector").long("steering-sector").value_name("FROM,TO").allow_hyphen_values(true)
+                 .help("Only grip the wheel with the stick between these angles, in degrees clockwise from straight up, e.g. -90,90 for the top half"))
+            .arg(Arg::new("calibrate").long("calibrate").action(ArgAction::SetTrue)
+                 .help("Learn the stick gate shape while running and print the resulting --gate-table"))
+            .arg(Arg::new("calibrate-duration").long("calibrate-duration").value_name("SECONDS")
+                 .help("Stop calibrating after this long, a finish-calibration command ends it early"))
+            .arg(Arg::new("hold-while-calibrating").long("hold-while-calibrating").action(ArgAction::SetTrue)
+                 .help("Keep the wheel centered until calibration finishes instead of steering with a half learned gate"))
+            .arg(Arg::new("diagnose").long("diagnose").value_name("DEVICE")
+                 .help("Print what an evdev device reports about its axes and force feedback, then exit"))
+            .arg(Arg::new("drift-log").long("drift-log").action(ArgAction::SetTrue)
+                 .help("Track where the released stick rests and report its offset and drift rate on exit"))
+            .arg(Arg::new("drift-warn").long("drift-warn").value_name("FRACTION")
+                 .help("Resting stick offset, as a fraction of full deflection, that --drift-log warns about"))
+            .arg(Arg::new("drift-correction").long("drift-correction").value_name("MILLISECONDS")
+                 .help("Follow where the released stick rests with this time constant and treat that as center"))
+            .arg(Arg::new("drift-limit").long("drift-limit").value_name("FRACTION")
+                 .help("Largest center offset --drift-correction applies, as a fraction of full deflection"))
+            .arg(Arg::new("center-offset").long("center-offset").value_name("X,Y").allow_hyphen_values(true)
+                 .help("Stick position taken as center, in raw units, as --drift-correction learns it"))
+            .arg(Arg::new("calibration-dir").long("calibration-dir").value_name("DIR")
+                 .help("Keep the stick calibration in DIR/PROFILE.toml apart from the config, loaded at startup and saved on exit"))
+            .arg(Arg::new("max-dt").long("max-dt").value_name("MILLISECONDS")
+                 .help("Longest time step fed to the wheel model, so a stall doesn't snap the wheel in one step"))
+            .arg(Arg::new("mode").long("mode").value_name("wheel|push")
+                 .help("Turn the wheel by rotating the gripped stick, or set it straight from how far the stick is pushed sideways"))
+            .arg(Arg::new("ratchet").long("ratchet").action(ArgAction::SetTrue)
+                 .help("While gripped only allow turning further from center, unwinding needs a re-grip"))
+            .arg(Arg::new("grip-blend").long("grip-blend").value_name("MILLISECONDS")
+                 .help("Ramp from centering into following the stick over this long after a grip starts"))
+            .arg(Arg::new("grip-reference").long("grip-reference").value_name("wheel|stick")
+                 .help("Whether a new grip continues from the wheel's angle or lines the wheel up with the stick"))
+            .arg(Arg::new("min-grip").long("min-grip").value_name("MILLISECONDS")
+                 .help("How long the stick has to stay gripped before its rotation turns the wheel"))
+            .arg(Arg::new("flick-threshold").long("flick-threshold").value_name("DEGREES")
+                 .help("Per frame stick rotation past which a step against the current turn direction is taken the long way round"))
+            .arg(Arg::new("reversal-damping").long("reversal-damping").value_name("FRACTION")
+                 .help("How much of the stick rotation to hold back right after a gripped turn changes direction"))
+            .arg(Arg::new("reversal-window").long("reversal-window").value_name("MILLISECONDS")
+                 .help("How long after a direction change the reversal damping takes to wear off"))
+            .arg(Arg::new("speed-gain").long("speed-gain").value_name("CENTER,LOCK,DEGREES_PER_SECOND")
+                 .help("Scale gripped rotation at this stick speed and up by CENTER around center and LOCK near full lock, slower turns get less of it"))
+            .arg(Arg::new("mid-band").long("mid-band").value_name("ignore|track|fine")
+                 .help("Behaviour of a stick past the deadzone but not gripped"))
+            .arg(Arg::new("mid-band-gain").long("mid-band-gain").value_name("FRACTION")
+                 .help("Portion of stick rotation followed in the track mid band mode"))
+            .arg(Arg::new("mid-band-rate").long("mid-band-rate").value_name("DEGREES_PER_SECOND")
+                 .help("Wheel turn rate at full sideways deflection in the fine mid band mode"))
+            .arg(Arg::new("inertia").long("inertia").action(ArgAction::SetTrue)
+                 .help("Keep the wheel spinning after a fast release until friction stops it"))
+            .arg(Arg::new("inertia-scale").long("inertia-scale").value_name("FRACTION")
+                 .help("Portion of the last gripped angular velocity carried over on release"))
+            .arg(Arg::new("friction").long("friction").value_name("PER_SECOND")
+                 .help("Exponential decay rate of the coasting wheel's velocity"))
+            .arg(Arg::new("speed-axis").long("speed-axis").value_name("AXIS")
+                 .help("Absolute axis reporting vehicle speed (e.g. ABS_RZ), enables the return spring"))
+            .arg(Arg::new("speed-axis-max").long("speed-axis-max").value_name("VALUE")
+                 .help("Raw value of the speed axis at full speed"))
+            .arg(Arg::new("fine-axis").long("fine-axis").value_name("AXIS")
+                 .help("Second stick axis (e.g. ABS_RX) adding a small correction on top of the steering"))
+            .arg(Arg::new("fine-range").long("fine-range").value_name("DEGREES")
+                 .help("Wheel angle added at full deflection of the fine axis"))
+            .arg(Arg::new("gyro-axis").long("gyro-axis").value_name("AXIS")
+                 .help("Absolute axis reporting angular rate (e.g. ABS_RY of a motion sensor), integrated into the wheel angle"))
+            .arg(Arg::new("gyro-resolution").long("gyro-resolution").value_name("UNITS")
+                 .help("Raw gyro axis units per degree per second"))
+            .arg(Arg::new("invert-gyro").long("invert-gyro").action(ArgAction::SetTrue)
+                 .help("Turn the wheel the other way for the same rotation of the controller"))
+            .arg(Arg::new("gyro-mode").long("gyro-mode").value_name("replace|fuse")
+                 .help("Steer with the gyro alone, or add its rotation to the stick's"))
+            .arg(Arg::new("freewheel-scroll").long("freewheel-scroll").value_name("DEGREES")
+                 .help("Turn stick rotation below the grip into one scroll step per this many degrees, e.g. for menus"))
+            .arg(Arg::new("scroll-axis").long("scroll-axis").value_name("AXIS")
+                 .help("Relative axis receiving --freewheel-scroll steps, REL_WHEEL by default"))
+            .arg(Arg::new("spring-threshold").long("spring-threshold").value_name("FRACTION")
+                 .help("Normalized speed above which the return spring engages"))
+            .arg(Arg::new("centering-gain").long("centering-gain").value_name("PER_SECOND")
+                 .help("How fast the released wheel returns, in proportion to how far off center it is, 1.57 by default"))
+            .arg(Arg::new("spring-gain").long("spring-gain").value_name("GAIN")
+                 .help("Centering strength of the return spring at full speed"))
+            .arg(Arg::new("state-socket").long("state-socket").value_name("PATH")
+                 .help("Unix socket or named pipe notified on every grip state transition"))
+            .arg(Arg::new("shm").long("shm").value_name("NAME")
+                 .help("Shared memory segment, in /dev/shm unless given as a path, holding the latest analysis for overlays"))
+            .arg(Arg::new("response-table").long("response-table").value_name("PATH")
+                 .help("File of 'input output' wheel angle pairs in degrees shaping the steering response"))
+            .arg(Arg::new("inner-gamma").long("inner-gamma").value_name("EXPONENT")
+                 .help("Response exponent below the curve crossover, above 1 gives finer control near center"))
+            .arg(Arg::new("outer-gamma").long("outer-gamma").value_name("EXPONENT")
+                 .help("Response exponent above the curve crossover"))
+            .arg(Arg::new("curve-crossover").long("curve-crossover").value_name("FRACTION")
+                 .help("Fraction of full lock where the inner and outer curves meet"))
+            .arg(Arg::new("game-gamma").long("game-gamma").value_name("EXPONENT")
+                 .help("Exponent the game applies to the axis, pre-distorted away so the end result is linear"))
+            .arg(Arg::new("max-steer-accel").long("max-steer-accel").value_name("DEGREES_PER_SECOND_SQUARED")
+                 .help("Limit how quickly the output's steering rate may change, easing into and out of turns"))
+            .arg(Arg::new("interpolate").long("interpolate").value_name("REPORTS_PER_SECOND")
+                 .help("Glide between gripped input reports at this rate, for controllers that report slowly"))
+            .arg(Arg::new("max-runtime").long("max-runtime").value_name("SECONDS")
+                 .help("Center the wheel and exit after running this long"))
+            .arg(Arg::new("coalesce-axes").long("coalesce-axes").action(ArgAction::SetTrue)
+                 .help("Wait for both stick axes to update before processing a report, for controllers that sync after every axis"))
+            .arg(Arg::new("coalesce-timeout").long("coalesce-timeout").value_name("MILLISECONDS")
+                 .help("Process a half-updated stick anyway once the other axis is this late"))
+            .arg(Arg::new("split-regrip").long("split-regrip").action(ArgAction::SetTrue)
+                 .help("Treat a grip released and retaken between two coalesced frames as a fresh grip"))
+            .arg(Arg::new("axis-skew").long("axis-skew").value_name("MILLISECONDS")
+                 .help("Act on reports whose moving stick axes were last updated further apart than this, a stale axis skews the angle"))
+            .arg(Arg::new("axis-skew-action").long("axis-skew-action").value_name("warn|interpolate")
+                 .help("Whether --axis-skew only warns or also carries the stale axis forward to the newer one's time"))
+            .arg(Arg::new("require-both-axes").long("require-both-axes").action(ArgAction::SetTrue)
+                 .help("Treat the stick as centered until both of its axes have reported at least once"))
+            .arg(Arg::new("input-time-bits").long("input-time-bits").value_name("32|64")
+                 .help("Width of the timestamp fields in the input events, for producers built for another word size than this one"))
+            .arg(Arg::new("event-time").long("event-time").value_name("input|monotonic|realtime")
+                 .help("Clock stamped on emitted events, input keeps the time of the event that caused them"))
+            .arg(Arg::new("tee").long("tee").value_name("PATH")
+                 .help("Mirror the emitted event stream to a file for inspection"))
+            .arg(Arg::new("expect").long("expect").value_name("PATH")
+                 .help("Compare the wheel reports against a --tee capture and report the first frame that differs"))
+            .arg(Arg::new("log-max-size").long("log-max-size").value_name("BYTES")
+                 .help("Rotate the --tee file and a --state-socket regular file once they grow past this size"))
+            .arg(Arg::new("log-keep").long("log-keep").value_name("COUNT")
+                 .help("Number of rotated files to keep as PATH.1 to PATH.COUNT, 0 just truncates"))
+            .arg(Arg::new("debug-trigger").long("debug-trigger").value_name("CONDITION,...")
+                 .help("Dump the wheel state and the last frames when any of delta>DEGREES or transition holds"))
+            .arg(Arg::new("debug-log").long("debug-log").value_name("PATH")
+                 .help("File receiving --debug-trigger snapshots instead of stderr"))
+            .arg(Arg::new("debug-format").long("debug-format").value_name("compact|multiline|off")
+                 .help("Layout of the per-frame debug output on stderr, off silences it"))
+            .arg(Arg::new("debug-fields").long("debug-fields").value_name("FIELD,...")
+                 .help("Which of x, y, angle, magnitude, state and wheel the per-frame debug output shows"))
+            .arg(Arg::new("log-transitions").long("log-transitions").action(ArgAction::SetTrue)
+                 .help("Log every grip and release with the magnitude and angle behind it, as `transition:` lines on stderr"))
+            .arg(Arg::new("keep-running").long("keep-running").action(ArgAction::SetTrue)
+                 .help("Carry on for mirrors and notifiers when the output pipe goes away instead of exiting"))
+            .arg(Arg::new("msc-timestamp").long("msc-timestamp").action(ArgAction::SetTrue)
+                 .help("Send an MSC_TIMESTAMP with every wheel report, in microseconds since startup"))
+            .arg(Arg::new("output-queue").long("output-queue").value_name("CHUNKS")
+                 .help("Write output from a separate thread through a queue of this many chunks"))
+            .arg(Arg::new("backpressure").long("backpressure").value_name("block|drop-oldest")
+                 .help("What to do when the output queue is full"))
+            .arg(Arg::new("writer-watchdog").long("writer-watchdog").value_name("MILLISECONDS")
+                 .help("Report output writes stuck for this long and center the wheel once they go through again; needs --output-queue"))
+            .arg(Arg::new("batch-delay").long("batch-delay").value_name("MILLISECONDS")
+                 .help("Collect output into fewer writes, holding each report back for at most about this long"))
+            .arg(Arg::new("batch-size").long("batch-size").value_name("BYTES")
+                 .help("Write a batch out early once it reaches this many bytes"))
+            .arg(Arg::new("stall-timeout").long("stall-timeout").value_name("MILLISECONDS")
+                 .help("Center the wheel if input stops this long while gripped off-center"))
+            .arg(Arg::new("initial-report").long("initial-report").action(ArgAction::SetTrue)
+                 .help("Emit a centered report at startup so games register the axis position right away"))
+            .arg(Arg::new("startup-sweep").long("startup-sweep").value_name("DEGREES_PER_SECOND")
+                 .help("Sweep the wheel from center to full left, full right and back at this speed before steering, for games that calibrate on the extremes"))
+            .arg(Arg::new("heartbeat").long("heartbeat").value_name("MILLISECONDS")
+                 .help("Repeat the last report when nothing was emitted for this long, for games that drop quiet axes"))
+            .arg(Arg::new("sync-on-change").long("sync-on-change").action(ArgAction::SetTrue)
+                 .help("Only emit a SYN_REPORT when something changed since the last one, still repeating the last report every --heartbeat (1000 ms unless set)"))
+            .arg(Arg::new("rate-limit").long("rate-limit").value_name("REPORTS_PER_SECOND")
+                 .help("Cap the rate of emitted wheel reports, excess reports collapse into the latest value"))
+            .arg(Arg::new("rate-burst").long("rate-burst").value_name("REPORTS")
+                 .help("Number of reports that may go out back to back before the rate limit applies"))
+            .arg(Arg::new("output-rate").long("output-rate").value_name("HZ")
+                 .help("Send the latest wheel value at exactly this rate (1 to 10000 Hz) from a timer, whatever rate the input comes in at; it also stands in for --heartbeat"))
+            .arg(Arg::new("center-indicator").long("center-indicator").value_name("PATH")
+                 .help("File written with 1 while the wheel is centered and 0 otherwise, e.g. an LED brightness"))
+            .arg(Arg::new("center-band").long("center-band").value_name("DEGREES")
+                 .help("How far from center the wheel still counts as centered for the indicator"))
+            .arg(Arg::new("center-snap-width").long("center-snap-width").value_name("UNITS")
+                 .help("Half width, in output axis units, of the band around center pulled towards it"))
+            .arg(Arg::new("center-snap-strength").long("center-snap-strength").value_name("FRACTION")
+                 .help("How hard the center band pulls, 0 passes through and 1 snaps to exact center"))
+            .arg(Arg::new("center-settle").long("center-settle").value_name("MILLISECONDS")
+                 .help("How long the wheel rests at center before the final centered report is sent"))
+            .arg(Arg::new("auto-center-angle").long("auto-center-angle").value_name("DEGREES")
+                 .help("Angle from center below which the wheel counts as centered and stops easing back"))
+            .arg(Arg::new("auto-center-states").long("auto-center-states").value_name("STATES")
+                 .help("Comma separated stick states the wheel eases back to center in; only freewheel for now, a gripped wheel follows the stick"))
+            .arg(Arg::new("auto-center-interval").long("auto-center-interval").value_name("MILLISECONDS")
+                 .help("Time between reports while the wheel eases back to center without input"))
+            .arg(Arg::new("output-margin").long("output-margin").value_name("UNITS")
+                 .help("Keep full lock this many axis units short of the axis extremes, for games that treat them as errors"))
+            .arg(Arg::new("output-center").long("output-center").value_name("VALUE")
+                 .help("Axis value of a centered wheel, for games that expect it off the midpoint"))
+            .arg(Arg::new("output-min").long("output-min").value_name("VALUE")
+                 .help("Axis value at full left lock"))
+            .arg(Arg::new("output-max").long("output-max").value_name("VALUE")
+                 .help("Axis value at full right lock"))
+            .arg(Arg::new("output-resolution").long("output-resolution").value_name("MAX")
+                 .help("Largest wheel axis value, 65535 by default; the device description and --print-layout follow it"))
+            .arg(Arg::new("layout").long("layout").value_name("default|xbox")
+                 .help("Virtual device the output is shaped for, xbox centers the wheel axis on zero"))
+            .arg(Arg::new("print-layout").long("print-layout").action(ArgAction::SetTrue)
+                 .help("Print the uinput device description matching --layout, then exit"))
+            .arg(Arg::new("verbose").long("verbose").action(ArgAction::SetTrue)
+                 .help("Print a table of sample stick inputs and the wheel angles and axis values they produce at startup"))
+            .arg(Arg::new("virtual-device").long("virtual-device").action(ArgAction::SetTrue)
+                 .help("Create the --layout device on /dev/uinput directly instead of writing events to stdout, so force feedback the game sends to it can be picked up"))
+            .arg(Arg::new("ff-device").long("ff-device").value_name("PATH")
+                 .help("Event device, usually the controller itself, that plays the game's force feedback under --virtual-device and --steering-rumble"))
+            .arg(Arg::new("steering-rumble").long("steering-rumble").value_name("magnitude|speed")
+                 .help("Rumble --ff-device in proportion to how hard the stick is pushed while gripped, or how fast the wheel turns"))
+            .arg(Arg::new("rumble-gamma").long("rumble-gamma").value_name("EXPONENT")
+                 .help("Response exponent of --steering-rumble, above 1 keeps light steering quiet"))
+            .arg(Arg::new("rumble-strength").long("rumble-strength").value_name("FRACTION")
+                 .help("Fraction of full motor power --steering-rumble reaches at full effort"))
+            .arg(Arg::new("output-hysteresis").long("output-hysteresis").value_name("UNITS")
+                 .help("Hold back output changes of up to this many axis units until the value stops moving"))
+            .arg(Arg::new("levels").long("levels").value_name("COUNT")
+                 .help("Only emit this many evenly spaced positions, e.g. 3 for full left, center and full right"))
+            .arg(Arg::new("level-hysteresis").long("level-hysteresis").value_name("FRACTION")
+                 .help("How far past the midpoint between two --levels the wheel has to go to switch, as a fraction of their spacing"))
+            .arg(Arg::new("control").long("control").value_name("FIFO")
+                 .help("Named pipe accepting commands: center, set-range <degrees>, set <setting> <value>, calibrate, finish-calibration, export-profile <path>, pause, resume, next-profile"))
+            .arg(Arg::new("bind").long("bind").value_name("KEY=COMMAND").action(ArgAction::Append)
+                 .help("Run a control command when a button (e.g. BTN_SELECT) is pressed, bound buttons don't reach the game"))
+            .arg(Arg::new("hold-button").long("hold-button").value_name("KEY")
+                 .help("Button that keeps the wheel where it is after letting go of the stick while held, e.g. for parking; it doesn't reach the game"))
+            .arg(Arg::new("export-profile").long("export-profile").value_name("PATH")
+                 .help("On exit, write the live settings including calibration results to a file usable with --config"))
+    }
+
+    pub fn from_args() -> Self {
+        let mut cmd = Self::command();
+        let matches = cmd.clone().get_matches();
+        let env: Vec<(String, String)> = std::env::vars().filter(|(name, _)| name.starts_with(ENV_PREFIX)).collect();
+        let result = matches.get_one::<String>("config")
+            .map(|path| ConfigFile::load(path.as_ref()))
+            .transpose()
+            .and_then(|file| {
+                let mut cfg = Self::resolve(Self::default(), file.as_ref(), &env, &cmd, &matches)?;
+                cfg.layers = Some(Arc::new(Layers { file, env: env.clone(), cmd: cmd.clone(), matches: matches.clone() }));
+                Ok(cfg)
+            });
+        result.unwrap_or_else(|e| cmd.error(ErrorKind::InvalidValue, e).exit())
+    }
+
+    // the config as if started with `--profile` set to the one after the
+    // current, in file order; settings changed at runtime don't carry over
+    pub fn next_profile(&self) -> Result<Self, String> {
+        let layers = self.layers.as_ref().ok_or("no config to switch profiles in")?;
+        let position = self.profiles.iter().position(|p| Some(&p.name) == self.profile.as_ref());
+        let next = match position {
+            Some(i) => &self.profiles[(i + 1) % self.profiles.len()],
+            None => self.profiles.first().ok_or("the config defines no profiles to switch to")?,
+        };
+        let mut cfg = Self::resolve_profile(Self::default(), layers.file.as_ref(), Some(&next.name),
+                                            &layers.env, &layers.cmd, &layers.matches)?;
+        cfg.layers = self.layers.clone();
+        Ok(cfg)
+    }
+
+    // defaults, then the config file and its selected profile, then
+    // ANALOGSTICK2WHEEL_* environment variables, then the command line;
+    // reads nothing itself so every layer can be handed in directly
+    pub fn resolve(defaults: Self, file: Option<&ConfigFile>, env: &[(String, String)],
+                   cmd: &Command, matches: &ArgMatches) -> Result<Self, String> {
+        let profile = matches.get_one::<String>("profile").map(String::as_str);
+        Self::resolve_profile(defaults, file, profile, env, cmd, matches)
+    }
+
+    fn resolve_profile(defaults: Self, file: Option<&ConfigFile>, profile: Option<&str>, env: &[(String, String)],
+                       cmd: &Command, matches: &ArgMatches) -> Result<Self, String> {
+        let layered = |calibration: Option<&ConfigFile>| -> Result<Self, String> {
+            let mut cfg = defaults.clone();
+            match (file, profile) {
+                (Some(file), _) => cfg.apply_file(file, profile)?,
+                (None, Some(_)) => return Err("--profile needs a --config file defining it".to_string()),
+                (None, None) => (),
+            }
+            for (key, value) in calibration.and_then(|c| c.section("")).unwrap_or_default() {
+                cfg.set(key, value).map_err(|e| format!("calibration: {e}"))?;
+            }
+            cfg.apply_env(cmd, env)?;
+            cfg.apply_matches(cmd, matches)?;
+            Ok(cfg)
+        };
+        // the calibration sits between the config and the environment, and
+        // where it's stored can come from any layer, so it takes a second pass
+        let mut cfg = layered(None)?;
+        if let Some(dir) = &cfg.calibration_dir
+            && let Some(calibration) = CalibrationStore::new(dir).load(profile)? {
+            cfg = layered(Some(&calibration))?;
+        }
+        cfg.finish()?;
+        Ok(cfg)
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        if self.calibrate {
+            self.start_calibration();
+        }
+        self.validate()
+    }
+
+    pub fn start_calibration(&mut self) {
+        let sectors = self.gate.as_ref().map_or(DEFAULT_GATE_SECTORS, |g| g.values().len());
+        self.gate = Some(GateTable::new(sectors, crate::MAX_MAGNITUDE/2.0));
+        self.calibrate = true;
+    }
+
+    // --sync-on-change never leaves the game without a report for long;
+    // --output-rate repeats the value on every period anyway
+    pub fn heartbeat_interval(&self) -> Option<Duration> {
+        if self.output_rate.is_some() {
+            return None;
+        }
+        self.heartbeat.or(self.sync_on_change.then_some(SYNC_ON_CHANGE_HEARTBEAT))
+    }
+
+    // under --saturation: nothing inside the deadzone, a straight line up to
+    // the saturation point and full deflection past it
+    pub fn shape_magnitude(&self, magnitude: f64) -> f64 {
+        let Some(saturation) = self.saturation else { return magnitude };
+        ((magnitude - self.deadzone) / (saturation - self.deadzone)).clamp(0.0, 1.0)
+    }
+
+    // grip thresholds are fractions of the stick as it is, only the saturation
+    // region reads as full deflection so a stick that can't reach it still grips
+    pub fn grip_magnitude(&self, magnitude: f64) -> f64 {
+        match self.saturation {
+            Some(saturation) if magnitude >= saturation => 1.0,
+            _ => magnitude,
+        }
+    }
+
+    // stick angles as atan2 of the axes, so straight up is -90 degrees there
+    pub fn in_steering_sector(&self, angle: f64) -> bool {
+        let Some((from, to)) = self.steering_sector else { return true };
+        let from_up = angle + std::f64::consts::FRAC_PI_2;
+        (from_up - from).rem_euclid(std::f64::consts::TAU) <= (to - from).rem_euclid(std::f64::consts::TAU)
+    }
+
+    pub fn rotation(&self) -> Option<Rotation> {
+        self.log_max_size.map(|max_size| Rotation { max_size, keep: self.log_keep })
+    }
+
+    // (full left, center, full right) output axis values; unset extremes stay
+    // where the layout and --output-margin put them, whatever the center
+    pub fn output_range(&self) -> (i32, i32, i32) {
+        let center = self.layout.center(self.output_resolution);
+        let span = self.output_resolution/2 - self.output_margin;
+        (self.output_min.unwrap_or(center - span),
+         self.output_center.unwrap_or(center),
+         self.output_max.unwrap_or(center + span))
+    }
+
+    // the layout's uinput description with the wheel axis at --output-resolution
+    pub fn description(&self) -> String {
+        let (min, max) = self.layout.extent(self.output_resolution);
+        let center = self.layout.center(self.output_resolution);
+        let mut in_wheel_axis = false;
+        let mut text = String::new();
+        for line in self.layout.description().lines() {
+            let indent = line.len() - line.trim_start().len();
+            if indent <= 4 {
+                in_wheel_axis = line.trim() == "ABS_X:";
+            }
+            let line = match line.trim().split_once(':') {
+                Some(("VALUE", _)) if in_wheel_axis => format!("      VALUE: {center}"),
+                Some(("MIN", _)) if in_wheel_axis => format!("      MIN: {min}"),
+                Some(("MAX", _)) if in_wheel_axis => format!("      MAX: {max}"),
+                _ => line.to_string(),
+            };
+            text += &line;
+            text += "\n";
+        }
+        text
+    }
+
+    // checks that need every setting in place
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(table) = &self.response_table
+            && !table.spans(self.steering_stop) {
+            return Err(format!("response table must span the steering range ({:.1} degrees)",
+                               self.steering_stop.to_degrees()));
+        }
+        if self.output_margin >= self.output_resolution/2 {
+            return Err(format!("output margin {} must be below half the output resolution, {}", self.output_margin, self.output_resolution/2));
+        }
+        // the fine adjustment alone reaching lock leaves nothing for the wheel to do
+        if self.fine_axis.is_some() && self.fine_range >= self.steering_stop {
+            return Err(format!("fine range ({:.1} degrees) must stay below the half steering range ({:.1} degrees)",
+                               self.fine_range.to_degrees(), self.steering_stop.to_degrees()));
+        }
+        let (min, center, max) = self.output_range();
+        if min >= max {
+            return Err(format!("output minimum {min} must be below the output maximum {max}"));
+        }
+        if !(min < center && center < max) {
+            return Err(format!("output center {center} must lie strictly between the output minimum {min} and maximum {max}"));
+        }
+        // the device only declares the layout's extent, values past it get clamped or rejected
+        let (low, high) = self.layout.extent(self.output_resolution);
+        if min < low || max > high {
+            return Err(format!("output range {min} to {max} must stay within the axis range {low} to {high}"));
+        }
+        // a synthesized tick while gripped would count the last stick rotation again
+        if self.auto_center.states.contains(&State::Gripped) {
+            return Err("the wheel can't ease back to center while gripped, auto-center-states only takes freewheel".to_string());
+        }
+        if let Some(saturation) = self.saturation
+            && saturation <= self.deadzone {
+            return Err(format!("saturation {saturation} must be past the deadzone {}", self.deadzone));
+        }
+        if self.ff_device.is_some() && !self.virtual_device && self.steering_rumble.is_none() {
+            return Err("--ff-device needs --virtual-device or --steering-rumble, it has nothing to play otherwise".to_string());
+        }
+        if self.output_rate.is_some() && self.rate_limit.is_some() {
+            return Err("--output-rate already sets the report rate, it can't be combined with --rate-limit".to_string());
+        }
+        if self.writer_watchdog.is_some() && self.output_queue.is_none() {
+            return Err("--writer-watchdog needs --output-queue, without one a stuck write stalls everything".to_string());
+        }
+        if self.steering_rumble.is_some() && self.ff_device.is_none() {
+            return Err("--steering-rumble needs --ff-device to rumble".to_string());
+        }
+        Ok(())
+    }
+
+    // top level settings first, then the selected `[profile.<name>]` section on top
+    fn apply_file(&mut self, file: &ConfigFile, profile: Option<&str>) -> Result<(), String> {
+        for (key, value) in file.section("").unwrap_or_default() {
+            self.set(key, value)?;
+        }
+        for (button, command) in file.section("bindings").unwrap_or_default() {
+            self.set("bind", &format!("{button}={command}")).map_err(|e| format!("bindings: {e}"))?;
+        }
+        self.profiles = file.sections.iter()
+            .filter_map(|(section, settings)| {
+                section.strip_prefix("profile.").map(|name| Profile { name: name.to_string(), settings: settings.clone() })
+            })
+            .collect();
+        if let Some(name) = profile {
+            let settings = self.profiles.iter()
+                .find(|p| p.name == name)
+                .map(|p| p.settings.clone())
+                .ok_or_else(|| {
+                    let available: Vec<&str> = self.profiles.iter().map(|p| p.name.as_str()).collect();
+                    if available.is_empty() {
+                        format!("unknown profile '{name}', the config defines no profiles")
+                    } else {
+                        format!("unknown profile '{name}', available profiles: {}", available.join(", "))
+                    }
+                })?;
+            for (key, value) in &settings {
+                self.set(key, value).map_err(|e| format!("profile {name}: {e}"))?;
+            }
+            self.profile = Some(name.to_string());
+        }
+        Ok(())
+    }
+
+    // --steering-range is ANALOGSTICK2WHEEL_STEERING_RANGE, flags take true or false
+    fn apply_env(&mut self, cmd: &Command, env: &[(String, String)]) -> Result<(), String> {
+        for arg in cmd.get_arguments() {
+            let key = arg.get_id().as_str();
+            if key == "config" || key == "profile" {
+                continue;
+            }
+            let name = format!("{ENV_PREFIX}{}", key.to_uppercase().replace('-', "_"));
+            if let Some((_, value)) = env.iter().find(|(n, _)| *n == name) {
+                self.set(key, value).map_err(|e| format!("{name}: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_matches(&mut self, cmd: &Command, matches: &ArgMatches) -> Result<(), String> {
+        for arg in cmd.get_arguments() {
+            let key = arg.get_id().as_str();
+            if key == "config" || key == "profile" || matches.value_source(key) != Some(ValueSource::CommandLine) {
+                continue;
+            }
+            if matches!(arg.get_action(), ArgAction::Append) {
+                for value in matches.get_many::<String>(key).into_iter().flatten() {
+                    self.set(key, value)?;
+                }
+            } else if arg.get_action().takes_values() {
+                if let Some(value) = matches.get_one::<String>(key) {
+                    self.set(key, value)?;
+                }
+            } else if matches!(arg.get_action(), ArgAction::SetTrue) {
+                self.set(key, "true")?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        self.apply_setting(key, value)?;
+        if !EPHEMERAL_SETTINGS.contains(&key) {
+            if key != "bind" {
+                self.settings.retain(|(k, _)| k != key);
+            }
+            self.settings.push((key.to_string(), value.to_string()));
+        }
+        Ok(())
+    }
+
+    // a config file reproducing the current settings; a learned gate shape and
+    // center offset replace however they were configured. under
+    // --calibration-dir the calibration is left to its own file
+    pub fn export(&self) -> String {
+        let mut text = format!("version = {CONFIG_VERSION}\n");
+        let gate_settings = ["gate-sectors", "gate-table"];
+        let separate = self.calibration_dir.is_some();
+        let (x, y) = self.center_offset;
+        let offset = (!separate && (x, y) != (0.0, 0.0)).then(|| ("center-offset".to_string(), format!("{x:.1},{y:.1}")));
+        let settings = self.settings.iter()
+            .filter(|(key, _)| self.gate.is_none() || !gate_settings.contains(&key.as_str()))
+            .filter(|(key, _)| key != "center-offset")
+            .filter(|(key, _)| !separate || !CALIBRATION_SETTINGS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .chain(self.gate.as_ref().filter(|_| !separate).map(|gate| ("gate-table".to_string(), gate.to_arg())))
+            .chain(offset);
+        for (key, value) in settings {
+            text += &format!("{key} = \"{}\"\n", value.replace('\\', "\\\\").replace('"', "\\\""));
+        }
+        text
+    }
+
+    // the hardware specific part of the settings, for --calibration-dir
+    pub fn export_calibration(&self) -> String {
+        let mut text = format!("version = {CONFIG_VERSION}\n");
+        text += &format!("max-magnitude-x = \"{}\"\n", self.max_magnitude_x);
+        text += &format!("max-magnitude-y = \"{}\"\n", self.max_magnitude_y);
+        if let Some(gate) = &self.gate {
+            text += &format!("gate-table = \"{}\"\n", gate.to_arg());
+        }
+        let (x, y) = self.center_offset;
+        text += &format!("center-offset = \"{x:.1},{y:.1}\"\n");
+        text
+    }
+
+    fn apply_setting(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "steering-range" => {
+                let v = parse_positive_degrees(key, value)?;
+                // a tiny range turns stick noise into full lock and quantization into a division by ~0
+                if v < MIN_STEERING_RANGE.to_radians() {
+                    return Err(format!("{key} must be at least {MIN_STEERING_RANGE} degrees"));
+                }
+                self.steering_stop = v/2.0;
+            },
+            "invert-x" => self.invert_x = parse_bool(key, value)?,
+            "invert-y" => self.invert_y = parse_bool(key, value)?,
+            "deadzone" => self.deadzone = parse_fraction(key, value)?,
+            "saturation" => self.saturation = Some(parse_f64(key, value).ok()
+                .filter(|v| *v > 0.0 && *v <= 1.0)
+                .ok_or_else(|| format!("{key} must be in the range (0, 1]"))?),
+            "max-magnitude-x" => self.max_magnitude_x = parse_positive(key, value)?,
+            "max-magnitude-y" => self.max_magnitude_y = parse_positive(key, value)?,
+            "clamp-input" => self.clamp_input = parse_bool(key, value)?,
+            "stick-smoothing" => {
+                let values = value.split(',')
+                    .map(|v| parse_positive(key, v.trim()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.stick_smoothing = match values[..] {
+                    [slow, fast, full_speed] if fast >= slow => Some(SmoothingCurve { slow, fast, full_speed }),
+                    [_, _, _] => return Err(format!("{key} needs FAST_HZ at least SLOW_HZ")),
+                    _ => return Err(format!("{key} must be SLOW_HZ,FAST_HZ,SPEED")),
+                };
+            },
+            "grip-angle-fallback" => self.grip_angle_fallback = parse_bool(key, value)?,
+            "grip-smoothing" => self.grip_smoothing = Some(parse_millis(key, value)?.as_secs_f64()),
+            "release-smoothing" => self.release_smoothing = Some(parse_millis(key, value)?.as_secs_f64()),
+            "reanchor-on-grip" => self.reanchor_on_grip = parse_bool(key, value)?,
+            "gate-sectors" => {
+                let n = value.parse::<usize>().ok().filter(|n| *n > 0)
+                    .ok_or_else(|| format!("{key} must be a positive integer"))?;
+                self.gate = Some(GateTable::new(n, crate::MAX_MAGNITUDE));
+            },
+            "gate-table" => {
+                let values = value.split(',')
+                    .map(|v| parse_positive(key, v.trim()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.gate = Some(GateTable::from_values(values));
+            },
+            "grip-thresholds" => {
+                let values = value.split(',')
+                    .map(|v| parse_fraction(key, v.trim()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.grip_thresholds = Some(GateTable::from_values(values));
+            },
+            "steering-sector" => {
+                let bounds = value.split(',')
+                    .map(|v| parse_f64(key, v.trim()).map(f64::to_radians))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.steering_sector = match bounds[..] {
+                    [from, to] if (to - from).rem_euclid(std::f64::consts::TAU) != 0.0 => Some((from, to)),
+                    _ => return Err(format!("{key} must be two different angles FROM,TO in degrees")),
+                };
+            },
+            "calibrate" => self.calibrate = parse_bool(key, value)?,
+            "calibrate-duration" => self.calibrate_duration = Some(parse_seconds(key, value)?),
+            "hold-while-calibrating" => self.hold_while_calibrating = parse_bool(key, value)?,
+            "diagnose" => self.diagnose = Some(PathBuf::from(value)),
+            "drift-log" => self.drift_log = parse_bool(key, value)?,
+            "drift-warn" => self.drift_warn = parse_fraction(key, value)?,
+            "drift-correction" => self.drift_correction = Some(parse_millis(key, value)?.as_secs_f64()),
+            "drift-limit" => self.drift_limit = parse_fraction(key, value)?,
+            "center-offset" => {
+                let offsets = value.split(',')
+                    .map(|v| parse_f64(key, v.trim()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.center_offset = match offsets[..] {
+                    [x, y] => (x, y),
+                    _ => return Err(format!("{key} must be X,Y")),
+                };
+            },
+            "calibration-dir" => self.calibration_dir = Some(PathBuf::from(value)),
+            "max-dt" => self.max_dt = parse_millis(key, value)?,
+            "mode" => self.mode = match value {
+                "wheel" => SteeringMode::Wheel,
+                "push" => SteeringMode::Push,
+                _ => return Err(format!("{key} must be one of wheel or push")),
+            },
+            "ratchet" => self.ratchet = parse_bool(key, value)?,
+            "grip-blend" => self.grip_blend = parse_optional_millis(key, value)?.as_secs_f64(),
+            "grip-reference" => self.grip_reference = match value {
+                "wheel" => GripReference::Wheel,
+                "stick" => GripReference::Stick,
+                _ => return Err(format!("{key} must be one of wheel or stick")),
+            },
+            "min-grip" => self.min_grip = parse_optional_millis(key, value)?.as_secs_f64(),
+            "flick-threshold" => {
+                let v = parse_positive_degrees(key, value)?;
+                if v >= std::f64::consts::PI {
+                    return Err(format!("{key} must be less than 180 degrees"));
+                }
+                self.flick_threshold = Some(v);
+            },
+            "reversal-damping" => self.reversal_damping = parse_fraction(key, value)?,
+            "reversal-window" => self.reversal_window = parse_millis(key, value)?.as_secs_f64(),
+            "speed-gain" => {
+                let values = value.split(',').map(str::trim).collect::<Vec<_>>();
+                self.speed_gain = match values[..] {
+                    [center, lock, speed] => Some(SpeedGain {
+                        center: parse_positive(key, center)?,
+                        lock: parse_positive(key, lock)?,
+                        speed: parse_positive_degrees(key, speed)?,
+                    }),
+                    _ => return Err(format!("{key} must be CENTER,LOCK,DEGREES_PER_SECOND")),
+                };
+            },
+            "mid-band" => self.mid_band = match value {
+                "ignore" => MidBand::Ignore,
+                "track" => MidBand::Track,
+                "fine" => MidBand::Fine,
+                _ => return Err(format!("{key} must be one of ignore, track or fine")),
+            },
+            "mid-band-gain" => self.mid_band_gain = parse_positive(key, value)?,
+            "mid-band-rate" => self.mid_band_rate = parse_positive_degrees(key, value)?,
+            "inertia" => self.inertia = parse_bool(key, value)?,
+            "inertia-scale" => self.inertia_scale = parse_positive(key, value)?,
+            "friction" => self.friction = parse_positive(key, value)?,
+            "speed-axis" => self.speed_axis = Some(parse_axis(value)?),
+            "speed-axis-max" => self.speed_axis_max = parse_positive(key, value)?,
+            "fine-axis" => self.fine_axis = Some(parse_axis(value)?),
+            "fine-range" => self.fine_range = parse_positive_degrees(key, value)?,
+            "gyro-axis" => self.gyro_axis = Some(parse_axis(value)?),
+            "gyro-resolution" => self.gyro_resolution = parse_positive(key, value)?,
+            "invert-gyro" => self.invert_gyro = parse_bool(key, value)?,
+            "gyro-mode" => self.gyro_mode = match value {
+                "replace" => GyroMode::Replace,
+                "fuse" => GyroMode::Fuse,
+                _ => return Err(format!("{key} must be one of replace or fuse")),
+            },
+            "freewheel-scroll" => self.freewheel_scroll = Some(parse_positive_degrees(key, value)?),
+            "scroll-axis" => self.scroll_axis = parse_relative_axis(value)?,
+            "spring-threshold" => self.spring_threshold = parse_fraction(key, value)?,
+            "spring-gain" => self.spring_gain = parse_positive(key, value)?,
+            "centering-gain" => self.centering_gain = parse_positive(key, value)?,
+            "state-socket" => self.state_socket = Some(PathBuf::from(value)),
+            "shm" => self.shm = Some(value.to_string()),
+            "response-table" => self.response_table = Some(ResponseTable::load(value.as_ref())?),
+            "inner-gamma" => self.gamma.inner = parse_positive(key, value)?,
+            "outer-gamma" => self.gamma.outer = parse_positive(key, value)?,
+            "curve-crossover" => {
+                let c = parse_fraction(key, value)?;
+                if c == 0.0 {
+                    return Err(format!("{key} must be greater than zero"));
+                }
+                self.gamma.crossover = c;
+            },
+            "game-gamma" => self.game_gamma = Some(parse_positive(key, value)?),
+            "max-steer-accel" => self.max_steer_accel = Some(parse_positive_degrees(key, value)?),
+            "interpolate" => self.interpolate = Some(parse_positive(key, value)?),
+            "max-runtime" => self.max_runtime = Some(parse_seconds(key, value)?),
+            "coalesce-axes" => self.coalesce_axes = parse_bool(key, value)?,
+            "require-both-axes" => self.require_both_axes = parse_bool(key, value)?,
+            "input-time-bits" => self.input_layout = match value {
+                "32" => EventLayout::Time32,
+                "64" => EventLayout::Time64,
+                _ => return Err(format!("{key} must be 32 or 64")),
+            },
+            "coalesce-timeout" => self.coalesce_timeout = parse_millis(key, value)?,
+            "split-regrip" => self.split_regrip = parse_bool(key, value)?,
+            "axis-skew" => self.axis_skew = Some(parse_millis(key, value)?),
+            "axis-skew-action" => self.axis_skew_action = match value {
+                "warn" => SkewAction::Warn,
+                "interpolate" => SkewAction::Interpolate,
+                _ => return Err(format!("{key} must be one of warn or interpolate")),
+            },
+            "event-time" => self.time_source = match value {
+                "input" => TimeSource::Input,
+                "monotonic" => TimeSource::Monotonic,
+                "realtime" => TimeSource::Realtime,
+                _ => return Err(format!("{key} must be one of input, monotonic or realtime")),
+            },
+            "tee" => self.tee = Some(PathBuf::from(value)),
+            "expect" => self.expect = Some(PathBuf::from(value)),
+            "log-max-size" => self.log_max_size = Some(value.parse::<u64>().ok().filter(|n| *n > 0)
+                .ok_or_else(|| format!("{key} must be a positive integer"))?),
+            "log-keep" => self.log_keep = value.parse::<usize>()
+                .map_err(|_| format!("{key} must be a non-negative integer"))?,
+            "debug-trigger" => self.debug_triggers = value.split(',')
+                .map(Trigger::parse)
+                .collect::<Result<_, _>>()?,
+            "debug-log" => self.debug_log = Some(PathBuf::from(value)),
+            "log-transitions" => self.log_transitions = parse_bool(key, value)?,
+            "debug-format" => self.debug_format = match value {
+                "compact" => DebugStyle::Compact,
+                "multiline" => DebugStyle::Multiline,
+                "off" => DebugStyle::Off,
+                _ => return Err(format!("{key} must be one of compact, multiline or off")),
+            },
+            "debug-fields" => self.debug_fields = value.split(',')
+                .map(DebugField::parse)
+                .collect::<Result<_, _>>()?,
+            "keep-running" => self.keep_running = parse_bool(key, value)?,
+            "msc-timestamp" => self.msc_timestamp = parse_bool(key, value)?,
+            "output-queue" => self.output_queue = Some(value.parse::<usize>().ok().filter(|n| *n > 0)
+                .ok_or_else(|| format!("{key} must be a positive integer"))?),
+            "backpressure" => self.backpressure = match value {
+                "block" => Backpressure::Block,
+                "drop-oldest" => Backpressure::DropOldest,
+                _ => return Err(format!("{key} must be either block or drop-oldest")),
+            },
+            "writer-watchdog" => self.writer_watchdog = Some(parse_millis(key, value)?),
+            "batch-delay" => self.batch_delay = Some(parse_millis(key, value)?),
+            "batch-size" => self.batch_size = value.parse::<usize>().ok().filter(|n| *n > 0)
+                .ok_or_else(|| format!("{key} must be a positive integer"))?,
+            "stall-timeout" => self.stall_timeout = Some(parse_millis(key, value)?),
+            "initial-report" => self.initial_report = parse_bool(key, value)?,
+            "startup-sweep" => self.startup_sweep = Some(parse_positive_degrees(key, value)?),
+            "heartbeat" => self.heartbeat = Some(parse_millis(key, value)?),
+            "sync-on-change" => self.sync_on_change = parse_bool(key, value)?,
+            "rate-limit" => self.rate_limit = Some(parse_positive(key, value)?),
+            "output-rate" => {
+                let v = parse_f64(key, value)?;
+                if !(1.0..=MAX_OUTPUT_RATE).contains(&v) {
+                    return Err(format!("{key} must be from 1 to {MAX_OUTPUT_RATE} Hz"));
+                }
+                self.output_rate = Some(v);
+            },
+            "rate-burst" => {
+                let v = parse_positive(key, value)?;
+                if v < 1.0 {
+                    return Err(format!("{key} must be at least 1"));
+                }
+                self.rate_burst = v;
+            },
+            "center-indicator" => self.center_indicator = Some(PathBuf::from(value)),
+            "center-band" => self.center_band = parse_positive_degrees(key, value)?,
+            "center-snap-width" => self.center_snap_width = value.parse::<u16>()
+                .map_err(|_| format!("{key} must be a whole number of axis units"))? as i32,
+            "center-snap-strength" => {
+                let v = parse_f64(key, value)?;
+                if !(0.0..=1.0).contains(&v) {
+                    return Err(format!("{key} must be in the range [0, 1]"));
+                }
+                self.center_snap_strength = v;
+            },
+            "center-settle" => self.center_settle = parse_optional_millis(key, value)?,
+            "auto-center-angle" => self.auto_center.min_angle = parse_positive_degrees(key, value)?,
+            "auto-center-states" => self.auto_center.states = value.split(',')
+                .map(|name| match name.trim() {
+                    "freewheel" => Ok(State::Freewheel),
+                    "gripped" => Ok(State::Gripped),
+                    _ => Err(format!("{key} must be a list of freewheel and gripped")),
+                })
+                .collect::<Result<_, _>>()?,
+            "auto-center-interval" => self.auto_center.interval = parse_millis(key, value)?,
+            "output-margin" => self.output_margin = value.parse::<i32>().ok()
+                .filter(|v| *v >= 0)
+                .ok_or_else(|| format!("{key} must be a whole number of axis units"))?,
+            "output-center" => self.output_center = Some(parse_axis_value(key, value)?),
+            "output-min" => self.output_min = Some(parse_axis_value(key, value)?),
+            "output-max" => self.output_max = Some(parse_axis_value(key, value)?),
+            "output-resolution" => self.output_resolution = value.parse::<i32>().ok()
+                .filter(|v| *v >= 255)
+                .ok_or_else(|| format!("{key} must be a whole number from 255 to {}", i32::MAX))?,
+            "layout" => self.layout = match value {
+                "default" => Layout::Default,
+                "xbox" => Layout::Xbox,
+                _ => return Err(format!("{key} must be one of default or xbox")),
+            },
+            "print-layout" => self.print_layout = parse_bool(key, value)?,
+            "verbose" => self.verbose = parse_bool(key, value)?,
+            "virtual-device" => self.virtual_device = parse_bool(key, value)?,
+            "ff-device" => self.ff_device = Some(PathBuf::from(value)),
+            "steering-rumble" => self.steering_rumble = Some(match value {
+                "magnitude" => RumbleSource::Magnitude,
+                "speed" => RumbleSource::Speed,
+                _ => return Err(format!("{key} must be one of magnitude or speed")),
+            }),
+            "rumble-gamma" => self.rumble_gamma = parse_positive(key, value)?,
+            "rumble-strength" => self.rumble_strength = parse_f64(key, value).ok()
+                .filter(|v| *v > 0.0 && *v <= 1.0)
+                .ok_or_else(|| format!("{key} must be in the range (0, 1]"))?,
+            "export-profile" => self.export_profile = Some(PathBuf::from(value)),
+            "output-hysteresis" => self.output_hysteresis = value.parse::<u16>()
+                .map_err(|_| format!("{key} must be a whole number of axis units"))? as i32,
+            "levels" => self.levels = Some(value.parse::<usize>().ok().filter(|n| *n >= 2)
+                .ok_or_else(|| format!("{key} must be an integer of at least 2"))?),
+            "level-hysteresis" => {
+                let v = parse_f64(key, value)?;
+                if !(0.0..0.5).contains(&v) {
+                    return Err(format!("{key} must be in the range [0, 0.5)"));
+                }
+                self.level_hysteresis = v;
+            },
+            "control" => self.control = Some(PathBuf::from(value)),
+            "bind" => {
+                let (button, command) = value.split_once('=')
+                    .ok_or_else(|| format!("{key} takes KEY=COMMAND, got '{value}'"))?;
+                let button = parse_key(button.trim())?;
+                let command = ControlCommand::parse(command)?;
+                self.bindings.retain(|(bound, _)| *bound != button);
+                self.bindings.push((button, command));
+            },
+            "hold-button" => self.hold_button = Some(parse_key(value)?),
+            _ => return Err(format!("unknown setting '{key}'")),
+        }
+        Ok(())
+    }
+}
+
+const DEFAULT_GATE_SECTORS: usize = 16;
+// what --calibration-dir keeps per profile instead of in the config
+const CALIBRATION_SETTINGS: [&str; 5] = ["max-magnitude-x", "max-magnitude-y", "gate-sectors", "gate-table", "center-offset"];
+// one-off actions rather than tuning, left out of exported profiles
+const EPHEMERAL_SETTINGS: [&str; 4] = ["calibrate", "diagnose", "print-layout", "export-profile"];
+const MIN_STEERING_RANGE: f64 = 10.0;
+const SYNC_ON_CHANGE_HEARTBEAT: Duration = Duration::from_millis(1000);
+const MAX_OUTPUT_RATE: f64 = 10000.0;
+
+// environment variables overriding the config file, see Config::apply_env
+const ENV_PREFIX: &str = "ANALOGSTICK2WHEEL_";
+
+pub fn parse_axis(value: &str) -> Result<AbsoluteAxis, String> {
+    if let Ok(code) = value.parse::<u16>() {
+        return AbsoluteAxis::from_code(code).map_err(|_| format!("no absolute axis with code {code}"));
+    }
+    let name = value.trim_start_matches("ABS_").to_uppercase();
+    AbsoluteAxis::iter()
+        .find(|axis| format!("{axis:?}").to_uppercase() == name)
+        .ok_or_else(|| format!("unknown absolute axis '{value}'"))
+}
+
+// REL_WHEEL, REL_HWHEEL and the like, or a numeric code
+pub fn parse_relative_axis(value: &str) -> Result<RelativeAxis, String> {
+    if let Ok(code) = value.parse::<u16>() {
+        return RelativeAxis::from_code(code).map_err(|_| format!("no relative axis with code {code}"));
+    }
+    let name = value.trim_start_matches("REL_").to_uppercase().replace('_', "").replace("HWHEEL", "HORIZONTALWHEEL");
+    RelativeAxis::iter()
+        .find(|axis| format!("{axis:?}").to_uppercase() == name)
+        .ok_or_else(|| format!("unknown relative axis '{value}'"))
+}
+
+// kernel style names (BTN_SELECT, KEY_F1) or numeric codes
+pub fn parse_key(value: &str) -> Result<Key, String> {
+    if let Ok(code) = value.parse::<u16>() {
+        return Key::from_code(code).map_err(|_| format!("no key with code {code}"));
+    }
+    let upper = value.to_uppercase();
+    let name = match upper.strip_prefix("BTN_") {
+        Some(button) => format!("BUTTON{button}"),
+        None => upper.trim_start_matches("KEY_").to_string(),
+    }.replace('_', "");
+    Key::iter()
+        .find(|key| format!("{key:?}").to_uppercase() == name)
+        .ok_or_else(|| format!("unknown key '{value}'"))
+}
+
+fn parse_axis_value(key: &str, value: &str) -> Result<i32, String> {
+    value.parse::<i32>().map_err(|_| format!("{key} must be a whole number axis value"))
+}
+
+fn parse_f64(key: &str, value: &str) -> Result<f64, String> {
+    value.parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .ok_or_else(|| format!("'{value}' is not a valid number for {key}"))
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    value.parse::<bool>().map_err(|_| format!("{key} must be true or false"))
+}
+
+fn parse_positive(key: &str, value: &str) -> Result<f64, String> {
+    let v = parse_f64(key, value)?;
+    if v > 0.0 {
+        Ok(v)
+    } else {
+        Err(format!("{key} must be greater than zero"))
+    }
+}
+
+fn parse_non_negative(key: &str, value: &str) -> Result<f64, String> {
+    let v = parse_f64(key, value)?;
+    if v >= 0.0 {
+        Ok(v)
+    } else {
+        Err(format!("{key} must not be negative"))
+    }
+}
+
+fn parse_seconds(key: &str, value: &str) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(parse_positive(key, value)?).map_err(|_| format!("{key} is too long"))
+}
+
+// every angle in the configuration is given in degrees and only turned into
+// the radians used internally here
+fn parse_degrees(key: &str, value: &str) -> Result<f64, String> {
+    Ok(parse_f64(key, value)?.to_radians())
+}
+
+fn parse_positive_degrees(key: &str, value: &str) -> Result<f64, String> {
+    parse_positive(key, value)?;
+    parse_degrees(key, value)
+}
+
+fn parse_millis(key: &str, value: &str) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(parse_positive(key, value)?/1000.0).map_err(|_| format!("{key} is too long"))
+}
+
+// for delays where 0 means none, which is also their default
+fn parse_optional_millis(key: &str, value: &str) -> Result<Duration, String> {
+    Duration::try_from_secs_f64(parse_non_negative(key, value)?/1000.0).map_err(|_| format!("{key} is too long"))
+}
+
+fn parse_fraction(key: &str, value: &str) -> Result<f64, String> {
+    let v = parse_f64(key, value)?;
+    if (0.0..1.0).contains(&v) {
+        Ok(v)
+    } else {
+        Err(format!("{key} must be in the range [0, 1)"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_center_triggers_only_when_a_tick_would_move_the_wheel() {
+        let auto_center = AutoCenter::default();
+        let due = auto_center.interval;
+        assert!(auto_center.triggers(0.5, State::Freewheel, due, 1.0, None));
+        assert!(!auto_center.triggers(0.5, State::Freewheel, due, 0.0, None));
+        assert!(!auto_center.triggers(0.5, State::Freewheel, due, 1.0, Some(0.5)));
+        assert!(auto_center.triggers(0.5, State::Freewheel, due, 1.0, Some(0.6)));
+        assert!(!auto_center.triggers(0.0, State::Freewheel, due, 1.0, None));
+        assert!(!auto_center.triggers(0.5, State::Gripped, due, 1.0, None));
+        assert!(!auto_center.triggers(0.5, State::Freewheel, Duration::ZERO, 1.0, None));
+    }
+
+    #[test]
+    fn saturation_only_shapes_the_grip_past_the_saturation_point() {
+        let mut cfg = Config::default();
+        cfg.set("deadzone", "0.1").unwrap();
+        cfg.set("saturation", "0.9").unwrap();
+        assert_eq!(cfg.shape_magnitude(0.05), 0.0);
+        assert!((cfg.shape_magnitude(0.5) - 0.5).abs() < 1e-12);
+        assert_eq!(cfg.shape_magnitude(0.95), 1.0);
+        assert_eq!(cfg.grip_magnitude(0.05), 0.05);
+        assert_eq!(cfg.grip_magnitude(0.85), 0.85);
+        assert_eq!(cfg.grip_magnitude(0.9), 1.0);
+    }
+
+    #[test]
+    fn auto_center_trigger_boundaries() {
+        let auto_center = AutoCenter { min_angle: 0.1, states: vec![State::Freewheel], interval: Duration::from_millis(4) };
+        let due = Duration::from_millis(4);
+        assert!(!auto_center.triggers(0.1, State::Freewheel, due, 1.0, None));
+        assert!(auto_center.triggers(0.1001, State::Freewheel, due, 1.0, None));
+        assert!(auto_center.triggers(-0.1001, State::Freewheel, due, 1.0, None));
+        assert!(!auto_center.triggers(0.5, State::Freewheel, due - Duration::from_micros(1), 1.0, None));
+        assert!(!auto_center.triggers(0.5, State::Gripped, due, 1.0, None));
+    }
+
+    #[test]
+    fn auto_center_while_gripped_is_rejected() {
+        let mut cfg = Config::default();
+        cfg.set("auto-center-states", "freewheel").unwrap();
+        assert!(cfg.validate().is_ok());
+        cfg.set("auto-center-states", "freewheel,gripped").unwrap();
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn export_includes_the_learned_center_offset() {
+        let mut cfg = Config::default();
+        cfg.set("center-offset", "1,2").unwrap();
+        cfg.center_offset = (120.0, -35.5);
+        let exported = cfg.export();
+        assert!(exported.contains("center-offset = \"120.0,-35.5\"\n"));
+        assert_eq!(exported.matches("center-offset").count(), 1);
+        cfg.set("calibration-dir", "/nonexistent").unwrap();
+        assert!(!cfg.export().contains("center-offset"));
+    }
+
+    #[test]
+    fn output_extremes_must_fit_the_axis() {
+        let mut cfg = Config::default();
+        let (low, high) = cfg.layout.extent(cfg.output_resolution);
+        cfg.set("output-min", &low.to_string()).unwrap();
+        cfg.set("output-max", &high.to_string()).unwrap();
+        assert!(cfg.validate().is_ok());
+        cfg.set("output-max", &(high + 1).to_string()).unwrap();
+        assert!(cfg.validate().is_err());
+        cfg.set("output-max", &high.to_string()).unwrap();
+        cfg.set("output-min", &(low - 1).to_string()).unwrap();
+        assert!(cfg.validate().is_err());
+        cfg.set("output-min", "40000").unwrap();
+        cfg.set("output-max", "30000").unwrap();
+        assert!(cfg.validate().unwrap_err().contains("below the output maximum"));
+    }
+
+    #[test]
+    fn the_spring_gain_must_be_positive() {
+        let mut cfg = Config::default();
+        assert!(cfg.set("spring-gain", "-1").is_err());
+        assert!(cfg.set("spring-gain", "0").is_err());
+        cfg.set("spring-gain", "2").unwrap();
+        assert_eq!(cfg.spring_gain, 2.0);
+    }
+
+    #[test]
+    fn delays_that_default_to_none_can_be_set_back_to_zero() {
+        let mut cfg = Config::default();
+        for key in ["min-grip", "grip-blend", "center-settle"] {
+            cfg.set(key, "20").unwrap();
+            cfg.set(key, "0").unwrap();
+            assert!(cfg.set(key, "-1").is_err());
+        }
+        assert_eq!((cfg.min_grip, cfg.grip_blend, cfg.center_settle), (0.0, 0.0, Duration::ZERO));
+    }
+
+    #[test]
+    fn max_runtime_out_of_range_is_an_error() {
+        let mut cfg = Config::default();
+        assert!(cfg.set("max-runtime", "1e300").is_err());
+        cfg.set("max-runtime", "2.5").unwrap();
+        assert_eq!(cfg.max_runtime, Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn the_curve_crossover_is_inside_the_range() {
+        let mut cfg = Config::default();
+        cfg.set("curve-crossover", "0.3").unwrap();
+        assert_eq!(cfg.gamma.crossover, 0.3);
+        assert!(cfg.set("curve-crossover", "0").is_err());
+        assert!(cfg.set("curve-crossover", "1").is_err());
+        assert!(cfg.set("inner-gamma", "0").is_err());
+    }
+
+    #[test]
+    fn millisecond_settings_out_of_range_are_errors() {
+        let mut cfg = Config::default();
+        assert!(cfg.set("stall-timeout", "1e300").is_err());
+        assert!(cfg.set("heartbeat", "1e300").is_err());
+        cfg.set("heartbeat", "250").unwrap();
+        assert_eq!(cfg.heartbeat, Some(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn the_steering_range_is_lock_to_lock_in_degrees() {
+        let mut cfg = Config::default();
+        cfg.set("steering-range", "540").unwrap();
+        assert!((cfg.steering_stop - 270f64.to_radians()).abs() < 1e-12);
+        assert!(cfg.set("steering-range", "-90").is_err());
+        assert!(cfg.set("steering-range", "0").is_err());
+    }
+
+    #[test]
+    fn a_response_table_must_span_the_steering_range() {
+        let table = ResponseTable::new(vec![(0.0, 0.0), (180f64.to_radians(), 180f64.to_radians())]).unwrap();
+        let mut cfg = Config { response_table: Some(table), ..Config::default() };
+        cfg.set("steering-range", "360").unwrap();
+        assert!(cfg.finish().is_ok());
+        cfg.set("steering-range", "400").unwrap();
+        assert_eq!(cfg.finish().unwrap_err(), "response table must span the steering range (200.0 degrees)");
+    }
+
+    // the config as from_args would resolve it with this file and command line
+    fn resolved(text: &str, args: &[&str]) -> Result<Config, String> {
+        let cmd = Config::command();
+        let matches = cmd.clone().try_get_matches_from(std::iter::once("analogstick2wheel").chain(args.iter().copied())).unwrap();
+        let file = ConfigFile::parse(text)?;
+        let mut cfg = Config::resolve(Config::default(), Some(&file), &[], &cmd, &matches)?;
+        cfg.layers = Some(Arc::new(Layers { file: Some(file), env: Vec::new(), cmd, matches }));
+        Ok(cfg)
+    }
+
+    const PROFILES: &str = "version = 1\ndeadzone = 0.1\n[profile.race]\ndeadzone = 0.2\n[profile.drift]\nratchet = true\n";
+
+    #[test]
+    fn a_profile_applies_over_the_top_level_settings() {
+        assert_eq!(resolved(PROFILES, &[]).unwrap().deadzone, 0.1);
+        let race = resolved(PROFILES, &["--profile", "race"]).unwrap();
+        assert_eq!((race.deadzone, race.profile.as_deref()), (0.2, Some("race")));
+        // and the command line over the profile
+        assert_eq!(resolved(PROFILES, &["--profile", "race", "--deadzone", "0.3"]).unwrap().deadzone, 0.3);
+        assert_eq!(resolved(PROFILES, &["--profile", "rally"]).unwrap_err(),
+                   "unknown profile 'rally', available profiles: race, drift");
+    }
+
+    #[test]
+    fn buttons_bind_to_control_commands() {
+        let mut cfg = Config::default();
+        cfg.set("bind", "BTN_SELECT=center").unwrap();
+        cfg.set("bind", "btn_start = set-range 540").unwrap();
+        // binding a button again replaces what it did
+        cfg.set("bind", "BTN_SELECT=pause").unwrap();
+        assert_eq!(cfg.bindings, [
+            (Key::ButtonStart, ControlCommand::SetRange("540".to_string())),
+            (Key::ButtonSelect, ControlCommand::Pause),
+        ]);
+        assert_eq!(cfg.set("bind", "BTN_SELECT").unwrap_err(), "bind takes KEY=COMMAND, got 'BTN_SELECT'");
+        assert!(cfg.set("bind", "BTN_SELECT=explode").is_err());
+        assert!(cfg.set("bind", "BTN_NOPE=center").is_err());
+    }
+
+    #[test]
+    fn the_bindings_table_binds_buttons_by_name() {
+        let cfg = resolved("version = 1\n[bindings]\nBTN_MODE = \"next-profile\"\nBTN_THUMBL = \"center\"\n", &[]).unwrap();
+        assert_eq!(cfg.bindings, [(Key::ButtonMode, ControlCommand::NextProfile), (Key::ButtonThumbl, ControlCommand::Center)]);
+    }
+
+    #[test]
+    fn the_xbox_layout_centers_the_wheel_axis_on_zero() {
+        let mut cfg = Config::default();
+        cfg.set("layout", "xbox").unwrap();
+        assert_eq!(cfg.output_range(), (-32767, 0, 32767));
+        let description = cfg.description();
+        assert!(description.starts_with("NAME: Microsoft X-Box 360 pad\n"));
+        assert!(description.contains("    ABS_X:\n      VALUE: 0\n      MIN: -32768\n      MAX: 32767\n"));
+        cfg.set("output-resolution", "1023").unwrap();
+        assert!(cfg.description().contains("    ABS_X:\n      VALUE: 0\n      MIN: -512\n      MAX: 511\n"));
+        // only the wheel axis follows the resolution
+        assert!(cfg.description().contains("    ABS_Y:\n      VALUE: 0\n      MIN: -32768\n      MAX: 32767\n"));
+    }
+
+    #[test]
+    fn calibrate_duration_out_of_range_is_an_error() {
+        let mut cfg = Config::default();
+        assert!(cfg.set("calibrate-duration", "1e300").is_err());
+        cfg.set("calibrate-duration", "10").unwrap();
+        assert_eq!(cfg.calibrate_duration, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn each_layer_overrides_the_one_below() {
+        let cmd = Config::command();
+        let file = ConfigFile::parse("version = 1\ndeadzone = 0.1\n").unwrap();
+        let env = vec![("ANALOGSTICK2WHEEL_DEADZONE".to_string(), "0.2".to_string())];
+        let resolve = |file: Option<&ConfigFile>, env: &[(String, String)], args: &[&str]| {
+            let matches = cmd.clone().try_get_matches_from(std::iter::once("analogstick2wheel").chain(args.iter().copied())).unwrap();
+            Config::resolve(Config::default(), file, env, &cmd, &matches).unwrap().deadzone
+        };
+        assert_eq!(resolve(None, &[], &[]), Config::default().deadzone);
+        assert_eq!(resolve(Some(&file), &[], &[]), 0.1);
+        assert_eq!(resolve(Some(&file), &env, &[]), 0.2);
+        assert_eq!(resolve(Some(&file), &env, &["--deadzone", "0.3"]), 0.3);
+        let matches = cmd.clone().try_get_matches_from(["analogstick2wheel"]).unwrap();
+        let bad = vec![("ANALOGSTICK2WHEEL_DEADZONE".to_string(), "2".to_string())];
+        assert!(Config::resolve(Config::default(), None, &bad, &cmd, &matches).unwrap_err().starts_with("ANALOGSTICK2WHEEL_DEADZONE: "));
+    }
+
+    #[test]
+    fn a_non_positive_or_tiny_steering_range_fails_resolution() {
+        assert_eq!(resolved("version = 1\nsteering-range = 0\n", &[]).unwrap_err(), "steering-range must be greater than zero");
+        assert_eq!(resolved("version = 1\n", &["--steering-range=-90"]).unwrap_err(), "steering-range must be greater than zero");
+        assert_eq!(resolved("version = 1\n", &["--steering-range", "5"]).unwrap_err(), "steering-range must be at least 10 degrees");
+        assert!((resolved("version = 1\n", &["--steering-range", "10"]).unwrap().steering_stop - 5f64.to_radians()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn input_time_bits_pick_the_event_layout() {
+        let mut cfg = Config::default();
+        assert_eq!(cfg.input_layout, EventLayout::native());
+        cfg.set("input-time-bits", "32").unwrap();
+        assert_eq!(cfg.input_layout, EventLayout::Time32);
+        cfg.set("input-time-bits", "64").unwrap();
+        assert_eq!(cfg.input_layout, EventLayout::Time64);
+        assert!(cfg.set("input-time-bits", "16").is_err());
+    }
+
+    #[test]
+    fn each_profile_loads_its_own_calibration() {
+        let dir = std::env::temp_dir().join(format!("analogstick2wheel-calibration-profiles-{}", std::process::id()));
+        let store = CalibrationStore::new(&dir);
+        let race = Config { max_magnitude_x: 30000.0, center_offset: (120.0, -40.0), ..Config::default() };
+        store.save(Some("race"), &race.export_calibration()).unwrap();
+        store.save(None, &Config { max_magnitude_x: 31000.0, ..Config::default() }.export_calibration()).unwrap();
+        let dir_arg = dir.to_str().unwrap();
+        let loaded_race = resolved(PROFILES, &["--calibration-dir", dir_arg, "--profile", "race"]);
+        let loaded_default = resolved(PROFILES, &["--calibration-dir", dir_arg]);
+        // a profile without a calibration of its own doesn't borrow another's
+        let loaded_drift = resolved(PROFILES, &["--calibration-dir", dir_arg, "--profile", "drift"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let loaded_race = loaded_race.unwrap();
+        assert_eq!((loaded_race.max_magnitude_x, loaded_race.center_offset), (30000.0, (120.0, -40.0)));
+        assert_eq!(loaded_race.deadzone, 0.2);
+        assert_eq!(loaded_default.unwrap().max_magnitude_x, 31000.0);
+        assert_eq!(loaded_drift.unwrap().max_magnitude_x, Config::default().max_magnitude_x);
+    }
+
+    #[test]
+    fn output_rate_is_bounded() {
+        let mut cfg = Config::default();
+        assert!(cfg.set("output-rate", "1e-300").is_err());
+        assert!(cfg.set("output-rate", "0.5").is_err());
+        assert!(cfg.set("output-rate", "20000").is_err());
+        cfg.set("output-rate", "250").unwrap();
+        assert_eq!(cfg.output_rate, Some(250.0));
+    }
+
+    #[test]
+    fn output_rate_takes_over_the_heartbeat() {
+        let mut cfg = Config::default();
+        cfg.set("heartbeat", "100").unwrap();
+        assert_eq!(cfg.heartbeat_interval(), Some(Duration::from_millis(100)));
+        cfg.set("output-rate", "250").unwrap();
+        assert_eq!(cfg.heartbeat_interval(), None);
+    }
+
+    #[test]
+    fn next_profile_cycles_in_file_order() {
+        let cfg = resolved(PROFILES, &[]).unwrap();
+        let race = cfg.next_profile().unwrap();
+        assert_eq!(race.profile.as_deref(), Some("race"));
+        let drift = race.next_profile().unwrap();
+        assert_eq!(drift.profile.as_deref(), Some("drift"));
+        // the drift profile doesn't set a deadzone, so the top level one is back
+        assert_eq!((drift.deadzone, drift.ratchet), (0.1, true));
+        assert_eq!(drift.next_profile().unwrap().profile.as_deref(), Some("race"));
+        assert!(resolved("deadzone = 0.1\n", &[]).unwrap().next_profile().is_err());
+    }
+}
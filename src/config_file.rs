@@ -0,0 +1,135 @@
+use std::{fs, path::Path};
+
+// a small TOML subset: `[section]` headers, `key = value` pairs with bare,
+// quoted or array values, and `#` comments. keys are the long option names.
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigFile {
+    pub version: u32,
+    // section name ("" for the top level) and its entries, in file order
+    pub sections: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read config {}: {e}", path.display()))?;
+        Self::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut file = Self { version: 0, sections: vec![(String::new(), Vec::new())] };
+        for (n, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                file.sections.push((name.trim().to_string(), Vec::new()));
+                continue;
+            }
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| format!("line {}: expected 'key = value'", n + 1))?;
+            let key = unquote(key.trim()).map_err(|e| format!("line {}: {e}", n + 1))?;
+            let value = parse_value(value.trim()).map_err(|e| format!("line {}: {e}", n + 1))?;
+            if key == "version" && file.sections.len() == 1 {
+                file.version = value.parse()
+                    .map_err(|_| format!("line {}: version must be a whole number", n + 1))?;
+                continue;
+            }
+            file.sections.last_mut().unwrap().1.push((key, value));
+        }
+        file.migrate()?;
+        Ok(file)
+    }
+
+    pub fn section(&self, name: &str) -> Option<&[(String, String)]> {
+        self.sections.iter()
+            .find(|(section, _)| section == name)
+            .map(|(_, entries)| entries.as_slice())
+    }
+
+    // upgrade older layouts in place, one version at a time
+    fn migrate(&mut self) -> Result<(), String> {
+        if self.version > CONFIG_VERSION {
+            return Err(format!("config version {} is newer than the supported version {CONFIG_VERSION}", self.version));
+        }
+        if self.version < CONFIG_VERSION {
+            eprintln!("warning: migrating config from version {} to {CONFIG_VERSION}, add `version = {CONFIG_VERSION}` once updated",
+                      self.version);
+        }
+        while self.version < CONFIG_VERSION {
+            match self.version {
+                // unversioned files may use TOML style snake_case setting names,
+                // at the top level and in profiles alike; bindings are keyed by key names
+                0 => for (_, entries) in self.sections.iter_mut().filter(|(section, _)| section != "bindings") {
+                    for (key, _) in entries.iter_mut() {
+                        *key = key.replace('_', "-");
+                    }
+                },
+                _ => unreachable!(),
+            }
+            self.version += 1;
+        }
+        Ok(())
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+fn unquote(value: &str) -> Result<String, String> {
+    match value.strip_prefix('"') {
+        None => Ok(value.to_string()),
+        Some(rest) => rest.strip_suffix('"')
+            .map(|s| s.replace("\\\"", "\"").replace("\\\\", "\\"))
+            .ok_or_else(|| "unterminated string".to_string()),
+    }
+}
+
+// arrays become the comma separated form the command line takes
+fn parse_value(value: &str) -> Result<String, String> {
+    match value.strip_prefix('[') {
+        None => unquote(value),
+        Some(rest) => {
+            let inner = rest.strip_suffix(']').ok_or_else(|| "unterminated array".to_string())?;
+            let items = inner.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(unquote)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(items.join(","))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unversioned_setting_names_are_migrated_in_every_section() {
+        let file = ConfigFile::parse("steering_range = 900\n[profile.rally]\ninvert_x = true\n[bindings]\nBTN_SELECT = \"center\"\n").unwrap();
+        assert_eq!(file.version, CONFIG_VERSION);
+        assert_eq!(file.section("").unwrap(), [("steering-range".to_string(), "900".to_string())]);
+        assert_eq!(file.section("profile.rally").unwrap(), [("invert-x".to_string(), "true".to_string())]);
+        assert_eq!(file.section("bindings").unwrap(), [("BTN_SELECT".to_string(), "center".to_string())]);
+    }
+
+    #[test]
+    fn versioned_files_are_left_alone() {
+        let file = ConfigFile::parse("version = 1\n[profile.rally]\nsome_key = 1\n").unwrap();
+        assert_eq!(file.section("profile.rally").unwrap(), [("some_key".to_string(), "1".to_string())]);
+        assert!(ConfigFile::parse("version = 2\n").is_err());
+    }
+}
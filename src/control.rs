@@ -0,0 +1,109 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+// line based commands for driving a running instance from scripts
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControlCommand {
+    Center,
+    SetRange(String),
+    Set(String, String),
+    Calibrate,
+    FinishCalibration,
+    ExportProfile(String),
+    Pause,
+    Resume,
+    NextProfile,
+}
+
+impl ControlCommand {
+    // the last argument is the rest of the line, so a path may contain spaces
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let (command, argument) = split_word(line.trim());
+        match (command, argument) {
+            ("center", "") => Ok(Self::Center),
+            ("set-range", degrees) if !degrees.is_empty() => Ok(Self::SetRange(degrees.to_string())),
+            ("set", setting) if !split_word(setting).1.is_empty() => {
+                let (key, value) = split_word(setting);
+                Ok(Self::Set(key.to_string(), value.to_string()))
+            },
+            ("calibrate", "") => Ok(Self::Calibrate),
+            ("finish-calibration", "") => Ok(Self::FinishCalibration),
+            ("export-profile", path) if !path.is_empty() => Ok(Self::ExportProfile(path.to_string())),
+            ("pause", "") => Ok(Self::Pause),
+            ("resume", "") => Ok(Self::Resume),
+            ("next-profile", "") => Ok(Self::NextProfile),
+            _ => Err(format!("unknown control command '{}'", line.trim())),
+        }
+    }
+}
+
+// the first word and the trimmed rest
+fn split_word(text: &str) -> (&str, &str) {
+    text.split_once(char::is_whitespace).map_or((text, ""), |(word, rest)| (word, rest.trim()))
+}
+
+// reads commands from a fifo forever, reopening it whenever the writer goes away
+pub fn listen(path: PathBuf, mut handle: impl FnMut(ControlCommand) + Send + 'static) {
+    thread::spawn(move || loop {
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("error: couldn't open control channel {}: {e}", path.display());
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+        };
+        for line in BufReader::new(file).lines() {
+            let Ok(line) = line else { break };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match ControlCommand::parse(&line) {
+                Ok(command) => handle(command),
+                Err(e) => eprintln!("error: {e}"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{ffi::CString, fs::OpenOptions, io::Write, os::unix::ffi::OsStrExt, sync::mpsc};
+
+    #[test]
+    fn commands_parse_with_their_arguments() {
+        assert_eq!(ControlCommand::parse("center"), Ok(ControlCommand::Center));
+        assert_eq!(ControlCommand::parse("  set-range 540 "), Ok(ControlCommand::SetRange("540".to_string())));
+        assert_eq!(ControlCommand::parse("set deadzone 0.1"), Ok(ControlCommand::Set("deadzone".to_string(), "0.1".to_string())));
+        assert_eq!(ControlCommand::parse("export-profile /tmp/p"), Ok(ControlCommand::ExportProfile("/tmp/p".to_string())));
+        assert_eq!(ControlCommand::parse("export-profile /tmp/my profile "),
+                   Ok(ControlCommand::ExportProfile("/tmp/my profile".to_string())));
+        assert_eq!(ControlCommand::parse("next-profile"), Ok(ControlCommand::NextProfile));
+        assert_eq!(ControlCommand::parse("set deadzone"), Err("unknown control command 'set deadzone'".to_string()));
+        assert!(ControlCommand::parse("center now").is_err());
+    }
+
+    #[test]
+    fn the_fifo_is_read_again_after_each_writer() {
+        let path = std::env::temp_dir().join(format!("analogstick2wheel-control-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        let (sender, commands) = mpsc::channel();
+        listen(path.clone(), move |command| sender.send(command).unwrap());
+        for lines in ["pause\n\nbogus\n", "resume\n"] {
+            OpenOptions::new().write(true).open(&path).unwrap().write_all(lines.as_bytes()).unwrap();
+        }
+        let timeout = Duration::from_secs(5);
+        assert_eq!(commands.recv_timeout(timeout), Ok(ControlCommand::Pause));
+        assert_eq!(commands.recv_timeout(timeout), Ok(ControlCommand::Resume));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,383 @@
+use std::{fs, path::Path};
+
+// output shaping stages applied to the wheel angle before quantization
+
+// piecewise linear angle-in -> angle-out lookup, symmetric around center
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResponseTable {
+    points: Vec<(f64, f64)>,
+}
+
+impl ResponseTable {
+    // points are (input, output) radian pairs for the positive half of the range
+    pub fn new(points: Vec<(f64, f64)>) -> Result<Self, String> {
+        let first = match (points.first(), points.last()) {
+            (Some(first), Some(_)) if points.len() >= 2 => *first,
+            _ => return Err("response table needs at least two points".to_string()),
+        };
+        if first != (0.0, 0.0) {
+            return Err("response table must start at 0 0".to_string());
+        }
+        for pair in points.windows(2) {
+            if pair[1].0 <= pair[0].0 || pair[1].1 < pair[0].1 {
+                return Err("response table must be monotonic".to_string());
+            }
+        }
+        Ok(Self { points })
+    }
+
+    // one "input output" pair in degrees per line, '#' starts a comment
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read response table {}: {e}", path.display()))?;
+        let mut points = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values = line.split_whitespace()
+                .map(|v| v.parse::<f64>().ok().filter(|v| v.is_finite()))
+                .collect::<Option<Vec<_>>>();
+            match values.as_deref() {
+                Some([i, o]) => points.push((i.to_radians(), o.to_radians())),
+                _ => return Err(format!("{}:{}: expected two numbers", path.display(), n + 1)),
+            }
+        }
+        Self::new(points)
+    }
+
+    pub fn spans(&self, range: f64) -> bool {
+        self.points.last().is_some_and(|(x, _)| *x >= range)
+    }
+
+    pub fn apply(&self, angle: f64) -> f64 {
+        let a = angle.abs();
+        let i = self.points.partition_point(|(x, _)| *x <= a);
+        let out = match (i.checked_sub(1).and_then(|j| self.points.get(j)), self.points.get(i)) {
+            (Some(&(x0, y0)), Some(&(x1, y1))) => y0 + (y1 - y0) * (a - x0)/(x1 - x0),
+            (Some(&(_, y)), None) => y,
+            _ => 0.0,
+        };
+        out.copysign(angle)
+    }
+}
+
+// power curve over the normalized lock fraction with separate exponents
+// below and above the crossover, meeting at it so nothing steps there
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PiecewiseGamma {
+    pub inner: f64,
+    pub outer: f64,
+    pub crossover: f64,
+}
+
+impl Default for PiecewiseGamma {
+    fn default() -> Self {
+        Self { inner: 1.0, outer: 1.0, crossover: 0.5 }
+    }
+}
+
+impl PiecewiseGamma {
+    pub fn is_identity(&self) -> bool {
+        self.inner == 1.0 && self.outer == 1.0
+    }
+
+    pub fn apply(&self, angle: f64, range: f64) -> f64 {
+        let u = (angle.abs()/range).min(1.0);
+        let c = self.crossover;
+        let v = if u < c {
+            c * (u/c).powf(self.inner)
+        } else {
+            c + (1.0 - c) * ((u - c)/(1.0 - c)).powf(self.outer)
+        };
+        (v * range).copysign(angle)
+    }
+}
+
+// cancels a game's own power curve on the axis: the game raising this
+// output to `game_gamma` gets back the original lock fraction
+pub fn predistort(angle: f64, range: f64, game_gamma: f64) -> f64 {
+    let u = (angle.abs()/range).min(1.0);
+    (u.powf(game_gamma.recip()) * range).copysign(angle)
+}
+
+// second order follower: the output chases the wheel with bounded acceleration,
+// braking early enough to stop on the target instead of overshooting it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AccelLimiter {
+    pub position: f64,
+    velocity: f64,
+}
+
+impl AccelLimiter {
+    pub fn step(&mut self, target: f64, max_accel: f64, d_t: f64) -> f64 {
+        let error = target - self.position;
+        // fastest approach that can still brake to a halt at the target
+        let braking_velocity = (2.0 * max_accel * error.abs()).sqrt().copysign(error);
+        let max_dv = max_accel * d_t;
+        self.velocity += (braking_velocity - self.velocity).clamp(-max_dv, max_dv);
+        self.position += self.velocity * d_t;
+        if (target - self.position) * error <= 0.0 {
+            self.position = target;
+            self.velocity = 0.0;
+        }
+        self.position
+    }
+
+    pub fn reset(&mut self, position: f64) {
+        self.position = position;
+        self.velocity = 0.0;
+    }
+}
+
+// cutoff of --stick-smoothing's filter for a given stick speed: `slow` hertz
+// at rest, rising in a straight line to `fast` at `full_speed` full
+// deflections per second and held there
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SmoothingCurve {
+    pub slow: f64,
+    pub fast: f64,
+    pub full_speed: f64,
+}
+
+impl SmoothingCurve {
+    pub fn cutoff(&self, speed: f64) -> f64 {
+        self.slow + (self.fast - self.slow) * (speed / self.full_speed).min(1.0)
+    }
+}
+
+// gain on fast gripped rotation that depends on where the wheel is: `center`
+// times the rotation around center and `lock` times at full lock, so a quick
+// turn gets to lock fast without flying past where it was meant to stop.
+// turns slower than `speed` radians per second get proportionally less of it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpeedGain {
+    pub center: f64,
+    pub lock: f64,
+    pub speed: f64,
+}
+
+impl SpeedGain {
+    // `position` is how far along towards the stop the wheel is, 0 to 1;
+    // turning back towards center always gets the center gain
+    pub fn gain(&self, position: f64, angular_speed: f64, towards_lock: bool) -> f64 {
+        let full = if towards_lock { self.center + (self.lock - self.center) * position.clamp(0.0, 1.0) } else { self.center };
+        1.0 + (full - 1.0) * (angular_speed / self.speed).min(1.0)
+    }
+}
+
+// the stick speed is smoothed as well, at this cutoff in hertz, so one noisy
+// sample doesn't open the filter up
+const SPEED_CUTOFF: f64 = 1.0;
+
+// low pass on the raw stick whose cutoff follows how fast it moves, slow
+// drift is smoothed while a flick goes through with hardly any lag
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AdaptiveFilter {
+    // filtered position and the raw one it came from, in MAX_MAGNITUDE units
+    position: Option<((f64, f64), (f64, f64))>,
+    // in full deflections per second
+    speed: f64,
+}
+
+impl AdaptiveFilter {
+    pub fn step(&mut self, curve: &SmoothingCurve, raw: (f64, f64), full_deflection: f64, d_t: f64) -> (f64, f64) {
+        let Some((filtered, last_raw)) = self.position else {
+            self.position = Some((raw, raw));
+            return raw;
+        };
+        if d_t <= 0.0 {
+            return filtered;
+        }
+        let moved = (raw.0 - last_raw.0).hypot(raw.1 - last_raw.1) / full_deflection;
+        self.speed += (moved / d_t - self.speed) * smoothing_factor(SPEED_CUTOFF, d_t);
+        let t = smoothing_factor(curve.cutoff(self.speed), d_t);
+        let filtered = (filtered.0 + (raw.0 - filtered.0) * t, filtered.1 + (raw.1 - filtered.1) * t);
+        self.position = Some((filtered, raw));
+        filtered
+    }
+}
+
+// share of the way a first order low pass at `cutoff` hertz moves in `d_t` seconds
+fn smoothing_factor(cutoff: f64, d_t: f64) -> f64 {
+    1.0 - (-std::f64::consts::TAU * cutoff * d_t).exp()
+}
+
+// picks one of `count` evenly spaced levels across -1..1 for a position in
+// that range; leaving the current level takes `hysteresis` (a fraction of the
+// level spacing) past the midpoint to the next one, so it doesn't chatter
+pub fn digital_level(position: f64, count: usize, hysteresis: f64, current: Option<usize>) -> usize {
+    let step = 2.0 / (count - 1) as f64;
+    let nearest = (((position.clamp(-1.0, 1.0) + 1.0) / step).round() as usize).min(count - 1);
+    match current {
+        Some(level) if level < count && (position - level_position(level, count)).abs() <= step * (0.5 + hysteresis) => level,
+        _ => nearest,
+    }
+}
+
+pub fn level_position(level: usize, count: usize) -> f64 {
+    -1.0 + 2.0 * level as f64 / (count - 1) as f64
+}
+
+// rumble motor magnitude for a steering effort in 0..1, shaped by `gamma`
+// and topping out at `strength` of full power
+pub fn rumble_intensity(effort: f64, gamma: f64, strength: f64) -> u16 {
+    (effort.clamp(0.0, 1.0).powf(gamma) * strength * u16::MAX as f64).round() as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn response_table_interpolates_symmetrically() {
+        let table = ResponseTable::new(vec![(0.0, 0.0), (1.0, 0.5), (2.0, 2.0)]).unwrap();
+        assert!(close(table.apply(0.5), 0.25));
+        assert!(close(table.apply(1.5), 1.25));
+        assert!(close(table.apply(-1.5), -1.25));
+        // held at the last point past the end of the table
+        assert!(close(table.apply(3.0), 2.0));
+        assert!(table.spans(2.0));
+        assert!(!table.spans(2.5));
+    }
+
+    #[test]
+    fn response_table_rejects_malformed_points() {
+        assert!(ResponseTable::new(vec![(0.0, 0.0)]).is_err());
+        assert!(ResponseTable::new(vec![(0.1, 0.0), (1.0, 1.0)]).is_err());
+        assert!(ResponseTable::new(vec![(0.0, 0.0), (1.0, 1.0), (1.0, 2.0)]).is_err());
+        assert!(ResponseTable::new(vec![(0.0, 0.0), (1.0, 1.0), (2.0, 0.5)]).is_err());
+    }
+
+    #[test]
+    fn response_table_loads_degrees_with_comments() {
+        let path = std::env::temp_dir().join(format!("analogstick2wheel-response-{}", std::process::id()));
+        fs::write(&path, "# in out\n0 0\n\n90 45 # halfway\n180 180\n").unwrap();
+        let table = ResponseTable::load(&path);
+        fs::write(&path, "0 0\n90\n").unwrap();
+        let malformed = ResponseTable::load(&path);
+        fs::remove_file(&path).unwrap();
+        let table = table.unwrap();
+        assert!(close(table.apply(45f64.to_radians()), 22.5f64.to_radians()));
+        assert!(malformed.unwrap_err().ends_with(":2: expected two numbers"));
+    }
+
+    #[test]
+    fn piecewise_gamma_meets_at_the_crossover() {
+        let gamma = PiecewiseGamma { inner: 2.0, outer: 0.5, crossover: 0.4 };
+        let range = 450f64.to_radians();
+        assert!(close(gamma.apply(0.0, range), 0.0));
+        assert!(close(gamma.apply(0.4 * range, range), 0.4 * range));
+        assert!(close(gamma.apply(range, range), range));
+        // below the crossover squared, above it square rooted
+        assert!(close(gamma.apply(0.2 * range, range), 0.4 * 0.25 * range));
+        assert!(close(gamma.apply(-0.7 * range, range), -(0.4 + 0.6 * 0.5f64.sqrt()) * range));
+        // held at full lock past it
+        assert!(close(gamma.apply(2.0 * range, range), range));
+    }
+
+    #[test]
+    fn the_default_gamma_is_the_identity() {
+        let gamma = PiecewiseGamma::default();
+        assert!(gamma.is_identity());
+        assert!(close(gamma.apply(-1.234, 3.0), -1.234));
+        assert!(!PiecewiseGamma { inner: 1.5, ..gamma }.is_identity());
+    }
+
+    #[test]
+    fn predistortion_cancels_the_game_curve() {
+        let range = 450f64.to_radians();
+        for fraction in [0.0, 0.1, 0.5, 0.9, 1.0] {
+            let angle = fraction * range;
+            let out = predistort(angle, range, 2.0);
+            // what the game makes of it, squaring the lock fraction
+            assert!(close((out / range).powf(2.0) * range, angle));
+            assert!(close(predistort(-angle, range, 2.0), -out));
+        }
+        assert!(close(predistort(0.25 * range, range, 2.0), 0.5 * range));
+    }
+
+    #[test]
+    fn the_accel_limiter_stops_on_the_target_without_overshooting() {
+        let mut limiter = AccelLimiter::default();
+        let (max_accel, d_t) = (10.0, 0.01);
+        let mut steps = 0;
+        let mut last_velocity = 0.0;
+        while limiter.position != 1.0 {
+            let before = limiter.position;
+            limiter.step(1.0, max_accel, d_t);
+            assert!(limiter.position <= 1.0 && limiter.position >= before);
+            let velocity = (limiter.position - before) / d_t;
+            // the speed changes by no more than max_accel allows, except for landing on the target
+            if limiter.position != 1.0 {
+                assert!((velocity - last_velocity).abs() <= max_accel * d_t + 1e-9);
+            }
+            last_velocity = velocity;
+            steps += 1;
+            assert!(steps < 1000);
+        }
+        // about the 2 * sqrt(distance / max_accel) seconds of a bang-bang move
+        assert!((50..80).contains(&steps), "{steps}");
+        limiter.reset(-1.0);
+        assert_eq!(limiter.step(-1.0, max_accel, d_t), -1.0);
+    }
+
+    #[test]
+    fn digital_levels_snap_with_hysteresis() {
+        // five levels at -1, -0.5, 0, 0.5 and 1
+        assert_eq!(level_position(0, 5), -1.0);
+        assert_eq!(level_position(3, 5), 0.5);
+        assert_eq!(digital_level(0.2, 5, 0.0, None), 2);
+        assert_eq!(digital_level(0.3, 5, 0.0, None), 3);
+        assert_eq!(digital_level(-3.0, 5, 0.0, None), 0);
+        // a tenth of the spacing past the midpoint before leaving the level
+        assert_eq!(digital_level(0.27, 5, 0.1, Some(2)), 2);
+        assert_eq!(digital_level(0.31, 5, 0.1, Some(2)), 3);
+        assert_eq!(digital_level(0.23, 5, 0.1, Some(3)), 3);
+        assert_eq!(digital_level(0.19, 5, 0.1, Some(3)), 2);
+    }
+
+    #[test]
+    fn rumble_intensity_follows_the_effort_curve() {
+        assert_eq!(rumble_intensity(0.0, 2.0, 1.0), 0);
+        assert_eq!(rumble_intensity(1.0, 2.0, 1.0), u16::MAX);
+        assert_eq!(rumble_intensity(0.5, 1.0, 1.0), 32768);
+        assert_eq!(rumble_intensity(0.5, 2.0, 1.0), 16384);
+        assert_eq!(rumble_intensity(0.5, 2.0, 0.5), 8192);
+        // effort past either end doesn't take the motor outside its range
+        assert_eq!(rumble_intensity(1.5, 2.0, 1.0), u16::MAX);
+        assert_eq!(rumble_intensity(-0.5, 2.0, 1.0), 0);
+    }
+
+    #[test]
+    fn the_adaptive_filter_smooths_a_slow_ramp_and_passes_a_fast_step() {
+        let curve = SmoothingCurve { slow: 1.0, fast: 50.0, full_speed: 2.0 };
+        let full = 32767.0;
+        let d_t = 0.005;
+        // a tenth of a deflection per second for a second
+        let mut filter = AdaptiveFilter::default();
+        let mut filtered = (0.0, 0.0);
+        for step in 0..=200 {
+            filtered = filter.step(&curve, (0.1 * full * step as f64 * d_t, 0.0), full, d_t);
+        }
+        // trailing several reports behind
+        assert!(filtered.0 < 0.1 * full - 5.0 * 0.1 * full * d_t, "{}", filtered.0 / full);
+        assert!(filtered.0 > 0.0);
+        // a full deflection from one report to the next
+        let mut filter = AdaptiveFilter::default();
+        filter.step(&curve, (0.0, 0.0), full, d_t);
+        filtered = filter.step(&curve, (0.0, full), full, d_t);
+        assert!(filtered.1 > 0.7 * full, "{}", filtered.1 / full);
+        for _ in 0..2 {
+            filtered = filter.step(&curve, (0.0, full), full, d_t);
+        }
+        assert!(filtered.1 > 0.98 * full, "{}", filtered.1 / full);
+        // the first sample has nothing to smooth against
+        assert_eq!(AdaptiveFilter::default().step(&curve, (5.0, 6.0), full, d_t), (5.0, 6.0));
+    }
+}
@@ -0,0 +1,146 @@
+use input_linux::{AbsoluteAxis, AbsoluteInfo, EventKind, EvdevHandle, ForceFeedbackKind, InputId};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io,
+    path::Path,
+};
+
+// what an evdev device says about itself
+pub struct Capabilities {
+    pub name: String,
+    pub id: InputId,
+    pub events: Vec<EventKind>,
+    pub axes: Vec<(AbsoluteAxis, AbsoluteInfo)>,
+    pub force_feedback: Vec<ForceFeedbackKind>,
+    pub effects: Option<i32>,
+    // whether an exclusive grab works, i.e. nobody else (such as intercept) holds one
+    pub grab: Result<(), String>,
+}
+
+impl Capabilities {
+    pub fn probe(path: &Path) -> io::Result<Self> {
+        // read-write access is needed for force feedback, fall back for plain inspection
+        let file = OpenOptions::new().read(true).write(true).open(path)
+            .or_else(|_| File::open(path))?;
+        let handle = EvdevHandle::new(file);
+        let name = handle.device_name()?;
+        let events: Vec<EventKind> = handle.event_bits()?.iter().collect();
+        let axes = if events.contains(&EventKind::Absolute) {
+            handle.absolute_bits()?.iter()
+                .map(|axis| handle.absolute_info(axis).map(|info| (axis, info)))
+                .collect::<io::Result<_>>()?
+        } else {
+            Vec::new()
+        };
+        let (force_feedback, effects) = if events.contains(&EventKind::ForceFeedback) {
+            (handle.force_feedback_bits()?.iter().collect(), handle.effects_count().ok())
+        } else {
+            (Vec::new(), None)
+        };
+        let grab = handle.grab(true).and_then(|_| handle.grab(false)).map_err(|e| e.to_string());
+        Ok(Self {
+            name: String::from_utf8_lossy(&name).trim_end_matches('\0').to_string(),
+            id: handle.device_id()?,
+            events,
+            axes,
+            force_feedback,
+            effects,
+            grab,
+        })
+    }
+
+    pub fn axis(&self, axis: AbsoluteAxis) -> Option<&AbsoluteInfo> {
+        self.axes.iter().find(|(a, _)| *a == axis).map(|(_, info)| info)
+    }
+
+    // settings that line the stick's reported ranges up with what the wheel model expects
+    pub fn suggestions(&self) -> Vec<String> {
+        let mut suggestions = Vec::new();
+        for (axis, setting) in [(AbsoluteAxis::X, "max-magnitude-x"), (AbsoluteAxis::Y, "max-magnitude-y")] {
+            let Some(info) = self.axis(axis) else { continue };
+            if info.minimum >= 0 {
+                suggestions.push(format!("{axis:?} is not centered on zero ({}..{}), the stick is expected to report signed values",
+                                         info.minimum, info.maximum));
+                continue;
+            }
+            // -32768..32767 is as far each way as the model's 32767, not a unit further
+            let extent = info.maximum.max(info.minimum.saturating_add(1).saturating_neg());
+            if extent as f64 != crate::MAX_MAGNITUDE {
+                suggestions.push(format!("--{setting} {extent}"));
+            }
+        }
+        suggestions
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "name: {}", self.name)?;
+        writeln!(f, "id: bus {:#06x} vendor {:#06x} product {:#06x} version {:#06x}",
+                 self.id.bustype, self.id.vendor, self.id.product, self.id.version)?;
+        writeln!(f, "events: {:?}", self.events)?;
+        for (axis, info) in &self.axes {
+            writeln!(f, "  {axis:?}: value {} range {}..{} fuzz {} flat {} resolution {}",
+                     info.value, info.minimum, info.maximum, info.fuzz, info.flat, info.resolution)?;
+        }
+        if !self.force_feedback.is_empty() {
+            writeln!(f, "force feedback: {:?}", self.force_feedback)?;
+            if let Some(effects) = self.effects {
+                writeln!(f, "  simultaneous effects: {effects}")?;
+            }
+        }
+        match &self.grab {
+            Ok(()) => writeln!(f, "grab: ok")?,
+            Err(e) => writeln!(f, "grab: failed, {e}")?,
+        }
+        for suggestion in self.suggestions() {
+            writeln!(f, "suggestion: {suggestion}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stick(x: (i32, i32), y: (i32, i32)) -> Capabilities {
+        let info = |(minimum, maximum)| AbsoluteInfo { value: 0, minimum, maximum, fuzz: 16, flat: 128, resolution: 0 };
+        Capabilities {
+            name: "pad".to_string(),
+            id: InputId { bustype: 3, vendor: 0x45e, product: 0x2ea, version: 0x301 },
+            events: vec![EventKind::Synchronize, EventKind::Absolute],
+            axes: vec![(AbsoluteAxis::X, info(x)), (AbsoluteAxis::Y, info(y))],
+            force_feedback: Vec::new(),
+            effects: None,
+            grab: Err("Device or resource busy".to_string()),
+        }
+    }
+
+    #[test]
+    fn ranges_other_than_the_expected_are_suggested_settings() {
+        assert!(stick((-32768, 32767), (-32768, 32767)).suggestions().is_empty());
+        assert_eq!(stick((-512, 511), (0, 255)).suggestions(), [
+            "--max-magnitude-x 511",
+            "Y is not centered on zero (0..255), the stick is expected to report signed values",
+        ]);
+    }
+
+    #[test]
+    fn capabilities_print_with_the_suggestions() {
+        let text = stick((-512, 511), (-32768, 32767)).to_string();
+        assert_eq!(text, "name: pad\n\
+                          id: bus 0x0003 vendor 0x045e product 0x02ea version 0x0301\n\
+                          events: [Synchronize, Absolute]\n  \
+                          X: value 0 range -512..511 fuzz 16 flat 128 resolution 0\n  \
+                          Y: value 0 range -32768..32767 fuzz 16 flat 128 resolution 0\n\
+                          grab: failed, Device or resource busy\n\
+                          suggestion: --max-magnitude-x 511\n");
+    }
+
+    #[test]
+    fn probing_something_other_than_an_evdev_device_fails() {
+        assert!(Capabilities::probe(Path::new("/dev/null")).is_err());
+    }
+}
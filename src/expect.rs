@@ -0,0 +1,145 @@
+use input_linux::{AbsoluteAxis, Event};
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::input::{EventLayout, EventReader};
+
+// --expect: the wheel reports as they go out, checked one by one against a
+// --tee capture of a run that steered right. only the first divergence is
+// reported, everything after it follows from it
+pub struct Expectation {
+    path: PathBuf,
+    expected: Vec<i32>,
+    // output bytes short of a whole event
+    partial: Vec<u8>,
+    frame: usize,
+    diverged: bool,
+}
+
+impl Expectation {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let expected = axis_values(File::open(path)?)?;
+        Ok(Self { path: path.to_path_buf(), expected, partial: Vec::new(), frame: 0, diverged: false })
+    }
+
+    fn check(&mut self, actual: i32) {
+        if self.diverged {
+            return;
+        }
+        match self.expected.get(self.frame) {
+            Some(&expected) if expected == actual => (),
+            Some(&expected) => {
+                eprintln!("expect: frame {} diverges from {}, expected {expected}, got {actual}", self.frame, self.path.display());
+                self.diverged = true;
+            },
+            None => {
+                eprintln!("expect: frame {} is past the end of {}, got {actual}", self.frame, self.path.display());
+                self.diverged = true;
+            },
+        }
+        self.frame += 1;
+    }
+
+    // the verdict once the output is done
+    pub fn finish(&self) {
+        if self.diverged {
+            return;
+        }
+        if self.frame < self.expected.len() {
+            eprintln!("expect: output ended after {} of the {} frames in {}", self.frame, self.expected.len(), self.path.display());
+        } else {
+            eprintln!("expect: all {} frames matched {}", self.frame, self.path.display());
+        }
+    }
+}
+
+// the wheel axis values in a stream of native input_events
+fn axis_values(stream: impl io::Read) -> io::Result<Vec<i32>> {
+    let mut reader = EventReader::new(stream, EventLayout::native());
+    let mut values = Vec::new();
+    while let Some(event) = reader.next_event()? {
+        if let Event::Absolute(event) = event
+            && event.axis == AbsoluteAxis::X {
+            values.push(event.value);
+        }
+    }
+    Ok(values)
+}
+
+// the output mirror feeding an Expectation, which stays shared so its verdict can be given at exit
+pub struct ExpectationWriter(pub Arc<Mutex<Expectation>>);
+
+impl Write for ExpectationWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut expectation = self.0.lock().unwrap();
+        expectation.partial.extend_from_slice(buf);
+        let size = EventLayout::native().size();
+        let whole = expectation.partial.len() / size * size;
+        let events: Vec<u8> = expectation.partial.drain(..whole).collect();
+        for value in axis_values(events.as_slice())? {
+            expectation.check(value);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use input_linux::{AbsoluteEvent, EventTime, SynchronizeEvent};
+    use std::{env, fs};
+
+    // wheel reports as they go out: the axis value, then a SYN_REPORT
+    fn reports(values: &[i32]) -> Vec<u8> {
+        values.iter().flat_map(|&value| {
+            let time = EventTime::new(1, 0);
+            [AbsoluteEvent::new(time, AbsoluteAxis::X, value).into_event().as_bytes().to_vec(),
+             SynchronizeEvent::report(time).into_event().as_bytes().to_vec()].concat()
+        }).collect()
+    }
+
+    fn expecting(name: &str, values: &[i32]) -> Arc<Mutex<Expectation>> {
+        let path = env::temp_dir().join(format!("analogstick2wheel-expect-{name}-{}", std::process::id()));
+        fs::write(&path, reports(values)).unwrap();
+        let expectation = Expectation::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        Arc::new(Mutex::new(expectation))
+    }
+
+    #[test]
+    fn a_matching_run_checks_every_frame() {
+        let expectation = expecting("match", &[32767, 33000, 34000]);
+        let mut writer = ExpectationWriter(expectation.clone());
+        let output = reports(&[32767, 33000, 34000]);
+        // split mid-event like a batched or partial write would be
+        writer.write_all(&output[..30]).unwrap();
+        writer.write_all(&output[30..]).unwrap();
+        let expectation = expectation.lock().unwrap();
+        assert_eq!((expectation.frame, expectation.diverged), (3, false));
+        assert!(expectation.partial.is_empty());
+    }
+
+    #[test]
+    fn a_mismatch_stops_at_the_first_divergence() {
+        let expectation = expecting("mismatch", &[32767, 33000, 34000]);
+        let mut writer = ExpectationWriter(expectation.clone());
+        writer.write_all(&reports(&[32767, 33100, 35000])).unwrap();
+        let state = expectation.lock().unwrap();
+        // frame 1 diverged, nothing after it was checked
+        assert_eq!((state.frame, state.diverged), (2, true));
+        drop(state);
+
+        let expectation = expecting("overlong", &[32767]);
+        ExpectationWriter(expectation.clone()).write_all(&reports(&[32767, 33000])).unwrap();
+        assert!(expectation.lock().unwrap().diverged);
+    }
+}
@@ -0,0 +1,215 @@
+use input_linux::{EvdevHandle, InputEvent, UInputHandle, sys};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io, mem,
+    path::Path,
+    sync::Arc,
+    thread,
+};
+
+// smallest change in motor magnitude worth another upload
+const RUMBLE_STEP: u16 = u16::MAX / 64;
+
+// effects the game uploads to the virtual device, played as rumble on the
+// controller. the kernel blocks the game's upload or erase until we answer it
+// through the uinput fd, so requests are acknowledged even without a controller
+pub fn spawn(device: Arc<UInputHandle<File>>, target: Option<EvdevHandle<File>>) {
+    thread::spawn(move || {
+        let mut bridge = Bridge { device, target, effects: HashMap::new() };
+        let mut events = [InputEvent::zeroed().into_raw(); 16];
+        loop {
+            let count = match bridge.device.read(&mut events) {
+                Ok(count) => count,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    eprintln!("error: reading force feedback from the virtual device failed, no longer forwarding it: {e}");
+                    return;
+                }
+            };
+            for event in &events[..count] {
+                if let Err(e) = bridge.handle(event) {
+                    eprintln!("warning: force feedback request failed: {e}");
+                }
+            }
+        }
+    });
+}
+
+struct Bridge {
+    device: Arc<UInputHandle<File>>,
+    target: Option<EvdevHandle<File>>,
+    // effect ids on the virtual device to the ones the controller handed out
+    effects: HashMap<i16, i16>,
+}
+
+impl Bridge {
+    fn handle(&mut self, event: &sys::input_event) -> io::Result<()> {
+        match (event.type_ as i32, event.code as i32) {
+            (sys::EV_UINPUT, sys::UI_FF_UPLOAD) => self.upload(event.value as u32),
+            (sys::EV_UINPUT, sys::UI_FF_ERASE) => self.erase(event.value as u32),
+            (sys::EV_FF, _) => self.play(event),
+            _ => Ok(()),
+        }
+    }
+
+    fn upload(&mut self, request_id: u32) -> io::Result<()> {
+        let mut upload: sys::uinput_ff_upload = unsafe { mem::zeroed() };
+        upload.request_id = request_id;
+        self.device.ff_upload_begin(&mut upload)?;
+        let id = upload.effect.id;
+        upload.retval = match (&self.target, to_rumble(&upload.effect)) {
+            (Some(target), Some(mut rumble)) => {
+                rumble.id = self.effects.get(&id).copied().unwrap_or(-1);
+                match target.send_force_feedback(&mut rumble) {
+                    Ok(()) => {
+                        self.effects.insert(id, rumble.id);
+                        0
+                    },
+                    Err(e) => -e.raw_os_error().unwrap_or(sys::Errno::EIO as i32),
+                }
+            },
+            // accepted and never played, games tend to give up on force feedback altogether otherwise
+            _ => 0,
+        };
+        self.device.ff_upload_end(&upload)
+    }
+
+    fn erase(&mut self, request_id: u32) -> io::Result<()> {
+        let mut erase = sys::uinput_ff_erase { request_id, retval: 0, effect_id: 0 };
+        self.device.ff_erase_begin(&mut erase)?;
+        if let Some(id) = self.effects.remove(&(erase.effect_id as i16))
+            && let Some(target) = &self.target
+            && let Err(e) = target.erase_force_feedback(id) {
+            erase.retval = -e.raw_os_error().unwrap_or(sys::Errno::EIO as i32);
+        }
+        self.device.ff_erase_end(&erase)
+    }
+
+    // playback requests carry the effect id as their code, gain is passed on as is
+    fn play(&self, event: &sys::input_event) -> io::Result<()> {
+        let Some(target) = &self.target else { return Ok(()) };
+        let code = if event.code == sys::FF_GAIN {
+            event.code
+        } else {
+            match self.effects.get(&(event.code as i16)) {
+                Some(id) => *id as u16,
+                None => return Ok(()),
+            }
+        };
+        target.write(&[sys::input_event { code, ..*event }]).map(drop)
+    }
+}
+
+// the closest rumble to an uploaded effect: steady forces drive the strong
+// motor, periodic ones the weak motor. springs, dampers and the like depend
+// on the wheel position, which rumble can't express, so they give None
+pub fn to_rumble(effect: &sys::ff_effect) -> Option<sys::ff_effect> {
+    let params: &sys::ff_effect_union = effect.into();
+    let (strong, weak) = match effect.type_ {
+        sys::FF_RUMBLE => (params.rumble().strong_magnitude, params.rumble().weak_magnitude),
+        sys::FF_CONSTANT => (motor_level(params.constant().level), 0),
+        sys::FF_RAMP => (motor_level(params.ramp().start_level).max(motor_level(params.ramp().end_level)), 0),
+        sys::FF_PERIODIC => (0, motor_level(params.periodic().magnitude)),
+        _ => return None,
+    };
+    let mut rumble = *effect;
+    rumble.type_ = sys::FF_RUMBLE;
+    rumble.direction = 0;
+    rumble.u = Default::default();
+    let params: &mut sys::ff_effect_union = (&mut rumble).into();
+    params.rumble_mut().strong_magnitude = strong;
+    params.rumble_mut().weak_magnitude = weak;
+    Some(rumble)
+}
+
+// signed force levels to the unsigned motor range, either direction shakes the same
+fn motor_level(level: i16) -> u16 {
+    level.unsigned_abs().saturating_mul(2)
+}
+
+// a rumble effect of our own on the controller, retuned as the steering effort changes
+pub struct SteeringRumble {
+    device: EvdevHandle<File>,
+    effect: sys::ff_effect,
+}
+
+impl SteeringRumble {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let device = EvdevHandle::new(OpenOptions::new().read(true).write(true).open(path)?);
+        let mut effect: sys::ff_effect = unsafe { mem::zeroed() };
+        effect.type_ = sys::FF_RUMBLE;
+        // uploaded on first use, a zero length plays until replaced
+        effect.id = -1;
+        Ok(Self { device, effect })
+    }
+
+    // every upload is an ioctl, so small changes are left alone
+    pub fn set(&mut self, magnitude: u16) -> io::Result<()> {
+        let first = self.effect.id == -1;
+        let params: &mut sys::ff_effect_union = (&mut self.effect).into();
+        let current = params.rumble().strong_magnitude;
+        let settled = magnitude.abs_diff(current) < RUMBLE_STEP && (magnitude == 0) == (current == 0);
+        if settled && !first {
+            return Ok(());
+        }
+        params.rumble_mut().strong_magnitude = magnitude;
+        params.rumble_mut().weak_magnitude = magnitude;
+        self.device.send_force_feedback(&mut self.effect)?;
+        if first {
+            let play = sys::input_event { type_: sys::EV_FF as u16, code: self.effect.id as u16, value: 1, ..InputEvent::zeroed().into_raw() };
+            self.device.write(&[play])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an effect as a game would upload it, with the parameters filled in by `set`
+    fn uploaded(type_: u16, set: impl FnOnce(&mut sys::ff_effect_union)) -> sys::ff_effect {
+        let mut effect: sys::ff_effect = unsafe { mem::zeroed() };
+        effect.type_ = type_;
+        effect.id = 3;
+        effect.direction = 0x4000;
+        effect.replay.length = 500;
+        set((&mut effect).into());
+        effect
+    }
+
+    fn motors(effect: &sys::ff_effect) -> (u16, u16) {
+        let params: &sys::ff_effect_union = effect.into();
+        (params.rumble().strong_magnitude, params.rumble().weak_magnitude)
+    }
+
+    #[test]
+    fn uploaded_effects_translate_to_rumble() {
+        let constant = to_rumble(&uploaded(sys::FF_CONSTANT, |p| p.constant_mut().level = -0x2000)).unwrap();
+        assert_eq!((constant.type_, constant.direction), (sys::FF_RUMBLE, 0));
+        // the game's id and timing carry over so playing and erasing it still work
+        assert_eq!((constant.id, constant.replay.length), (3, 500));
+        assert_eq!(motors(&constant), (0x4000, 0));
+        let ramp = to_rumble(&uploaded(sys::FF_RAMP, |p| {
+            p.ramp_mut().start_level = 0x1000;
+            p.ramp_mut().end_level = -0x3000;
+        })).unwrap();
+        assert_eq!(motors(&ramp), (0x6000, 0));
+        let periodic = to_rumble(&uploaded(sys::FF_PERIODIC, |p| p.periodic_mut().magnitude = i16::MAX)).unwrap();
+        assert_eq!(motors(&periodic), (0, u16::MAX - 1));
+        let rumble = to_rumble(&uploaded(sys::FF_RUMBLE, |p| {
+            p.rumble_mut().strong_magnitude = 100;
+            p.rumble_mut().weak_magnitude = 200;
+        })).unwrap();
+        assert_eq!(motors(&rumble), (100, 200));
+        assert!(to_rumble(&uploaded(sys::FF_SPRING, |p| p.condition_mut()[0].right_saturation = 0x1000)).is_none());
+    }
+
+    #[test]
+    fn force_levels_saturate_on_the_motor_range() {
+        assert_eq!(motor_level(0), 0);
+        assert_eq!(motor_level(i16::MIN), u16::MAX);
+        assert_eq!(motor_level(-100), motor_level(100));
+    }
+}
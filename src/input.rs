@@ -0,0 +1,225 @@
+use input_linux::{Event, InputEvent, sys};
+use std::io::{self, Read};
+
+// one input_event of the widest layout
+const MAX_EVENT_SIZE: usize = 24;
+
+// width of the timeval at the start of each input_event, which differs between
+// 32 and 64 bit producers; type, code and value follow at the same sizes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventLayout {
+    Time32,
+    Time64,
+}
+
+impl EventLayout {
+    pub fn native() -> Self {
+        if cfg!(target_pointer_width = "64") { EventLayout::Time64 } else { EventLayout::Time32 }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            EventLayout::Time32 => 16,
+            EventLayout::Time64 => 24,
+        }
+    }
+
+    // (seconds, microseconds, type, code, value)
+    fn fields(self, bytes: &[u8]) -> (i64, i64, u16, u16, i32) {
+        let time = match self {
+            EventLayout::Time32 => 8,
+            EventLayout::Time64 => 16,
+        };
+        let (seconds, micros) = match self {
+            EventLayout::Time32 => (i32::from_ne_bytes(bytes[0..4].try_into().unwrap()) as i64,
+                                    i32::from_ne_bytes(bytes[4..8].try_into().unwrap()) as i64),
+            EventLayout::Time64 => (i64::from_ne_bytes(bytes[0..8].try_into().unwrap()),
+                                    i64::from_ne_bytes(bytes[8..16].try_into().unwrap())),
+        };
+        (seconds, micros,
+         u16::from_ne_bytes(bytes[time..time + 2].try_into().unwrap()),
+         u16::from_ne_bytes(bytes[time + 2..time + 4].try_into().unwrap()),
+         i32::from_ne_bytes(bytes[time + 4..time + 8].try_into().unwrap()))
+    }
+}
+
+// reads input_event structs off a byte stream, realigning on event boundaries
+// when the stream gets corrupted instead of misparsing everything after it
+pub struct EventReader<R> {
+    inner: R,
+    layout: EventLayout,
+    buffer: [u8; MAX_EVENT_SIZE],
+    filled: usize,
+}
+
+impl<R: Read> EventReader<R> {
+    pub fn new(inner: R, layout: EventLayout) -> Self {
+        Self { inner, layout, buffer: [0; MAX_EVENT_SIZE], filled: 0 }
+    }
+
+    // None once the stream ends; a read error discards the event it cut short
+    pub fn next_event(&mut self) -> io::Result<Option<Event>> {
+        let mut skipped = 0;
+        loop {
+            let size = self.layout.size();
+            while self.filled < size {
+                match self.inner.read(&mut self.buffer[self.filled..size]) {
+                    Ok(0) => return Ok(None),
+                    Ok(n) => self.filled += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                    Err(e) => {
+                        self.filled = 0;
+                        return Err(e);
+                    },
+                }
+            }
+            let decoded = decode(self.layout, &self.buffer[..size]);
+            if !matches!(decoded, Decoded::Misaligned) && skipped > 0 {
+                eprintln!("warning: input resynchronized after discarding {skipped} bytes");
+                skipped = 0;
+            }
+            match decoded {
+                Decoded::Event(event) => {
+                    self.filled = 0;
+                    return Ok(Some(event));
+                },
+                // on a boundary all the same, so the whole event goes
+                Decoded::Unknown { type_, code } => {
+                    eprintln!("warning: skipping an input event of unknown type {type_} code {code}");
+                    self.filled = 0;
+                },
+                // slide the window by a byte and try again
+                Decoded::Misaligned => {
+                    self.buffer.copy_within(1..size, 0);
+                    self.filled -= 1;
+                    skipped += 1;
+                },
+            }
+        }
+    }
+}
+
+enum Decoded {
+    Event(Event),
+    // a known type with a code input_linux has no name for
+    Unknown { type_: u16, code: u16 },
+    // the timestamp or type can't be one, so the window isn't on an event boundary
+    Misaligned,
+}
+
+fn decode(layout: EventLayout, bytes: &[u8]) -> Decoded {
+    let (seconds, micros, type_, code, value) = layout.fields(bytes);
+    if !(0..1_000_000).contains(&micros) {
+        return Decoded::Misaligned;
+    }
+    let raw = sys::input_event {
+        time: sys::timeval { tv_sec: seconds as _, tv_usec: micros as _ },
+        type_,
+        code,
+        value,
+    };
+    let Ok(event) = InputEvent::from_raw(&raw) else { return Decoded::Misaligned };
+    match Event::new(*event) {
+        Ok(event) => Decoded::Event(event),
+        Err(_) => Decoded::Unknown { type_, code },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use input_linux::{AbsoluteAxis, EventTime};
+
+    fn encode(layout: EventLayout, seconds: i64, micros: i64, axis: u16, value: i32) -> Vec<u8> {
+        let mut bytes = match layout {
+            EventLayout::Time32 => [(seconds as i32).to_ne_bytes(), (micros as i32).to_ne_bytes()].concat(),
+            EventLayout::Time64 => [seconds.to_ne_bytes(), micros.to_ne_bytes()].concat(),
+        };
+        bytes.extend_from_slice(&3u16.to_ne_bytes());
+        bytes.extend_from_slice(&axis.to_ne_bytes());
+        bytes.extend_from_slice(&value.to_ne_bytes());
+        bytes
+    }
+
+    fn events(stream: &[u8], layout: EventLayout) -> Vec<(EventTime, AbsoluteAxis, i32)> {
+        let mut reader = EventReader::new(stream, layout);
+        let mut events = Vec::new();
+        while let Some(event) = reader.next_event().unwrap() {
+            let Event::Absolute(event) = event else { panic!("{event:?}") };
+            events.push((event.time, event.axis, event.value));
+        }
+        events
+    }
+
+    #[test]
+    fn both_time_layouts_read_back() {
+        for layout in [EventLayout::Time32, EventLayout::Time64] {
+            let stream = [encode(layout, 5, 250, 0, -1200), encode(layout, 6, 999_999, 1, 300)].concat();
+            assert_eq!(stream.len(), 2 * layout.size());
+            assert_eq!(events(&stream, layout), [
+                (EventTime::new(5, 250), AbsoluteAxis::X, -1200),
+                (EventTime::new(6, 999_999), AbsoluteAxis::Y, 300),
+            ]);
+        }
+    }
+
+    #[test]
+    fn corrupted_input_resynchronizes_on_the_next_event() {
+        let layout = EventLayout::Time64;
+        let stream = [vec![0xff; 3], encode(layout, 5, 250, 0, 42), encode(layout, 6, 0, 1, 43)].concat();
+        assert_eq!(events(&stream, layout), [
+            (EventTime::new(5, 250), AbsoluteAxis::X, 42),
+            (EventTime::new(6, 0), AbsoluteAxis::Y, 43),
+        ]);
+    }
+
+    #[test]
+    fn an_unknown_event_is_skipped_whole() {
+        let layout = EventLayout::Time64;
+        let stream = [encode(layout, 5, 250, 0x40, 42), encode(layout, 6, 0, 1, 43)].concat();
+        assert_eq!(events(&stream, layout), [(EventTime::new(6, 0), AbsoluteAxis::Y, 43)]);
+    }
+
+    // hands out `first`, fails once, then hands out the rest
+    struct Failing<'a> {
+        first: &'a [u8],
+        failed: bool,
+        rest: &'a [u8],
+    }
+
+    impl Read for Failing<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if !self.first.is_empty() {
+                return self.first.read(buf);
+            }
+            if !self.failed {
+                self.failed = true;
+                return Err(io::ErrorKind::Other.into());
+            }
+            self.rest.read(buf)
+        }
+    }
+
+    #[test]
+    fn a_read_error_discards_the_event_it_cut_short() {
+        let layout = EventLayout::Time64;
+        let (cut, whole) = (encode(layout, 5, 250, 0, 42), encode(layout, 6, 0, 1, 43));
+        let mut reader = EventReader::new(Failing { first: &cut[..10], failed: false, rest: &whole }, layout);
+        assert!(reader.next_event().is_err());
+        let Some(Event::Absolute(event)) = reader.next_event().unwrap() else { panic!() };
+        assert_eq!((event.time, event.axis, event.value), (EventTime::new(6, 0), AbsoluteAxis::Y, 43));
+        assert!(reader.next_event().unwrap().is_none());
+    }
+
+    #[test]
+    fn a_partial_event_at_the_end_is_the_end_of_input() {
+        let layout = EventLayout::Time64;
+        let stream = encode(layout, 5, 250, 0, 42);
+        assert_eq!(events(&stream[..20], layout), []);
+        // short reads are put together into whole events
+        let mut reader = EventReader::new(io::Read::chain(&stream[..7], &stream[7..]), layout);
+        assert!(reader.next_event().unwrap().is_some());
+        assert!(reader.next_event().unwrap().is_none());
+    }
+}
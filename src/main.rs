@@ -1,11 +1,43 @@
+mod calibration;
+mod config;
+mod config_file;
+mod control;
+mod curve;
+mod device;
+mod expect;
+mod ff;
+mod input;
+mod notify;
+mod output;
+mod rotate;
+mod shm;
+mod trace;
+mod virtual_device;
+
+use calibration::{CalibrationStore, DriftLog};
+use curve::{AccelLimiter, AdaptiveFilter};
+use expect::{Expectation, ExpectationWriter};
+use ff::SteeringRumble;
+use config::{Config, GripReference, GyroMode, MidBand, RumbleSource, SkewAction, SteeringMode, TimeSource};
+use control::ControlCommand;
+use input::EventReader;
+use notify::{CenterIndicator, StateNotifier};
+use output::{Output, OutputHandle, OutputQueue, TokenBucket, explain_write_error};
+use rotate::RotatingFile;
+use shm::{SharedState, Snapshot};
+use trace::{DebugField, DebugStyle, FrameHistory};
+use virtual_device::DeviceWriter;
 use input_linux::*;
 use std::{
-    convert::From,
     default::Default,
-    io::{self, Read, Write},
-    mem,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    mem::ManuallyDrop,
+    os::fd::FromRawFd,
+    panic,
     ops::Deref,
-    sync::{Arc, RwLock},
+    path::Path,
+    sync::{Arc, Mutex, OnceLock, RwLock, atomic::{AtomicBool, Ordering}},
     time::{Duration, Instant, UNIX_EPOCH},
     thread,
 };
@@ -17,18 +49,47 @@ struct Frame {
     state: State,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnalysisResult {
+    pub angle: Option<f64>,
+    pub magnitude: f64,
+    // before --saturation shaped it
+    pub raw_magnitude: f64,
+    pub grip_threshold: f64,
+    pub state: State,
+}
+
 impl Frame {
-    pub fn analyze(&self) -> (f64,f64,State) {
-        let x = self.x as f64;
-        let y = self.y as f64;
-        let mag = (x.powi(2) + y.powi(2)).sqrt()/MAX_MAGNITUDE;
-        (y.atan2(x), mag, if mag > GRIP_THRESHOLD { State::Gripped } else { State::Freewheel })
+    // both axes brought to the common MAX_MAGNITUDE scale
+    pub fn scaled(&self, cfg: &Config) -> (f64, f64) {
+        (self.x as f64 * MAX_MAGNITUDE/cfg.max_magnitude_x, self.y as f64 * MAX_MAGNITUDE/cfg.max_magnitude_y)
+    }
+
+    pub fn analyze(&self, cfg: &Config) -> AnalysisResult {
+        let (x, y) = self.scaled(cfg);
+        let (x, y) = (x - cfg.center_offset.0, y - cfg.center_offset.1);
+        let angle = y.atan2(x);
+        let max_magnitude = cfg.gate.as_ref().map_or(MAX_MAGNITUDE, |gate| gate.max_magnitude(angle));
+        let raw_magnitude = (x.powi(2) + y.powi(2)).sqrt()/max_magnitude;
+        let magnitude = cfg.shape_magnitude(raw_magnitude);
+        let grip_threshold = cfg.grip_thresholds.as_ref().map_or(GRIP_THRESHOLD, |table| table.at(angle));
+        // outside --steering-sector the stick is left to other uses and never grips
+        let grip_magnitude = cfg.grip_magnitude(raw_magnitude);
+        let state = if cfg.in_steering_sector(angle) { State::from_magnitude(grip_magnitude, grip_threshold) } else { State::Freewheel };
+        AnalysisResult {
+            // no meaningful direction inside the deadzone, so don't hand out a reference angle
+            angle: (raw_magnitude > cfg.deadzone).then_some(angle),
+            magnitude,
+            raw_magnitude,
+            grip_threshold,
+            state,
+        }
     }
 
-    pub fn resolve(&mut self) -> (f64, f64) {
-        let (a,m,s) = self.analyze();
-        self.state = s;
-        (a,m)
+    pub fn resolve(&mut self, cfg: &Config) -> AnalysisResult {
+        let result = self.analyze(cfg);
+        self.state = result.state;
+        result
     }
 }
 
@@ -47,17 +108,27 @@ struct ProcessedFrame {
     inner: Frame,
     analog_angle: Option<f64>,
     analog_magnitude: f64,
+    raw_magnitude: f64,
+    grip_threshold: f64,
 }
 
-impl From<Frame> for ProcessedFrame {
-    fn from(mut value: Frame) -> Self {
-        let (a,m) = value.resolve();
+impl ProcessedFrame {
+    pub fn new(mut value: Frame, cfg: &Config) -> Self {
+        let result = value.resolve(cfg);
         Self {
             inner: value,
-            analog_angle: Some(a),
-            analog_magnitude: m,
+            analog_angle: result.angle,
+            analog_magnitude: result.magnitude,
+            raw_magnitude: result.raw_magnitude,
+            grip_threshold: result.grip_threshold,
         }
     }
+
+    // decide the grip from a different (e.g. filtered) magnitude than the frame's own
+    pub fn regrip(&mut self, grip_magnitude: f64, cfg: &Config) {
+        let in_sector = self.analog_angle.is_none_or(|angle| cfg.in_steering_sector(angle));
+        self.inner.state = if in_sector { State::from_magnitude(grip_magnitude, self.grip_threshold) } else { State::Freewheel };
+    }
 }
 
 impl Deref for ProcessedFrame {
@@ -68,166 +139,1148 @@ impl Deref for ProcessedFrame {
 }
 
 impl ProcessedFrame {
-    pub fn dbg_string(&self) -> String {
-        format!("x: {: >8.6}, y: {: >8.6}, analog_angle: {}, analog_magnitude: {: >8.6}, state: {:?}",
-                self.inner.x, self.inner.y,
-                self.analog_angle.map_or(" Undef. ".to_string(), |opt| format!("{: >8.6}", opt.to_degrees())),
-                self.analog_magnitude, self.state,
-        )
+    pub fn dbg_string(&self, cfg: &Config, wheel_angle: f64, axis_value: i32) -> String {
+        let fields = cfg.debug_fields.iter().map(|field| match field {
+            DebugField::X => format!("x: {: >8.6}", self.inner.x),
+            DebugField::Y => format!("y: {: >8.6}", self.inner.y),
+            DebugField::Angle => format!("analog_angle: {}",
+                                         self.analog_angle.map_or(" Undef. ".to_string(), |opt| format!("{: >8.6}", opt.to_degrees()))),
+            DebugField::Magnitude => format!("analog_magnitude: {: >8.6}", self.analog_magnitude),
+            DebugField::State => format!("state: {:?}", self.state),
+            DebugField::Wheel => format!("wheel_angle: {: >8.6} aka {:>5}", wheel_angle.to_degrees(), axis_value),
+        });
+        match cfg.debug_format {
+            DebugStyle::Multiline => fields.map(|field| field + "\n").collect(),
+            DebugStyle::Compact | DebugStyle::Off => fields.collect::<Vec<_>>().join(", "),
+        }
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
-enum State {
+pub enum State {
     Freewheel,
     Gripped,
 }
 
+impl State {
+    pub fn from_magnitude(magnitude: f64, threshold: f64) -> Self {
+        if magnitude > threshold { State::Gripped } else { State::Freewheel }
+    }
+}
+
+// defaults, see Config for the runtime values
 // symmetrical, 5/4 ratio comes from the 900deg sweep, 450deg to each side
 //const STEERING_STOP: f64 = std::f64::consts::TAU * 5.0/4.0;
 const STEERING_STOP: f64 = std::f64::consts::TAU * 3.0;
 const MAX_MAGNITUDE: f64 = 32767.0;
+// time between the reports of --startup-sweep
+const SWEEP_STEP: Duration = Duration::from_millis(10);
+// below this the wheel counts as centered and auto-centering stops
+const REST_ANGLE: f64 = 0.0005;
+// smaller wheel angles are numerical residue, see renormalize
+const ANGLE_EPSILON: f64 = 1e-12;
 const GRIP_THRESHOLD: f64 = 0.92;
+// how long the stick has to rest before --drift-correction learns from it
+const DRIFT_REST: Duration = Duration::from_millis(500);
+const HYSTERESIS_SETTLE: Duration = Duration::from_millis(100);
+const INERTIA_REST_VELOCITY: f64 = 0.05;
+const INERTIA_MAX_VELOCITY: f64 = std::f64::consts::TAU * 2.0;
+
+// output side glide from the previously shown angle to the newest gripped
+// target, spread over one input period so slow controllers come out smooth
+#[derive(Clone, Copy)]
+struct Interpolation {
+    from: f64,
+    to: f64,
+    started: Instant,
+    span: Duration,
+}
+
+impl Interpolation {
+    fn at(&self, now: Instant) -> f64 {
+        lerp(self.from, self.to, now.duration_since(self.started).as_secs_f64()/self.span.as_secs_f64())
+    }
+
+    fn done(&self) -> bool {
+        self.started.elapsed() >= self.span
+    }
+}
+
+// wheel dynamics carried from one frame to the next
+#[derive(Clone, Copy, Debug, Default)]
+struct Motion {
+    // angular velocity, tracked while gripped and spent coasting after
+    // release when inertia is enabled
+    velocity: f64,
+    // seconds since the current grip started
+    grip_time: f64,
+    // stick rotation of the last gripped frame
+    last_delta: f64,
+    // sign of the last nonzero gripped rotation, and seconds since it flipped
+    direction: f64,
+    since_reversal: Option<f64>,
+}
 
 #[derive(Clone)]
 struct Data {
+    config: Config,
     prev: ProcessedFrame,
     cur: Frame,
+    speed: f64,
+    // normalized deflection of the fine adjustment axis
+    fine: f64,
+    // latest --gyro-axis reading in radians per second
+    gyro_rate: f64,
+    // --hold-button is down, the wheel doesn't ease back to center
+    position_held: bool,
     wheel_angle: f64,
+    motion: Motion,
     last_wheel_report: Instant,
+    last_calibration_print: Instant,
+    calibration_changed: bool,
+    calibration_started: Instant,
+    notifier: Option<Arc<Mutex<StateNotifier>>>,
+    shared_state: Option<Arc<Mutex<SharedState>>>,
+    expectation: Option<Arc<Mutex<Expectation>>>,
+    started: Instant,
+    axis_updates: AxisUpdates,
+    // every stick axis that reported at least once since startup
+    axes_seen: AxisUpdates,
+    // stick axis values as reported, with the input time they came in at
+    x_sample: AxisSample,
+    y_sample: AxisSample,
+    // --axis-skew already warned about skewed reports
+    skew_warned: bool,
+    // the fine adjustment was already clamped at lock once
+    offset_clamped: bool,
+    output: OutputHandle,
+    last_input: Instant,
+    paused: bool,
+    rate_limit: Option<TokenBucket>,
+    pending_report: Option<i32>,
+    last_axis_value: Option<i32>,
+    // true axis value held back by --output-hysteresis and when it last changed
+    held_report: Option<(i32, Instant)>,
+    center_indicator: Option<CenterIndicator>,
+    // low passed stick magnitude for --grip-smoothing
+    grip_magnitude: f64,
+    // the last stick angle outside the deadzone, for --grip-angle-fallback
+    last_angle: Option<f64>,
+    stick_filter: AdaptiveFilter,
+    drift: DriftLog,
+    // output side wheel angle under --max-steer-accel
+    limiter: AccelLimiter,
+    interpolation: Option<Interpolation>,
+    // smoothed time between input reports, and whether the current tick came from one
+    input_period: Duration,
+    last_input_report: Instant,
+    from_input: bool,
+    last_interpolated_report: Instant,
+    // the final centered report went out, nothing left for the timer to do
+    at_rest: bool,
+    // the wheel angle a synthesized tick left unchanged, not ticked again until fresh input
+    stalled_at: Option<f64>,
+    last_written: Instant,
+    // output level chosen under --levels
+    level: Option<usize>,
+    history: Option<FrameHistory>,
+    // freewheel rotation not yet turned into a --freewheel-scroll step
+    scroll: f64,
+    // when the stick last came to rest, for --drift-correction
+    resting_since: Option<Instant>,
+    // a held back half frame let go of the grip since the last tick
+    released_between: bool,
+    // axis value of the last report that went out, and whether events were
+    // emitted after its SYN_REPORT, for --sync-on-change
+    written_value: Option<i32>,
+    unsynced: bool,
+    steering_rumble: Option<Arc<Mutex<SteeringRumble>>>,
+    // a paced_output thread is running, see start_pacer
+    pacing: bool,
+}
+
+impl Data {
+    // everything at its startup state, with the optional outputs left off
+    fn new(config: Config, output: OutputHandle) -> Self {
+        let rate_limit = rate_limiter(&config);
+        let center_indicator = config.center_indicator.clone().map(CenterIndicator::new);
+        Self {
+            config,
+            notifier: None,
+            shared_state: None,
+            expectation: None,
+            output,
+            started: Instant::now(),
+            last_input: Instant::now(),
+            paused: false,
+            axis_updates: Default::default(),
+            axes_seen: Default::default(),
+            x_sample: Default::default(),
+            y_sample: Default::default(),
+            skew_warned: false,
+            offset_clamped: false,
+            last_wheel_report: Instant::now(),
+            last_calibration_print: Instant::now(),
+            calibration_changed: false,
+            calibration_started: Instant::now(),
+            wheel_angle: 0.0,
+            motion: Default::default(),
+            // freewheeling with no reference angle, so a stick that's already gripped
+            // on the first frame only sets the reference and the wheel doesn't jump
+            prev: Default::default(),
+            cur: Default::default(),
+            speed: 0.0,
+            fine: 0.0,
+            gyro_rate: 0.0,
+            position_held: false,
+            rate_limit,
+            pending_report: None,
+            last_axis_value: None,
+            held_report: None,
+            center_indicator,
+            grip_magnitude: 0.0,
+            last_angle: None,
+            stick_filter: Default::default(),
+            drift: Default::default(),
+            limiter: Default::default(),
+            interpolation: None,
+            input_period: Duration::from_millis(16),
+            last_input_report: Instant::now(),
+            from_input: false,
+            last_interpolated_report: Instant::now(),
+            at_rest: true,
+            stalled_at: None,
+            last_written: Instant::now(),
+            level: None,
+            history: None,
+            scroll: 0.0,
+            resting_since: None,
+            released_between: false,
+            written_value: None,
+            unsynced: false,
+            steering_rumble: None,
+            pacing: false,
+        }
+    }
+
+    // a whole new config, e.g. another profile. what was learned under the old
+    // one is saved first, and what startup derives from the config is derived anew
+    fn reconfigure(&mut self, config: Config) {
+        save_calibration(self);
+        self.rate_limit = rate_limiter(&config);
+        self.stick_filter = Default::default();
+        self.level = None;
+        self.interpolation = None;
+        self.config = config;
+        self.wheel_angle = self.wheel_angle.clamp(-self.config.steering_stop, self.config.steering_stop);
+        self.limiter.reset(self.wheel_angle);
+    }
+
+    // how hard the timer's ticks pull the wheel back to center, 0 leaves it where it is
+    fn centering(&self) -> f64 {
+        // a pushed wheel sits wherever the stick puts it and a gyro driven one
+        // wherever the rotation took it, neither has anything to ease
+        let replaced = self.config.gyro_axis.is_some() && self.config.gyro_mode == GyroMode::Replace;
+        if self.position_held || self.config.mode == SteeringMode::Push || replaced {
+            return 0.0;
+        }
+        centering_strength(&self.config, self.speed)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        if let Err(e) = self.output.write_all(bytes) {
+            output_failed(e);
+        }
+        self.unsynced = true;
+    }
+
+    // a SYN_REPORT passed through from the input, dropped under --sync-on-change
+    // when nothing went out since the last one
+    fn emit_sync(&mut self, event: &SynchronizeEvent) {
+        if self.config.sync_on_change && !self.unsynced {
+            return;
+        }
+        self.emit(event.as_event().as_bytes());
+        self.unsynced = false;
+    }
+
+    fn flush_output(&self) {
+        if let Err(e) = self.output.flush() {
+            output_failed(e);
+        }
+    }
+
+    // the fine axis rides on top of the wheel model, so centering doesn't fight it
+    fn output_value(&mut self) -> i32 {
+        let wheel_angle = if self.config.max_steer_accel.is_some() {
+            self.limiter.position
+        } else if let Some(interpolation) = &self.interpolation {
+            interpolation.at(Instant::now())
+        } else {
+            self.wheel_angle
+        };
+        let holding = self.config.calibrate && self.config.hold_while_calibrating;
+        let fine = if holding { 0.0 } else { self.fine };
+        let stop = self.config.steering_stop;
+        let offset_angle = wheel_angle + fine * self.config.fine_range;
+        // offsets on top of a wheel already at lock would take the output past it
+        if offset_angle.abs() > stop && !self.offset_clamped {
+            eprintln!("warning: the fine adjustment took the wheel past full lock, holding it at lock; not warning again");
+            self.offset_clamped = true;
+        }
+        let (axis_value, level) = axis_value_for(&self.config, offset_angle.clamp(-stop, stop), self.level);
+        self.level = level;
+        axis_value
+    }
+
+    fn center(&mut self) {
+        self.wheel_angle = 0.0;
+        self.motion = Default::default();
+        self.limiter.reset(0.0);
+        self.interpolation = None;
+        self.level = None;
+        let axis_value = quantize_wheel_angle(&self.config, 0.0);
+        self.last_axis_value = Some(axis_value);
+        // paced_output sends it on its own schedule
+        if self.config.output_rate.is_none() {
+            write_output_event(self, axis_value, synthesized_time(&self.config));
+            self.flush_output();
+        }
+        self.held_report = None;
+        self.at_rest = true;
+    }
+
+    // once easing has run out, drop the leftover fraction and report the resting position once
+    fn settle(&mut self) {
+        self.wheel_angle = 0.0;
+        self.motion = Default::default();
+        self.limiter.reset(0.0);
+        self.interpolation = None;
+        self.level = None;
+        let axis_value = self.output_value();
+        emit_report(self, axis_value, synthesized_time(&self.config));
+        self.flush_output();
+        self.at_rest = true;
+        self.last_wheel_report = Instant::now();
+    }
+}
+
+// a stick axis' latest value and the one before it, with their input times in microseconds
+#[derive(Clone, Copy, Default)]
+struct AxisSample {
+    value: i32,
+    time: Option<i64>,
+    previous: Option<(i32, i64)>,
+}
+
+impl AxisSample {
+    fn record(&mut self, value: i32, time: EventTime) {
+        if let Some(last) = self.time {
+            self.previous = Some((self.value, last));
+        }
+        self.value = value;
+        self.time = Some(time.seconds() * 1_000_000 + time.microseconds());
+    }
+
+    // evdev leaves out values that didn't change, so an axis at rest can go
+    // quiet for any length of time without being behind
+    fn moving(&self) -> bool {
+        self.previous.is_some_and(|(value, _)| value != self.value)
+    }
+
+    // carried along the slope between the last two samples, at most one sample
+    // interval ahead so an axis that stopped doesn't overshoot
+    fn at(&self, time: i64) -> f64 {
+        match (self.time, self.previous) {
+            (Some(last), Some((value, before))) if last > before => {
+                let slope = (self.value - value) as f64 / (last - before) as f64;
+                self.value as f64 + slope * (time - last).min(last - before) as f64
+            },
+            _ => self.value as f64,
+        }
+    }
+}
+
+// which stick axes changed since the last processed frame
+#[derive(Clone, Default)]
+struct AxisUpdates {
+    x: bool,
+    y: bool,
+    since: Option<Instant>,
+}
+
+impl AxisUpdates {
+    fn mark(&mut self, axis: AbsoluteAxis) {
+        match axis {
+            AbsoluteAxis::X => self.x = true,
+            AbsoluteAxis::Y => self.y = true,
+            _ => return,
+        }
+        self.since.get_or_insert_with(Instant::now);
+    }
+
+    fn both(&self) -> bool {
+        self.x && self.y
+    }
+
+    fn complete(&self, timeout: Duration) -> bool {
+        match self.since {
+            None => true,
+            Some(since) => self.both() || since.elapsed() >= timeout,
+        }
+    }
 }
 
 fn main() {
+    let config = Config::from_args();
+    if config.print_layout {
+        print!("{}", config.description());
+        return;
+    }
+    if let Some(path) = &config.diagnose {
+        match device::Capabilities::probe(path) {
+            Ok(capabilities) => print!("{capabilities}"),
+            Err(e) => {
+                eprintln!("error: couldn't inspect {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    // anchor the event clock before anything gets emitted
+    now_event_time();
+    install_panic_hook(&config);
+    if config.verbose {
+        eprint!("{}", response_summary(&config));
+    }
+    install_signal_handlers();
+    let notifier = config.state_socket.as_ref().map(|path| {
+        match StateNotifier::open(path, config.rotation()) {
+            Ok(notifier) => Arc::new(Mutex::new(notifier)),
+            Err(e) => {
+                eprintln!("error: couldn't open state socket {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        }
+    });
+    let shared_state = config.shm.as_ref().map(|name| {
+        match SharedState::create(name) {
+            Ok(shared) => Arc::new(Mutex::new(shared)),
+            Err(e) => {
+                eprintln!("error: couldn't create shared memory segment {name}: {e}");
+                std::process::exit(1);
+            }
+        }
+    });
+    let mut output = if config.virtual_device {
+        Output::new(DeviceWriter(create_virtual_device(&config)), config.keep_running)
+    } else {
+        Output::stdout(config.keep_running)
+    };
+    if let Some(delay) = config.batch_delay {
+        output = output.batched(config.batch_size, delay);
+    }
+    if let Some(path) = &config.tee
+        && let Err(e) = output.mirror_to_file(path, config.rotation()) {
+        eprintln!("error: couldn't open output mirror {}: {e}", path.display());
+        std::process::exit(1);
+    }
+    let expectation = config.expect.as_ref().map(|path| match Expectation::load(path) {
+        Ok(expectation) => Arc::new(Mutex::new(expectation)),
+        Err(e) => {
+            eprintln!("error: couldn't read expected output {}: {e}", path.display());
+            std::process::exit(1);
+        },
+    });
+    if let Some(expectation) = &expectation {
+        output.mirror("--expect".to_string(), ExpectationWriter(expectation.clone()));
+    }
+    let output = match config.output_queue {
+        Some(capacity) => OutputHandle::Queued(OutputQueue::spawn(output, capacity, config.backpressure)),
+        None => OutputHandle::Direct(Arc::new(Mutex::new(output))),
+    };
+    let history = (!config.debug_triggers.is_empty()).then(|| {
+        let sink = config.debug_log.as_ref().map(|path| match RotatingFile::create(path, config.rotation()) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("error: couldn't open debug log {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        });
+        FrameHistory::new(sink)
+    });
+    let steering_rumble = config.steering_rumble.and(config.ff_device.as_ref()).map(|path| {
+        match SteeringRumble::open(path) {
+            Ok(rumble) => Arc::new(Mutex::new(rumble)),
+            Err(e) => {
+                eprintln!("error: couldn't open force feedback device {}: {e}", path.display());
+                std::process::exit(1);
+            }
+        }
+    });
     let data = Arc::new(RwLock::new(Data {
-        last_wheel_report: Instant::now(),
-        wheel_angle: 0.0,
-        prev: Default::default(),
-        cur: Default::default(),
+        notifier,
+        shared_state,
+        expectation,
+        history,
+        steering_rumble,
+        ..Data::new(config, output)
     }));
 
-    let tick =
-        |state: &mut Data, event: SynchronizeEvent| {
-            if event.kind == SynchronizeKind::Report {
-                let processed = ProcessedFrame::from(state.cur);
-                state.wheel_angle = wheel_behaviour(state.wheel_angle, &processed, &state.prev, state.last_wheel_report.elapsed().as_secs_f64());
-                let axis_val = quantize_wheel_angle(state.wheel_angle);
-                write_output_event(axis_val, event.time);
-                eprintln!("{}, wheel_angle: {: >8.6} aka {:>5}   ",
-                          processed.dbg_string(),
-                          state.wheel_angle.to_degrees(),
-                          axis_val);
-                Some(processed)
-            } else {
-                io::stdout().write_all(event.as_event().as_bytes()).unwrap();
-                None
-            }
-        };
+    {
+        let mut state = data.write().unwrap();
+        if state.config.initial_report {
+            let (axis_val, time) = (quantize_wheel_angle(&state.config, 0.0), synthesized_time(&state.config));
+            write_output_event(&mut state, axis_val, time);
+            state.flush_output();
+        }
+        if let Some(speed) = state.config.startup_sweep {
+            startup_sweep(&mut state, speed);
+        }
+    }
 
     {
         let data_handle = data.clone();
-        let tick = tick.clone();
-        thread::spawn(move || {
-            loop {
-                let state = data_handle.read().unwrap().clone();
-                if state.wheel_angle.abs() > 0.0005 && state.prev.state == State::Freewheel {
-                    let delta = Instant::now().duration_since(state.last_wheel_report);
-                    if delta > Duration::from_millis(4) {
-                        let unix_time = UNIX_EPOCH.elapsed().unwrap();
-                        let timestamp = EventTime::new(unix_time.as_secs() as i64, unix_time.subsec_micros() as i64);
-                        let mut state = data_handle.write().unwrap();
-                        tick(&mut state, SynchronizeEvent::report(timestamp));
-                        state.last_wheel_report = Instant::now();
-                    }
-                }
-                thread::sleep(Duration::from_millis(10));
-            }
+        thread::spawn(move || loop {
+            timer_tick(&data_handle);
+            start_pacer(&data_handle);
+            thread::sleep(Duration::from_millis(10));
         })
     };
 
+    start_pacer(&data);
+
+    let watchdog = data.read().unwrap().config.writer_watchdog;
+    if let (Some(timeout), OutputHandle::Queued(queue)) = (watchdog, data.read().unwrap().output.clone()) {
+        watch_writer(data.clone(), queue, timeout);
+    }
+
+    if let Some(path) = data.read().unwrap().config.control.clone() {
+        let data_handle = data.clone();
+        control::listen(path, move |command| apply_control(&mut data_handle.write().unwrap(), command));
+    }
+
+    let mut reader = EventReader::new(io::stdin().lock(), data.read().unwrap().config.input_layout);
     loop {
-        #[derive(Debug)]
-        enum LoopError {
-            EventCreation(RangeError),
-            Other(String),
-        }
-        let input =
-            read_input_event(&mut std::io::stdin())
-            .map_err(|e| match e {
-                e => LoopError::Other(e.to_string())
-            })
-            .and_then(|x| { Event::new(x).map_err(|e| LoopError::EventCreation(e)) });
-
-        match input {
-            Ok(event) => {
+        match reader.next_event() {
+            Ok(Some(event)) => {
                 let mut state = data.write().unwrap();
-                match event {
+                state.last_input = Instant::now();
+                match restamp(&state.config, event) {
                     Event::Absolute(event) => {
                         match event.axis {
-                            AbsoluteAxis::X => { state.cur.x = event.value; true },
-                            AbsoluteAxis::Y => { state.cur.y = event.value; true },
+                            AbsoluteAxis::X | AbsoluteAxis::Y => {
+                                record_stick(&mut state, event.axis, event.value, event.time);
+                                true
+                            },
+                            axis if Some(axis) == state.config.fine_axis => {
+                                let fine = (event.value as f64/MAX_MAGNITUDE).clamp(-1.0, 1.0);
+                                state.fine = if fine.abs() > state.config.deadzone { fine } else { 0.0 };
+                                state.emit(event.as_event().as_bytes());
+                                false
+                            },
+                            // angular rate, not something the game should see
+                            axis if Some(axis) == state.config.gyro_axis => {
+                                let rate = (event.value as f64 / state.config.gyro_resolution).to_radians();
+                                state.gyro_rate = if state.config.invert_gyro { -rate } else { rate };
+                                false
+                            },
+                            axis if Some(axis) == state.config.speed_axis => {
+                                state.speed = (event.value as f64 / state.config.speed_axis_max).clamp(0.0, 1.0);
+                                state.emit(event.as_event().as_bytes());
+                                false
+                            },
                             _ => {
-                                io::stdout().write_all(event.as_event().as_bytes()).unwrap();
+                                state.emit(event.as_event().as_bytes());
                                 false
                             },
                         }
                     },
+                    Event::Key(event) if Some(event.key) == state.config.hold_button => {
+                        state.position_held = event.value != KeyState::RELEASED;
+                        false
+                    },
+                    Event::Key(event) if let Some(command) = state.config.bindings.iter()
+                        .find(|(button, _)| *button == event.key)
+                        .map(|(_, command)| command.clone()) => {
+                        if event.value == KeyState::PRESSED {
+                            apply_control(&mut state, command);
+                        }
+                        false
+                    },
                     Event::Synchronize(event) => {
-                        if let Some(processed) = tick(&mut state, event) {
-                            state.prev = processed;
-                            state.last_wheel_report = Instant::now();
+                        if state.config.coalesce_axes && !state.axis_updates.complete(state.config.coalesce_timeout) {
+                            // half a stick update, hold the frame until the other axis arrives
+                            state.emit_sync(&event);
+                            if state.config.split_regrip && state.cur.analyze(&state.config).state == State::Freewheel {
+                                state.released_between = true;
+                            }
+                        } else {
+                            let period = state.last_input_report.elapsed().min(state.config.max_dt);
+                            state.input_period = state.input_period.mul_f64(0.8) + period.mul_f64(0.2);
+                            state.last_input_report = Instant::now();
+                            state.from_input = true;
+                            state.stalled_at = None;
+                            if let Some(limit) = state.config.axis_skew {
+                                check_axis_skew(&mut state, limit);
+                            }
+                            if let Some(processed) = tick(&mut state, event) {
+                                state.prev = processed;
+                                state.last_wheel_report = Instant::now();
+                                state.axis_updates = Default::default();
+                            }
+                            state.from_input = false;
                         }
-                        let sepoch = UNIX_EPOCH.elapsed().unwrap();
-                        let skew = sepoch.saturating_sub(
-                            Duration::from_secs(event.time.seconds() as u64).saturating_add(Duration::from_micros(event.time.microseconds() as u64))
-                        );
-
-                        eprintln!("skew is {:?}", skew);
                         true
                     },
                     _ => {
-                        io::stdout().write_all(event.as_event().as_bytes()).unwrap();
+                        state.emit(event.as_event().as_bytes());
                         false
                     }
                 };
             },
-            Err(e) => {
-                match e {
-                    LoopError::Other(oe) => {
-                        eprintln!("error: {oe}");
-                        // flush garbage out
-                        let mut buf: [u8; 256] = [0; 256];
-                        let _ = io::stdin().read(&mut buf);
-                    }
-                    LoopError::EventCreation(ce) => {
-                        eprintln!("value range error: {ce}")
-                    }
+            Ok(None) => {
+                eprintln!("input closed, centering and exiting");
+                shutdown(&mut data.write().unwrap());
+            },
+            // only the event the error cut short is lost, the next one reads fine
+            Err(e) => eprintln!("warning: reading input failed, discarding the partial event: {e}"),
+        }
+        data.read().unwrap().flush_output();
+    }
+}
+
+// one round of the timer thread: whatever is due without fresh input,
+// from auto-centering to the exit conditions
+fn timer_tick(data: &RwLock<Data>) {
+    let state = data.read().unwrap().clone();
+    let auto_center = &state.config.auto_center;
+    let since_report = state.last_wheel_report.elapsed();
+    // the output may still be catching up with a wheel that already stopped
+    let lagging = state.config.max_steer_accel.is_some()
+        && (state.limiter.position - state.wheel_angle).abs() > auto_center.min_angle
+        && since_report >= auto_center.interval;
+    if lagging || auto_center.triggers(state.wheel_angle, state.prev.state, since_report, state.centering(), state.stalled_at) {
+        let mut state = data.write().unwrap();
+        let time = synthesized_time(&state.config);
+        let before = state.wheel_angle;
+        tick(&mut state, SynchronizeEvent::report(time));
+        state.last_wheel_report = Instant::now();
+        state.stalled_at = (state.wheel_angle == before).then_some(before);
+    } else if !state.at_rest && state.wheel_angle.abs() <= auto_center.min_angle
+        && state.prev.state == State::Freewheel
+        && state.last_wheel_report.elapsed() >= state.config.center_settle {
+        data.write().unwrap().settle();
+    }
+    if let Some(rate) = state.config.interpolate
+        && let Some(interpolation) = state.interpolation
+        && state.last_interpolated_report.elapsed().as_secs_f64() >= rate.recip() {
+        let mut state = data.write().unwrap();
+        if interpolation.done() {
+            state.interpolation = None;
+        }
+        let (axis_val, time) = (state.output_value(), synthesized_time(&state.config));
+        emit_report(&mut state, axis_val, time);
+        state.flush_output();
+        state.last_interpolated_report = Instant::now();
+    }
+    if let Some((axis_val, since)) = state.held_report
+        && since.elapsed() >= HYSTERESIS_SETTLE {
+        let mut state = data.write().unwrap();
+        let time = synthesized_time(&state.config);
+        emit_report(&mut state, axis_val, time);
+        state.flush_output();
+    }
+    if state.pending_report.is_some() {
+        let mut state = data.write().unwrap();
+        if let Some(axis_val) = state.pending_report
+            && state.rate_limit.as_mut().is_none_or(|bucket| bucket.try_take()) {
+            state.pending_report = None;
+            let time = synthesized_time(&state.config);
+            write_output_event(&mut state, axis_val, time);
+            state.flush_output();
+        }
+    }
+    // the value already out, not a newer one held back by hysteresis or the rate limit
+    if let Some(interval) = state.config.heartbeat_interval()
+        && state.last_written.elapsed() >= interval {
+        let mut state = data.write().unwrap();
+        let axis_val = state.last_axis_value.unwrap_or(quantize_wheel_angle(&state.config, 0.0));
+        let time = synthesized_time(&state.config);
+        write_output_event(&mut state, axis_val, time);
+        state.flush_output();
+    }
+    // pushes out batches that sat for --batch-delay without a newer report
+    if state.config.batch_delay.is_some() {
+        data.read().unwrap().flush_output();
+    }
+    let stalled = state.config.stall_timeout.is_some_and(|timeout| state.last_input.elapsed() >= timeout);
+    if stalled && state.prev.state == State::Gripped && state.wheel_angle.abs() > state.config.auto_center.min_angle {
+        eprintln!("warning: no input for {:?} while gripped off-center, centering the wheel", state.last_input.elapsed());
+        let mut state = data.write().unwrap();
+        state.center();
+        state.prev = Default::default();
+        state.last_wheel_report = Instant::now();
+    }
+    if state.config.calibrate
+        && state.config.calibrate_duration.is_some_and(|duration| state.calibration_started.elapsed() >= duration) {
+        finish_calibration(&mut data.write().unwrap());
+    }
+    if TERMINATED.load(Ordering::SeqCst) {
+        eprintln!("terminated, centering and exiting");
+        shutdown(&mut data.write().unwrap());
+    }
+    if state.config.max_runtime.is_some_and(|limit| state.started.elapsed() >= limit) {
+        eprintln!("maximum run time reached, centering and exiting");
+        shutdown(&mut data.write().unwrap());
+    }
+}
+
+// one frame through the wheel model and out; the processed frame for input
+// reports, None for other SYN events which just pass through
+fn tick(state: &mut Data, event: SynchronizeEvent) -> Option<ProcessedFrame> {
+    if event.kind == SynchronizeKind::Report {
+        if state.config.calibrate {
+            calibrate_gate(state);
+        }
+        // an axis that never reported reads as 0, which would point the stick along the other one
+        let mut frame = if state.config.require_both_axes && !state.axes_seen.both() { Frame::default() } else { state.cur };
+        let d_t = state.last_wheel_report.elapsed().min(state.config.max_dt).as_secs_f64();
+        if let Some(curve) = state.config.stick_smoothing {
+            let (x, y) = state.stick_filter.step(&curve, (frame.x as f64, frame.y as f64), MAX_MAGNITUDE, d_t);
+            (frame.x, frame.y) = (x.round() as i32, y.round() as i32);
+        }
+        let mut processed = ProcessedFrame::new(frame, &state.config);
+        if let Some(time_constant) = state.config.grip_smoothing {
+            let grip_magnitude = state.config.grip_magnitude(processed.raw_magnitude);
+            state.grip_magnitude = lerp(state.grip_magnitude, grip_magnitude, 1.0 - (-d_t/time_constant).exp());
+            processed.regrip(state.grip_magnitude, &state.config);
+        }
+        // a filtered magnitude can hold the grip while the raw stick sweeps through the deadzone
+        match processed.analog_angle {
+            Some(angle) => state.last_angle = Some(angle),
+            None if state.config.grip_angle_fallback && processed.state == State::Gripped => {
+                processed.analog_angle = state.last_angle;
+            },
+            None => (),
+        }
+        if let Some(time_constant) = state.config.drift_correction {
+            track_center(state, &frame, processed.analog_angle.is_none(), d_t, time_constant);
+        }
+        // inside the deadzone the stick should sit still at center
+        if state.config.drift_log && processed.analog_angle.is_none() {
+            let t = state.started.elapsed().as_secs_f64();
+            state.drift.observe(t, processed.raw_magnitude);
+        }
+        let previous_angle = state.wheel_angle;
+        let holding = state.config.calibrate && state.config.hold_while_calibrating;
+        // the endpoints alone would count the rotation made while let go
+        let mut prev = state.prev.clone();
+        if std::mem::take(&mut state.released_between) {
+            prev.inner.state = State::Freewheel;
+        }
+        if state.config.reanchor_on_grip && prev.state == State::Freewheel && processed.state == State::Gripped {
+            state.wheel_angle = snap_to_axis_grid(&state.config, state.wheel_angle);
+        }
+        if !state.paused && !holding {
+            let mut motion = state.motion;
+            let centering = state.centering();
+            state.wheel_angle = match (state.config.gyro_axis, state.config.gyro_mode) {
+                (Some(_), GyroMode::Replace) => integrate_gyro(&state.config, state.wheel_angle, state.gyro_rate, d_t),
+                (Some(_), GyroMode::Fuse) => {
+                    let steered = wheel_behaviour(&state.config, state.wheel_angle, &mut motion, &processed, &prev, centering, d_t);
+                    integrate_gyro(&state.config, steered, state.gyro_rate, d_t)
+                },
+                (None, _) => wheel_behaviour(&state.config, state.wheel_angle, &mut motion, &processed, &prev, centering, d_t),
+            };
+            state.motion = motion;
+            state.wheel_angle = renormalize(&state.config, state.wheel_angle);
+        }
+        // smooths out a noisy release without slowing down the gripped wheel
+        if let Some(time_constant) = state.config.release_smoothing
+            && processed.state == State::Freewheel {
+            state.wheel_angle = lerp(previous_angle, state.wheel_angle, 1.0 - (-d_t/time_constant).exp());
+        }
+        if state.config.interpolate.is_some() {
+            let now = Instant::now();
+            let shown = state.interpolation.map_or(previous_angle, |interpolation| interpolation.at(now));
+            state.interpolation = (state.from_input && processed.state == State::Gripped).then_some(Interpolation {
+                from: shown,
+                to: state.wheel_angle,
+                started: now,
+                span: state.input_period,
+            });
+        }
+        if let Some(max_accel) = state.config.max_steer_accel {
+            let wheel_angle = state.wheel_angle;
+            state.limiter.step(wheel_angle, max_accel, d_t);
+        }
+        if state.wheel_angle.abs() > state.config.auto_center.min_angle {
+            state.at_rest = false;
+        }
+        let axis_val = state.output_value();
+        let centered = in_center_band(&state.config, state.wheel_angle);
+        if state.center_indicator.as_mut().is_some_and(|indicator| !indicator.update(centered)) {
+            state.center_indicator = None;
+        }
+        if let Some(notifier) = &state.notifier {
+            notifier.lock().unwrap().update(processed.state, event.time, state.wheel_angle);
+        }
+        if let Some(shared) = &state.shared_state {
+            let snapshot = Snapshot {
+                time: event.time,
+                state: processed.state,
+                x: processed.x,
+                y: processed.y,
+                angle: processed.analog_angle,
+                magnitude: processed.analog_magnitude,
+                wheel_angle: state.wheel_angle,
+            };
+            shared.lock().unwrap().publish(&snapshot);
+        }
+        if let Some(step) = state.config.freewheel_scroll {
+            freewheel_scroll(state, &processed, step, event.time);
+        }
+        report_axis(state, axis_val, event);
+        if let Some(source) = state.config.steering_rumble {
+            rumble_with_steering(state, source, &processed, state.wheel_angle - previous_angle, d_t);
+        }
+        if state.config.log_transitions && processed.state != state.prev.state {
+            log_transition(state, &processed, event.time);
+        }
+        let line = processed.dbg_string(&state.config, state.wheel_angle, axis_val);
+        if state.config.debug_format != DebugStyle::Off {
+            eprintln!("{line}");
+        }
+        if state.history.is_some() {
+            debug_trigger(state, line, state.wheel_angle - previous_angle, processed.state != state.prev.state);
+        }
+        Some(processed)
+    } else {
+        state.emit_sync(&event);
+        None
+    }
+}
+
+// controllers that send x and y in separate reports can leave one axis well
+// behind the other, and the angle between a fresh and a stale value points
+// nowhere the stick ever was
+fn check_axis_skew(state: &mut Data, limit: Duration) {
+    let (Some(x_time), Some(y_time)) = (state.x_sample.time, state.y_sample.time) else { return };
+    let skew = x_time.abs_diff(y_time);
+    let stale = if x_time < y_time { &state.x_sample } else { &state.y_sample };
+    if skew <= limit.as_micros() as u64 || !stale.moving() {
+        return;
+    }
+    // a controller that does it once tends to keep doing it, once is enough to know
+    if !state.skew_warned {
+        let stale = if x_time < y_time { "x" } else { "y" };
+        eprintln!("warning: stick axes updated {:.1}ms apart, past --axis-skew; {stale} is behind, not warning again", skew as f64 / 1000.0);
+        state.skew_warned = true;
+    }
+    if state.config.axis_skew_action == SkewAction::Interpolate {
+        let cfg = &state.config;
+        if x_time < y_time {
+            state.cur.x = clamp_input(cfg, state.x_sample.at(y_time).round() as i32, cfg.max_magnitude_x);
+        } else {
+            state.cur.y = clamp_input(cfg, state.y_sample.at(x_time).round() as i32, cfg.max_magnitude_y);
+        }
+    }
+}
+
+// stray values past the axis' full deflection would read as an overlong stick
+fn clamp_input(cfg: &Config, value: i32, max_magnitude: f64) -> i32 {
+    if cfg.clamp_input {
+        let limit = max_magnitude as i32;
+        value.clamp(-limit, limit)
+    } else {
+        value
+    }
+}
+
+fn calibrate_gate(state: &mut Data) {
+    let (x, y) = state.cur.scaled(&state.config);
+    let Some(gate) = state.config.gate.as_mut() else { return };
+    state.calibration_changed |= gate.observe(y.atan2(x), (x.powi(2) + y.powi(2)).sqrt());
+    if state.last_calibration_print.elapsed() > Duration::from_secs(1) {
+        let values = gate.values();
+        let smallest = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let largest = values.iter().copied().fold(0.0, f64::max);
+        let remaining = state.config.calibrate_duration
+            .map_or(String::new(), |duration| format!(", {}s left", duration.saturating_sub(state.calibration_started.elapsed()).as_secs()));
+        eprintln!("calibration: sector maxima range {smallest:.0} to {largest:.0}{remaining}, roll the stick around the gate");
+        if state.calibration_changed {
+            eprintln!("calibration: --gate-table {}", gate.to_arg());
+        }
+        state.last_calibration_print = Instant::now();
+        state.calibration_changed = false;
+    }
+}
+
+// a stick resting inside the deadzone for a while slowly becomes the new
+// center, so drift developing over a session doesn't eat into the deadzone
+fn track_center(state: &mut Data, frame: &Frame, resting: bool, d_t: f64, time_constant: f64) {
+    if !resting {
+        state.resting_since = None;
+        return;
+    }
+    let since = *state.resting_since.get_or_insert_with(Instant::now);
+    if since.elapsed() < DRIFT_REST {
+        return;
+    }
+    let (x, y) = frame.scaled(&state.config);
+    let limit = state.config.drift_limit * MAX_MAGNITUDE;
+    let t = 1.0 - (-d_t/time_constant).exp();
+    let (ox, oy) = state.config.center_offset;
+    state.config.center_offset = (lerp(ox, x, t).clamp(-limit, limit), lerp(oy, y, t).clamp(-limit, limit));
+}
+
+fn start_calibration(state: &mut Data) {
+    state.config.start_calibration();
+    state.calibration_started = Instant::now();
+    if state.config.hold_while_calibrating {
+        state.center();
+    }
+}
+
+fn finish_calibration(state: &mut Data) {
+    if !state.config.calibrate {
+        return;
+    }
+    state.config.calibrate = false;
+    if let Some(gate) = &state.config.gate {
+        eprintln!("calibration finished: --gate-table {}", gate.to_arg());
+    }
+    save_calibration(state);
+}
+
+fn rate_limiter(cfg: &Config) -> Option<TokenBucket> {
+    cfg.rate_limit.map(|rate| TokenBucket::new(cfg.rate_burst, rate))
+}
+
+// the drift estimate goes along as a comment, it's measured rather than set
+fn save_calibration(state: &Data) {
+    let Some(dir) = &state.config.calibration_dir else { return };
+    let store = CalibrationStore::new(dir);
+    let profile = state.config.profile.as_deref();
+    let mut text = state.config.export_calibration();
+    if let (Some(mean), Some(rate)) = (state.drift.mean_offset(), state.drift.rate()) {
+        text += &format!("# resting stick offset {:.2}% of full deflection, changing by {:+.3}%/min\n", mean * 100.0, rate * 60.0 * 100.0);
+    }
+    match store.save(profile, &text) {
+        Ok(()) => eprintln!("calibration saved to {}", store.path(profile).display()),
+        Err(e) => eprintln!("error: couldn't save calibration to {}: {e}", store.path(profile).display()),
+    }
+}
+
+fn apply_control(state: &mut Data, command: ControlCommand) {
+    let mut set = |key: &str, value: &str| {
+        let mut config = state.config.clone();
+        match config.set(key, value).and_then(|_| config.validate()) {
+            Ok(()) => {
+                // the token bucket is built from these, a new rate or burst needs a new one
+                if (config.rate_limit, config.rate_burst) != (state.config.rate_limit, state.config.rate_burst) {
+                    state.rate_limit = rate_limiter(&config);
                 }
-            }
+                state.config = config;
+            },
+            Err(e) => eprintln!("error: {e}"),
         }
-        io::stdout().flush().unwrap();
+    };
+    match command {
+        ControlCommand::SetRange(degrees) => set("steering-range", &degrees),
+        // the flag alone would leave calibration without a gate table to fill in
+        ControlCommand::Set(key, value) if key == "calibrate" => {
+            let mut config = state.config.clone();
+            match config.set(&key, &value) {
+                Ok(()) if config.calibrate => start_calibration(state),
+                Ok(()) => finish_calibration(state),
+                Err(e) => eprintln!("error: {e}"),
+            }
+        },
+        ControlCommand::Set(key, value) => set(&key, &value),
+        ControlCommand::Center => state.center(),
+        ControlCommand::Calibrate => start_calibration(state),
+        ControlCommand::FinishCalibration => finish_calibration(state),
+        ControlCommand::ExportProfile(path) => export_profile(&state.config, path.as_ref()),
+        ControlCommand::Pause => state.paused = true,
+        ControlCommand::Resume => state.paused = false,
+        // the wheel stays where it is, held inside the new profile's range
+        ControlCommand::NextProfile => match state.config.next_profile() {
+            Ok(config) => {
+                state.reconfigure(config);
+                eprintln!("switched to profile {}", state.config.profile.as_deref().unwrap_or_default());
+            },
+            Err(e) => eprintln!("error: {e}"),
+        },
+    }
+}
+
+fn export_profile(cfg: &Config, path: &Path) {
+    match fs::write(path, cfg.export()) {
+        Ok(()) => eprintln!("profile exported to {}", path.display()),
+        Err(e) => eprintln!("error: couldn't export profile to {}: {e}", path.display()),
+    }
+}
+
+// a raw stick axis report into the frame being put together
+fn record_stick(state: &mut Data, axis: AbsoluteAxis, value: i32, time: EventTime) {
+    if axis == AbsoluteAxis::X {
+        let x = if state.config.invert_x { value.saturating_neg() } else { value };
+        state.cur.x = clamp_input(&state.config, x, state.config.max_magnitude_x);
+        state.x_sample.record(state.cur.x, time);
+    } else {
+        let y = if state.config.invert_y { value.saturating_neg() } else { value };
+        state.cur.y = clamp_input(&state.config, y, state.config.max_magnitude_y);
+        state.y_sample.record(state.cur.y, time);
     }
+    state.axis_updates.mark(axis);
+    state.axes_seen.mark(axis);
 }
 
 fn lerp(from: f64, to: f64, t: f64) -> f64 {
     let t = t.clamp(0.0,1.0);
-    return (1.0-t)*from + t*to;
+    (1.0-t)*from + t*to
 }
 
-fn wheel_behaviour(cur_wheel_angle: f64, cur: &ProcessedFrame, prev: &ProcessedFrame, d_t: f64) -> f64 {
-    let easing = || { lerp(cur_wheel_angle, 0.0, ((std::f64::consts::TAU/4.0)*d_t).clamp(0.0,0.2)) };
-    cur.analog_angle.map(|aangle| {
+// with a speed axis bound, the wheel holds its position while slow and
+// centers harder the faster the vehicle goes past the spring threshold
+fn centering_strength(cfg: &Config, speed: f64) -> f64 {
+    match cfg.speed_axis {
+        None => 1.0,
+        Some(_) if speed <= cfg.spring_threshold => 0.0,
+        Some(_) => cfg.spring_gain * (speed - cfg.spring_threshold)/(1.0 - cfg.spring_threshold),
+    }
+}
+
+// `strength` scales the freewheel easing back to center, 0 leaves the wheel where it is
+fn wheel_behaviour(cfg: &Config, cur_wheel_angle: f64, motion: &mut Motion, cur: &ProcessedFrame, prev: &ProcessedFrame, strength: f64, d_t: f64) -> f64 {
+    if cfg.mode == SteeringMode::Push {
+        *motion = Default::default();
+        return push_position(cfg, cur);
+    }
+    let gripped = prev.state == State::Gripped && cur.state == State::Gripped && cur.analog_angle.is_some();
+    if !gripped {
+        motion.grip_time = 0.0;
+        motion.last_delta = 0.0;
+        motion.direction = 0.0;
+        motion.since_reversal = None;
+    }
+    if !gripped && cfg.inertia && motion.velocity.abs() > INERTIA_REST_VELOCITY {
+        let coasted = cur_wheel_angle + motion.velocity * d_t;
+        motion.velocity *= (-cfg.friction * d_t).exp();
+        if coasted.abs() >= cfg.steering_stop {
+            motion.velocity = 0.0;
+        }
+        return coasted.clamp(-cfg.steering_stop, cfg.steering_stop);
+    }
+    // proportional: the return slows down as the wheel nears center
+    let easing = || {
+        lerp(cur_wheel_angle, 0.0, (cfg.centering_gain*d_t*strength).clamp(0.0,(0.2*strength).min(1.0)))
+    };
+    let new_angle = cur.analog_angle.map(|aangle| {
         match (prev.state, cur.state) {
             (State::Gripped, State::Gripped) => {
+                // a grip only counts once held for --min-grip, shorter touches leave the wheel easing
+                motion.grip_time += d_t;
+                let held = motion.grip_time - cfg.min_grip;
+                if held < 0.0 {
+                    return easing();
+                }
+                if cfg.grip_reference == GripReference::Stick && cfg.min_grip > 0.0 && held < d_t {
+                    return stick_reference(cur_wheel_angle, aangle);
+                }
                 let da = prev.analog_angle.map_or(0.0, |p_aangle| {
-                    cyclic_signed_distance(aangle, p_aangle)
+                    unwrap_flick(cfg, aangle, p_aangle, motion.last_delta)
                 });
-                da + cur_wheel_angle
+                motion.last_delta = da;
+                let da = reversal_damped(cfg, motion, da, d_t);
+                let da = match cfg.speed_gain {
+                    Some(gain) if d_t > 0.0 => {
+                        let towards_lock = da * cur_wheel_angle >= 0.0;
+                        da * gain.gain(cur_wheel_angle.abs() / cfg.steering_stop, da.abs() / d_t, towards_lock)
+                    },
+                    _ => da,
+                };
+                // ratchet: unwinding back towards center needs a re-grip
+                let accumulated = if cfg.ratchet && da * cur_wheel_angle < 0.0 {
+                    cur_wheel_angle
+                } else {
+                    da + cur_wheel_angle
+                };
+                // hand over from the easing gradually right after grabbing the stick
+                if held < cfg.grip_blend {
+                    lerp(easing(), accumulated, held/cfg.grip_blend)
+                } else {
+                    accumulated
+                }
             },
+            (State::Freewheel, State::Gripped) if cfg.grip_reference == GripReference::Stick && cfg.min_grip == 0.0 => {
+                stick_reference(cur_wheel_angle, aangle)
+            },
+            (_, State::Freewheel) => mid_band(cfg, cur_wheel_angle, aangle, cur, prev, d_t).unwrap_or_else(easing),
             _ => easing()
         }
-    }).unwrap_or_else(easing).clamp(-STEERING_STOP, STEERING_STOP)
+    // held at the stops every step, so spinning on past lock winds nothing up
+    // and turning back moves the wheel off the stop right away
+    }).unwrap_or_else(easing).clamp(-cfg.steering_stop, cfg.steering_stop);
+    motion.velocity = if gripped && d_t > 0.0 {
+        (cfg.inertia_scale * (new_angle - cur_wheel_angle)/d_t).clamp(-INERTIA_MAX_VELOCITY, INERTIA_MAX_VELOCITY)
+    } else {
+        0.0
+    };
+    new_angle
+}
+
+// rotation of the controller over the last step, held at the steering stops
+fn integrate_gyro(cfg: &Config, wheel_angle: f64, rate: f64, d_t: f64) -> f64 {
+    (wheel_angle + rate * d_t).clamp(-cfg.steering_stop, cfg.steering_stop)
+}
+
+// --mode push: magnitude times the sideways component of the direction,
+// the deadzone reads as center
+fn push_position(cfg: &Config, cur: &ProcessedFrame) -> f64 {
+    cur.analog_angle.map_or(0.0, |aangle| cfg.steering_stop * cur.analog_magnitude.min(1.0) * aangle.cos())
+}
+
+// attenuates the rotation right after a change of direction, fading out over
+// --reversal-window
+fn reversal_damped(cfg: &Config, motion: &mut Motion, da: f64, d_t: f64) -> f64 {
+    if da * motion.direction < 0.0 {
+        motion.since_reversal = Some(0.0);
+    } else if let Some(since) = motion.since_reversal.as_mut() {
+        *since += d_t;
+    }
+    if da != 0.0 {
+        motion.direction = da.signum();
+    }
+    match motion.since_reversal {
+        Some(since) if since < cfg.reversal_window => {
+            da * (1.0 - cfg.reversal_damping * (1.0 - since/cfg.reversal_window))
+        },
+        _ => da,
+    }
+}
+
+// evdev Y grows downwards, so straight up sits at -90 degrees; take the turn nearest the wheel
+fn stick_reference(cur_wheel_angle: f64, aangle: f64) -> f64 {
+    let stick_angle = aangle + std::f64::consts::FRAC_PI_2;
+    cur_wheel_angle + cyclic_signed_distance(stick_angle, cur_wheel_angle)
+}
+
+// stick pushed out of the deadzone but short of a grip
+fn mid_band(cfg: &Config, cur_wheel_angle: f64, aangle: f64, cur: &ProcessedFrame, prev: &ProcessedFrame, d_t: f64) -> Option<f64> {
+    match cfg.mid_band {
+        MidBand::Ignore => None,
+        MidBand::Track => {
+            let da = prev.analog_angle.map_or(0.0, |p_aangle| cyclic_signed_distance(aangle, p_aangle));
+            Some(cur_wheel_angle + cfg.mid_band_gain * da)
+        },
+        MidBand::Fine => Some(cur_wheel_angle + cfg.mid_band_rate * d_t * cur.x as f64/cfg.max_magnitude_x),
+    }
 }
 
+// shortest rotation from b to a, in [-PI, PI)
 fn cyclic_signed_distance(a: f64, b: f64) -> f64 {
     let mut r = a - b;
     const T: f64 = std::f64::consts::TAU;
@@ -243,31 +1296,1855 @@ fn cyclic_signed_distance(a: f64, b: f64) -> f64 {
     r
 }
 
-fn quantize_wheel_angle(angle: f64) -> i32 {
-    const HALF_U16: i32 = u16::MAX as i32/2;
-    HALF_U16 + (HALF_U16 as f64/STEERING_STOP * angle).trunc() as i32
+// rotation from b to a going the positive way round only, in [0, TAU)
+fn cyclic_forward_distance(a: f64, b: f64) -> f64 {
+    (a - b).rem_euclid(std::f64::consts::TAU)
 }
 
-fn read_input_event<T: Read>(handle: &mut T) -> io::Result<InputEvent> {
-    let mut buffer = [0u8; mem::size_of::<InputEvent>()];
-    handle.read_exact(&mut buffer)?;
-    let event = unsafe { mem::transmute(buffer) };
-    return Ok(event)
+// a big jump against the way the stick was already turning most likely
+// wrapped past half a turn, so keep going in the same direction instead
+fn unwrap_flick(cfg: &Config, aangle: f64, p_aangle: f64, last_delta: f64) -> f64 {
+    let da = cyclic_signed_distance(aangle, p_aangle);
+    match cfg.flick_threshold {
+        Some(threshold) if da.abs() > threshold && da * last_delta < 0.0 => if last_delta > 0.0 {
+            cyclic_forward_distance(aangle, p_aangle)
+        } else {
+            -cyclic_forward_distance(p_aangle, aangle)
+        },
+        _ => da,
+    }
 }
 
-fn write_output_event(axis_value: i32, timestamp: EventTime) {
-    let synthesized_event
-        = AbsoluteEvent::new(
-            timestamp,
-            AbsoluteAxis::X,
-            axis_value,
-        );
-    let output: Vec<u8>
-        = [
-            synthesized_event.into_event(),
-            SynchronizeEvent::new(timestamp, SynchronizeKind::Report, 0).into_event(),
-        ].iter()
-         .flat_map(|x| x.into_bytes())
-         .collect();
-    io::stdout().write_all(&output).unwrap();
+fn in_center_band(cfg: &Config, wheel_angle: f64) -> bool {
+    wheel_angle.abs() <= cfg.center_band
+}
+
+fn shape_output(cfg: &Config, wheel_angle: f64) -> f64 {
+    let angle = if cfg.gamma.is_identity() { wheel_angle } else { cfg.gamma.apply(wheel_angle, cfg.steering_stop) };
+    let angle = cfg.response_table.as_ref().map_or(angle, |table| table.apply(angle));
+    let angle = cfg.game_gamma.map_or(angle, |gamma| curve::predistort(angle, cfg.steering_stop, gamma));
+    angle.clamp(-cfg.steering_stop, cfg.steering_stop)
+}
+
+// the output stages from wheel angle to axis value, with the --levels level
+// the wheel was at going in and the one it ends up at
+fn axis_value_for(cfg: &Config, wheel_angle: f64, level: Option<usize>) -> (i32, Option<usize>) {
+    let angle = shape_output(cfg, wheel_angle);
+    let (angle, level) = match cfg.levels {
+        Some(count) => {
+            let stop = cfg.steering_stop;
+            let level = curve::digital_level(angle/stop, count, cfg.level_hysteresis, level);
+            (curve::level_position(level, count) * stop, Some(level))
+        },
+        None => (angle, level),
+    };
+    (snap_center(cfg, quantize_wheel_angle(cfg, angle)), level)
+}
+
+// sample stick inputs run through the wheel model and output stages, so the
+// effect of the range, curve and deadzone settings shows before playing
+fn response_summary(cfg: &Config) -> String {
+    let stop = cfg.steering_stop;
+    let (input, samples): (&str, Vec<(f64, f64)>) = match cfg.mode {
+        SteeringMode::Wheel => ("stick rotation", [0.0, 0.125, 0.25, 0.5, 0.75, 1.0, 1.25].iter()
+            .map(|fraction| ((fraction * stop).to_degrees(), (fraction * stop).clamp(-stop, stop)))
+            .collect()),
+        SteeringMode::Push => ("sideways push", [0.0, 0.1, 0.25, 0.5, 0.75, 1.0].iter()
+            .map(|push| (push * 100.0, if *push > cfg.deadzone { push * stop } else { 0.0 }))
+            .collect()),
+    };
+    let unit = if cfg.mode == SteeringMode::Push { "%" } else { "°" };
+    let mut summary = format!("steering response:\n  {input:>14}  {:>12}  {:>10}\n", "wheel angle", "axis value");
+    for (sample, wheel_angle) in samples {
+        let (axis_value, _) = axis_value_for(cfg, wheel_angle, None);
+        summary += &format!("  {:>13.1}{unit}  {:>11.1}°  {axis_value:>10}\n", sample, wheel_angle.to_degrees());
+    }
+    summary
+}
+
+fn quantize_wheel_angle(cfg: &Config, angle: f64) -> i32 {
+    // each side of center scales on its own, so an off-midpoint center still reaches both extremes
+    let (min, center, max) = cfg.output_range();
+    let span = if angle < 0.0 { center - min } else { max - center };
+    center + (span as f64/cfg.steering_stop * angle).trunc() as i32
+}
+
+// the wheel angle of the whole axis unit it currently comes out as, so a
+// grip continues from exactly what the game has rather than a fraction past it
+fn snap_to_axis_grid(cfg: &Config, angle: f64) -> f64 {
+    let (min, center, max) = cfg.output_range();
+    let span = if angle < 0.0 { center - min } else { max - center };
+    let unit = cfg.steering_stop / span as f64;
+    (angle / unit).trunc() * unit
+}
+
+// the wheel angle is a running sum of per-frame deltas but stays within the
+// steering stops, where an f64 holds about 1e-16 radians of precision, so
+// rounding error over even days of play stays far below one axis unit and
+// needs no correction. what can go wrong is a non-finite delta poisoning the
+// sum for good, and picoradian residue keeping a centered wheel
+// off zero; both are cleared after every step
+fn renormalize(cfg: &Config, angle: f64) -> f64 {
+    if !angle.is_finite() || angle.abs() < ANGLE_EPSILON {
+        0.0
+    } else {
+        angle.clamp(-cfg.steering_stop, cfg.steering_stop)
+    }
+}
+
+fn snap_center(cfg: &Config, axis_value: i32) -> i32 {
+    let center = quantize_wheel_angle(cfg, 0.0);
+    let offset = axis_value - center;
+    if offset.abs() <= cfg.center_snap_width {
+        center + (offset as f64 * (1.0 - cfg.center_snap_strength)).round() as i32
+    } else {
+        axis_value
+    }
+}
+
+// realtime as of the first call, advanced by the monotonic clock from then on,
+// so emitted timestamps never step backwards when the wall clock gets adjusted
+fn now_event_time() -> EventTime {
+    let (realtime, instant) = clock_anchor();
+    let unix_time = *realtime + instant.elapsed();
+    EventTime::new(unix_time.as_secs() as i64, unix_time.subsec_micros() as i64)
+}
+
+fn realtime_event_time() -> EventTime {
+    let unix_time = UNIX_EPOCH.elapsed().unwrap_or_default();
+    EventTime::new(unix_time.as_secs() as i64, unix_time.subsec_micros() as i64)
+}
+
+// timestamp for reports made up without an input event to take it from
+fn synthesized_time(cfg: &Config) -> EventTime {
+    match cfg.time_source {
+        TimeSource::Realtime => realtime_event_time(),
+        TimeSource::Input | TimeSource::Monotonic => now_event_time(),
+    }
+}
+
+// input events, and everything derived from them, carry the configured clock
+fn restamp(cfg: &Config, event: Event) -> Event {
+    let time = match cfg.time_source {
+        TimeSource::Input => return event,
+        TimeSource::Monotonic => now_event_time(),
+        TimeSource::Realtime => realtime_event_time(),
+    };
+    let mut raw = event.into_event();
+    raw.time = time;
+    Event::with_event(raw)
+}
+
+fn clock_anchor() -> &'static (Duration, Instant) {
+    static ANCHOR: OnceLock<(Duration, Instant)> = OnceLock::new();
+    ANCHOR.get_or_init(|| (UNIX_EPOCH.elapsed().unwrap_or_default(), Instant::now()))
+}
+
+// microseconds since startup, wrapping like the kernel's own MSC_TIMESTAMP
+fn msc_timestamp() -> i32 {
+    clock_anchor().1.elapsed().as_micros() as u32 as i32
+}
+
+// leave the game with a centered wheel before going away
+fn shutdown(state: &mut Data) -> ! {
+    state.center();
+    // the pacer doesn't outlive the exit, the centered wheel can't wait for it
+    if let Some(axis_value) = state.last_axis_value.filter(|_| state.config.output_rate.is_some()) {
+        write_output_event(state, axis_value, synthesized_time(&state.config));
+    }
+    if state.config.drift_log {
+        state.drift.report(state.config.drift_warn);
+    }
+    if let Some(path) = &state.config.export_profile {
+        export_profile(&state.config, path);
+    }
+    save_calibration(state);
+    if let Err(e) = state.output.sync() {
+        output_failed(e);
+    }
+    if let Some(expectation) = &state.expectation {
+        expectation.lock().unwrap().finish();
+    }
+    std::process::exit(0)
+}
+
+// there's no one left to center the wheel for
+fn output_failed(e: io::Error) -> ! {
+    eprintln!("error: {}", explain_write_error(&e));
+    std::process::exit(1)
+}
+
+// changes within the hysteresis band only go out once the value stops moving
+fn report_axis(state: &mut Data, axis_value: i32, event: SynchronizeEvent) {
+    match state.last_axis_value {
+        Some(last) if axis_value != last && (axis_value - last).abs() <= state.config.output_hysteresis => {
+            if state.held_report.is_none_or(|(held, _)| held != axis_value) {
+                state.held_report = Some((axis_value, Instant::now()));
+            }
+            state.emit_sync(&event);
+        },
+        _ => emit_report(state, axis_value, event.time),
+    }
+}
+
+// steps go out ahead of the wheel report and share its SYN_REPORT;
+// clockwise scrolls down like a click wheel
+fn freewheel_scroll(state: &mut Data, cur: &ProcessedFrame, step: f64, time: EventTime) {
+    let spinning = cur.state == State::Freewheel && state.prev.state == State::Freewheel;
+    let (Some(angle), Some(prev_angle), true) = (cur.analog_angle, state.prev.analog_angle, spinning) else {
+        state.scroll = 0.0;
+        return;
+    };
+    state.scroll += cyclic_signed_distance(angle, prev_angle);
+    let steps = (state.scroll / step).trunc();
+    if steps != 0.0 {
+        state.scroll -= steps * step;
+        state.emit(RelativeEvent::new(time, state.config.scroll_axis, -steps as i32).into_event().as_bytes());
+    }
+}
+
+fn rumble_with_steering(state: &mut Data, source: RumbleSource, cur: &ProcessedFrame, wheel_delta: f64, d_t: f64) {
+    let Some(rumble) = state.steering_rumble.clone() else { return };
+    let effort = match source {
+        RumbleSource::Magnitude if cur.state == State::Gripped => cur.analog_magnitude,
+        RumbleSource::Magnitude => 0.0,
+        RumbleSource::Speed if d_t > 0.0 => wheel_delta.abs() / d_t / std::f64::consts::TAU,
+        RumbleSource::Speed => 0.0,
+    };
+    let magnitude = curve::rumble_intensity(effort, state.config.rumble_gamma, state.config.rumble_strength);
+    if let Err(e) = rumble.lock().unwrap().set(magnitude) {
+        eprintln!("warning: steering rumble failed, disabling it: {e}");
+        state.steering_rumble = None;
+    }
+}
+
+// a line of its own prefix so grip flicker can be picked out of the rest of stderr
+fn log_transition(state: &Data, cur: &ProcessedFrame, time: EventTime) {
+    // under --grip-smoothing the filtered magnitude is what decided it
+    let magnitude = if state.config.grip_smoothing.is_some() { state.grip_magnitude } else { state.config.grip_magnitude(cur.raw_magnitude) };
+    let angle = cur.analog_angle.map_or("none".to_string(), |angle| format!("{:.1}", angle.to_degrees()));
+    eprintln!("transition: {:?} -> {:?} at {}.{:06}, magnitude {magnitude:.3} against threshold {:.3}, angle {angle}",
+              state.prev.state, cur.state, time.seconds(), time.microseconds(), cur.grip_threshold);
+}
+
+fn debug_trigger(state: &mut Data, line: String, wheel_delta: f64, transition: bool) {
+    let Some(history) = state.history.as_mut() else { return };
+    history.record(line);
+    let Some(trigger) = state.config.debug_triggers.iter().find(|t| t.fires(wheel_delta, transition)) else { return };
+    let summary = format!("wheel_angle: {:.6} delta: {:.6} speed: {:.3} fine: {:.3} motion: {:?} output: {:?} held: {:?} pending: {:?} paused: {}",
+                          state.wheel_angle.to_degrees(), wheel_delta.to_degrees(), state.speed, state.fine, state.motion,
+                          state.last_axis_value, state.held_report.map(|(value, _)| value), state.pending_report, state.paused);
+    history.dump(&format!("{trigger:?}"), &summary);
+}
+
+// rate limited reports keep only the newest value, sent once a token frees up;
+// under --sync-on-change a value that is already out is not sent again
+fn emit_report(state: &mut Data, axis_value: i32, timestamp: EventTime) {
+    state.last_axis_value = Some(axis_value);
+    state.held_report = None;
+    if state.config.output_rate.is_some() {
+        // paced_output sends it on its own schedule
+    } else if state.config.sync_on_change && !state.unsynced && state.written_value == Some(axis_value) {
+        state.pending_report = None;
+    } else if state.rate_limit.as_mut().is_some_and(|bucket| !bucket.try_take()) {
+        state.pending_report = Some(axis_value);
+    } else {
+        state.pending_report = None;
+        write_output_event(state, axis_value, timestamp);
+    }
+}
+
+fn write_output_event(state: &mut Data, axis_value: i32, timestamp: EventTime) {
+    if let Err(e) = state.output.write_report(&report_bytes(&state.config, axis_value, timestamp)) {
+        output_failed(e);
+    }
+    state.last_written = Instant::now();
+    state.written_value = Some(axis_value);
+    state.unsynced = false;
+}
+
+fn report_bytes(cfg: &Config, axis_value: i32, timestamp: EventTime) -> Vec<u8> {
+    let synthesized_event
+        = AbsoluteEvent::new(
+            timestamp,
+            AbsoluteAxis::X,
+            axis_value,
+        );
+    let mut events = vec![synthesized_event.into_event()];
+    if cfg.msc_timestamp {
+        events.push(MiscEvent::new(timestamp, MiscKind::Timestamp, msc_timestamp()).into_event());
+    }
+    events.push(SynchronizeEvent::new(timestamp, SynchronizeKind::Report, 0).into_event());
+    events.iter()
+        .flat_map(|x| x.into_bytes())
+        .collect()
+}
+
+// center, full left, full right and back to center, before anything else
+// touches the output. input meanwhile waits in the pipe
+fn startup_sweep(state: &mut Data, speed: f64) {
+    let stop = state.config.steering_stop;
+    let mut angle = 0.0;
+    for target in [-stop, stop, 0.0] {
+        let mut last_step = Instant::now();
+        // each leg ends on its target exactly, so the extremes aren't stepped over
+        while angle != target {
+            thread::sleep(SWEEP_STEP);
+            let step = last_step.elapsed().as_secs_f64() * speed;
+            last_step = Instant::now();
+            angle = if (target - angle).abs() <= step { target } else { angle + step.copysign(target - angle) };
+            let (axis_val, time) = (quantize_wheel_angle(&state.config, angle), synthesized_time(&state.config));
+            write_output_event(state, axis_val, time);
+            state.flush_output();
+        }
+    }
+    state.last_wheel_report = Instant::now();
+}
+
+// set and next-profile can turn --output-rate on while running, so the timer
+// thread calls this too and starts the pacer whenever one is missing
+fn start_pacer(data: &Arc<RwLock<Data>>) {
+    let idle = |state: &Data| state.pacing || state.config.output_rate.is_none();
+    if idle(&data.read().unwrap()) {
+        return;
+    }
+    let mut state = data.write().unwrap();
+    // checked again under the write lock, the other caller may have won the race
+    if let Some(rate) = state.config.output_rate && !state.pacing {
+        state.pacing = true;
+        paced_output(data.clone(), rate);
+    }
+}
+
+// --output-rate: the latest value on a fixed cadence, repeated while it
+// doesn't change. deadlines advance by whole periods so the rate doesn't
+// drift with the time each write takes
+fn paced_output(data: Arc<RwLock<Data>>, rate: f64) {
+    thread::spawn(move || {
+        let mut deadline = Instant::now() + Duration::from_secs_f64(rate.recip());
+        loop {
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            let mut state = data.write().unwrap();
+            // read anew every period, so a changed rate applies right away and
+            // a config without one ends the pacer before it doubles any report
+            let Some(rate) = state.config.output_rate else {
+                state.pacing = false;
+                return;
+            };
+            if let Some(axis_val) = state.last_axis_value {
+                let time = synthesized_time(&state.config);
+                write_output_event(&mut state, axis_val, time);
+                state.flush_output();
+            }
+            let period = Duration::from_secs_f64(rate.recip());
+            deadline += period;
+            // after a stall, carry on from now rather than bursting to catch up
+            if deadline < Instant::now() {
+                deadline = Instant::now() + period;
+            }
+        }
+    });
+}
+
+// a write stuck in the sink leaves the game looking at a frozen wheel; once
+// writes go through again the wheel is centered instead of jumping to wherever
+// the stick led it in the meantime
+fn watch_writer(data: Arc<RwLock<Data>>, queue: Arc<OutputQueue>, timeout: Duration) {
+    thread::spawn(move || {
+        let mut stalled = false;
+        loop {
+            thread::sleep(timeout / 4);
+            match (queue.busy_for().filter(|busy| *busy >= timeout), stalled) {
+                (Some(busy), false) => {
+                    eprintln!("error: an output write has been stuck for {busy:?}, the game sees a frozen wheel");
+                    stalled = true;
+                },
+                (None, true) => {
+                    eprintln!("output writes go through again, centering the wheel");
+                    stalled = false;
+                    let mut state = data.write().unwrap();
+                    state.center();
+                    state.prev = Default::default();
+                    state.last_wheel_report = Instant::now();
+                },
+                _ => (),
+            }
+        }
+    });
+}
+
+// the --layout device on /dev/uinput, with force feedback requests from the
+// game answered (and with --ff-device played) on a thread of their own
+fn create_virtual_device(cfg: &Config) -> Arc<UInputHandle<File>> {
+    let description = virtual_device::Description::parse(&cfg.description())
+        .expect("built-in layout descriptions parse");
+    let device = match description.create() {
+        Ok(device) => Arc::new(device),
+        Err(e) => {
+            eprintln!("error: couldn't create the virtual device on /dev/uinput: {e}");
+            std::process::exit(1);
+        }
+    };
+    let target = cfg.ff_device.as_ref().map(|path| match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => EvdevHandle::new(file),
+        Err(e) => {
+            eprintln!("error: couldn't open force feedback device {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    });
+    ff::spawn(device.clone(), target);
+    device
+}
+
+// a panicking thread may hold or have poisoned the state lock, so the hook
+// writes a center report prepared up front straight to the output descriptor
+fn install_panic_hook(cfg: &Config) {
+    let cfg = cfg.clone();
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        eprintln!("error: analogstick2wheel crashed, centering the wheel before exiting; please report the message above");
+        // a device of our own disappears along with the process instead
+        if !cfg.virtual_device {
+            emergency_center(&center_report(&cfg));
+        }
+        // a dead timer or writer thread would leave the rest limping along
+        std::process::exit(101);
+    }));
+}
+
+// stamped when it's needed, not when the hook was installed
+fn center_report(cfg: &Config) -> Vec<u8> {
+    report_bytes(cfg, quantize_wheel_angle(cfg, 0.0), synthesized_time(cfg))
+}
+
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_terminate(_: libc::c_int) {
+    TERMINATED.store(true, Ordering::SeqCst);
+}
+
+// SIGINT and SIGTERM only raise a flag, the timer thread then shuts down like
+// when the input closes; nothing else is safe to do inside a signal handler
+fn install_signal_handlers() {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        unsafe { libc::signal(signal, on_terminate as extern "C" fn(libc::c_int) as libc::sighandler_t) };
+    }
+}
+
+// safe to call any number of times, only the first call writes
+fn emergency_center(report: &[u8]) {
+    static CENTERED: AtomicBool = AtomicBool::new(false);
+    if CENTERED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    // borrow fd 1 without locking io::stdout, which the panicking thread may be holding
+    let mut stdout = ManuallyDrop::new(unsafe { File::from_raw_fd(1) });
+    let _ = stdout.write_all(report).and_then(|_| stdout.flush());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{EventLayout, EventReader};
+    use std::{f64::consts::PI, fs, io::Write};
+
+    // what the tests read back of everything emitted
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Capture {
+        // the wheel axis values emitted so far
+        fn axis_values(&self) -> Vec<i32> {
+            let bytes = self.0.lock().unwrap().clone();
+            let mut reader = EventReader::new(bytes.as_slice(), EventLayout::native());
+            let mut values = Vec::new();
+            while let Some(event) = reader.next_event().unwrap() {
+                if let Event::Absolute(event) = event
+                    && event.axis == AbsoluteAxis::X {
+                    values.push(event.value);
+                }
+            }
+            values
+        }
+    }
+
+    fn data_with(settings: &[(&str, &str)]) -> (Data, Capture) {
+        let mut config = Config::default();
+        for (key, value) in settings {
+            config.set(key, value).unwrap();
+        }
+        let capture = Capture::default();
+        let output = OutputHandle::Direct(Arc::new(Mutex::new(Output::new(capture.clone(), false))));
+        (Data::new(config, output), capture)
+    }
+
+    // a stick report coming in, the way the input loop hands it to tick
+    fn report(state: &mut Data, x: i32, y: i32) {
+        (state.cur.x, state.cur.y) = (x, y);
+        state.axes_seen.mark(AbsoluteAxis::X);
+        state.axes_seen.mark(AbsoluteAxis::Y);
+        state.from_input = true;
+        if let Some(processed) = tick(state, SynchronizeEvent::report(now_event_time())) {
+            state.prev = processed;
+            state.last_wheel_report = Instant::now();
+        }
+        state.from_input = false;
+    }
+
+    // the stick at full deflection, in degrees as atan2 of the axes
+    fn report_at(state: &mut Data, degrees: f64) {
+        let angle = degrees.to_radians();
+        report(state, (MAX_MAGNITUDE * angle.cos()).round() as i32, (MAX_MAGNITUDE * angle.sin()).round() as i32);
+    }
+
+    fn frame_at(cfg: &Config, x: i32, y: i32) -> ProcessedFrame {
+        ProcessedFrame::new(Frame { x, y, state: State::Freewheel }, cfg)
+    }
+
+    fn full_deflection(cfg: &Config, degrees: f64) -> ProcessedFrame {
+        let angle = degrees.to_radians();
+        frame_at(cfg, (MAX_MAGNITUDE * angle.cos()).round() as i32, (MAX_MAGNITUDE * angle.sin()).round() as i32)
+    }
+
+    // the stick let go with the wheel half a turn out, one tenth of a second later
+    fn released_at(cfg: &Config, speed: f64) -> f64 {
+        let gripped = ProcessedFrame::new(Frame { x: 0, y: -32767, state: State::Freewheel }, cfg);
+        let released = ProcessedFrame::new(Frame::default(), cfg);
+        wheel_behaviour(cfg, PI, &mut Motion::default(), &released, &gripped, centering_strength(cfg, speed), 0.1)
+    }
+
+    #[test]
+    fn saturation_keeps_the_raw_magnitude_for_drift_and_grip() {
+        let mut cfg = Config::default();
+        cfg.set("deadzone", "0.1").unwrap();
+        cfg.set("saturation", "0.9").unwrap();
+        let resting = Frame { x: (MAX_MAGNITUDE * 0.05) as i32, y: 0, state: State::Freewheel }.analyze(&cfg);
+        assert_eq!(resting.magnitude, 0.0);
+        assert!((resting.raw_magnitude - 0.05).abs() < 1e-4);
+        assert_eq!(resting.angle, None);
+        let near_gate = Frame { x: 0, y: (MAX_MAGNITUDE * 0.91) as i32, state: State::Freewheel }.analyze(&cfg);
+        assert_eq!(near_gate.state, State::Gripped);
+        let halfway = Frame { x: 0, y: (MAX_MAGNITUDE * 0.85) as i32, state: State::Freewheel }.analyze(&cfg);
+        assert_eq!(halfway.state, State::Freewheel);
+    }
+
+    #[test]
+    fn debug_line_formats() {
+        let mut cfg = Config::default();
+        let frame = frame_at(&cfg, 0, -32767);
+        let compact = frame.dbg_string(&cfg, 0.0, 32767);
+        assert_eq!(compact.matches(", ").count(), DebugField::ALL.len() - 1);
+        assert!(compact.contains("state: Gripped"));
+        cfg.set("debug-format", "multiline").unwrap();
+        let multiline = frame.dbg_string(&cfg, 0.0, 32767);
+        assert_eq!(multiline.lines().count(), DebugField::ALL.len());
+        assert!(!multiline.contains(", "));
+    }
+
+    #[test]
+    fn debug_line_fields() {
+        let mut cfg = Config::default();
+        cfg.set("debug-fields", "state,wheel").unwrap();
+        let line = frame_at(&cfg, 0, 0).dbg_string(&cfg, 0.0, 32767);
+        assert_eq!(line, format!("state: Freewheel, wheel_angle: {: >8.6} aka 32767", 0.0));
+        cfg.set("debug-fields", "angle").unwrap();
+        assert_eq!(frame_at(&cfg, 0, 0).dbg_string(&cfg, 0.0, 32767), "analog_angle:  Undef. ");
+    }
+
+    #[test]
+    fn the_same_release_centers_harder_at_speed() {
+        let mut cfg = Config::default();
+        cfg.set("speed-axis", "ABS_GAS").unwrap();
+        assert_eq!(released_at(&cfg, 0.0), PI);
+        assert_eq!(released_at(&cfg, cfg.spring_threshold), PI);
+        let slow = released_at(&cfg, 0.3);
+        let fast = released_at(&cfg, 1.0);
+        assert!(slow < PI, "{slow}");
+        assert!(fast < slow, "{fast} vs {slow}");
+        // without a speed axis the spring doesn't care how fast the vehicle goes
+        let cfg = Config::default();
+        assert_eq!(released_at(&cfg, 0.0), released_at(&cfg, 1.0));
+        assert!(released_at(&cfg, 0.0) < PI);
+    }
+
+    #[test]
+    fn analyze_names_angle_magnitude_and_state() {
+        let cfg = Config::default();
+        let result = Frame { x: 0, y: -32767, state: State::Freewheel }.analyze(&cfg);
+        assert_eq!(result.angle, Some(-std::f64::consts::FRAC_PI_2));
+        assert_eq!(result.magnitude, 1.0);
+        assert_eq!(result.grip_threshold, GRIP_THRESHOLD);
+        assert_eq!(result.state, State::Gripped);
+        let result = Frame { x: 16000, y: 0, state: State::Gripped }.analyze(&cfg);
+        assert_eq!(result.angle, Some(0.0));
+        assert_eq!(result.state, State::Freewheel);
+    }
+
+    #[test]
+    fn the_deadzone_has_no_stick_angle() {
+        let mut cfg = Config::default();
+        cfg.set("deadzone", "0.2").unwrap();
+        let inside = (MAX_MAGNITUDE * 0.19) as i32;
+        let outside = (MAX_MAGNITUDE * 0.21) as i32;
+        assert_eq!(Frame { x: inside, y: 0, state: State::Freewheel }.analyze(&cfg).angle, None);
+        assert_eq!(Frame { x: 0, y: outside, state: State::Freewheel }.analyze(&cfg).angle, Some(std::f64::consts::FRAC_PI_2));
+        assert!(cfg.set("deadzone", "1").is_err());
+    }
+
+    #[test]
+    fn the_gate_table_normalizes_the_magnitude() {
+        let mut cfg = Config::default();
+        cfg.set("gate-table", "20000,30000").unwrap();
+        // two sectors, centered straight down (positive y) and straight up
+        let down = Frame { x: 0, y: 20000, state: State::Freewheel }.analyze(&cfg);
+        assert!((down.raw_magnitude - 1.0).abs() < 1e-9);
+        let up = Frame { x: 0, y: -20000, state: State::Freewheel }.analyze(&cfg);
+        assert!((up.raw_magnitude - 20000.0 / 30000.0).abs() < 1e-9);
+        // halfway between the sector centers the gate is interpolated
+        let right = Frame { x: 25000, y: 0, state: State::Freewheel }.analyze(&cfg);
+        assert!((right.raw_magnitude - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_coalesced_frame_waits_for_both_axes() {
+        let mut updates = AxisUpdates::default();
+        assert!(updates.complete(Duration::from_secs(60)));
+        updates.mark(AbsoluteAxis::RX);
+        assert!(updates.complete(Duration::from_secs(60)));
+        updates.mark(AbsoluteAxis::X);
+        assert!(!updates.complete(Duration::from_secs(60)));
+        // a lone axis goes through once the timeout runs out
+        assert!(updates.complete(Duration::ZERO));
+        updates.mark(AbsoluteAxis::Y);
+        assert!(updates.complete(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn the_steering_range_sets_the_full_scale_angle() {
+        let mut cfg = Config::default();
+        cfg.set("steering-range", "180").unwrap();
+        let (min, center, max) = cfg.output_range();
+        assert_eq!(quantize_wheel_angle(&cfg, 0.0), center);
+        assert_eq!(quantize_wheel_angle(&cfg, 90f64.to_radians()), max);
+        assert_eq!(quantize_wheel_angle(&cfg, -90f64.to_radians()), min);
+        assert_eq!(quantize_wheel_angle(&cfg, 45f64.to_radians()), center + (max - center) / 2);
+        // past the range the output holds at the stop
+        assert_eq!(quantize_wheel_angle(&cfg, shape_output(&cfg, 120f64.to_radians())), max);
+    }
+
+    #[test]
+    fn control_commands_apply_to_the_running_state() {
+        let (mut state, capture) = data_with(&[]);
+        apply_control(&mut state, ControlCommand::SetRange("540".to_string()));
+        assert!((state.config.steering_stop - 270f64.to_radians()).abs() < 1e-12);
+        // an invalid setting leaves the configuration as it was
+        apply_control(&mut state, ControlCommand::Set("deadzone".to_string(), "2".to_string()));
+        assert_eq!(state.config.deadzone, Config::default().deadzone);
+        // a rate limit set while running takes effect like one given at startup
+        apply_control(&mut state, ControlCommand::Set("rate-limit".to_string(), "100".to_string()));
+        assert!(state.rate_limit.is_some());
+        // setting the flag calibrates just like the calibrate command
+        apply_control(&mut state, ControlCommand::Set("calibrate".to_string(), "true".to_string()));
+        assert!(state.config.calibrate && state.config.gate.is_some());
+        apply_control(&mut state, ControlCommand::Set("calibrate".to_string(), "false".to_string()));
+        assert!(!state.config.calibrate);
+        apply_control(&mut state, ControlCommand::Pause);
+        assert!(state.paused);
+        apply_control(&mut state, ControlCommand::Resume);
+        assert!(!state.paused);
+        state.wheel_angle = 1.0;
+        apply_control(&mut state, ControlCommand::Center);
+        assert_eq!(state.wheel_angle, 0.0);
+        assert_eq!(capture.axis_values(), [state.config.output_range().1]);
+    }
+
+    #[test]
+    fn the_center_snap_blends_inside_its_band() {
+        let mut cfg = Config::default();
+        cfg.set("center-snap-width", "100").unwrap();
+        let center = quantize_wheel_angle(&cfg, 0.0);
+        cfg.set("center-snap-strength", "0").unwrap();
+        assert_eq!(snap_center(&cfg, center + 60), center + 60);
+        assert_eq!(snap_center(&cfg, center - 100), center - 100);
+        cfg.set("center-snap-strength", "1").unwrap();
+        assert_eq!(snap_center(&cfg, center + 60), center);
+        assert_eq!(snap_center(&cfg, center - 100), center);
+        cfg.set("center-snap-strength", "0.5").unwrap();
+        assert_eq!(snap_center(&cfg, center + 60), center + 30);
+        // outside the band the output passes through
+        assert_eq!(snap_center(&cfg, center + 101), center + 101);
+        assert!(cfg.set("center-snap-strength", "1.5").is_err());
+    }
+
+    #[test]
+    fn inverted_axes_are_negated_as_they_come_in() {
+        let (mut state, _) = data_with(&[("invert-y", "true")]);
+        record_stick(&mut state, AbsoluteAxis::X, 1200, now_event_time());
+        record_stick(&mut state, AbsoluteAxis::Y, 3400, now_event_time());
+        assert_eq!((state.cur.x, state.cur.y), (1200, -3400));
+        record_stick(&mut state, AbsoluteAxis::Y, i32::MIN, now_event_time());
+        assert_eq!(state.cur.y, i32::MAX);
+        let (mut state, _) = data_with(&[("invert-x", "true")]);
+        record_stick(&mut state, AbsoluteAxis::X, 1200, now_event_time());
+        assert_eq!(state.cur.x, -1200);
+        assert!(state.axes_seen.x && !state.axes_seen.y);
+    }
+
+    #[test]
+    fn a_stall_is_fed_to_the_wheel_model_as_max_dt() {
+        // the same released wheel ten seconds after its last report
+        let after_stall = |max_dt: &str| {
+            let (mut state, _) = data_with(&[("max-dt", max_dt)]);
+            state.wheel_angle = 180f64.to_radians();
+            state.last_wheel_report = Instant::now() - Duration::from_secs(10);
+            report(&mut state, 0, 0);
+            state.wheel_angle
+        };
+        // eased back for 50ms worth, not the full ten seconds
+        let clamped = after_stall("50");
+        let expected = 180f64.to_radians() * (1.0 - Config::default().centering_gain * 0.05);
+        assert!((clamped - expected).abs() < 1e-9, "{}", clamped.to_degrees());
+        assert!(after_stall("20000") < clamped);
+    }
+
+    #[test]
+    fn the_ratchet_only_turns_away_from_center() {
+        let turn = |settings: &[(&str, &str)]| {
+            let (mut state, _) = data_with(settings);
+            for degrees in [0.0, 10.0, 20.0, 30.0] {
+                report_at(&mut state, degrees);
+            }
+            let turned = state.wheel_angle;
+            report_at(&mut state, 20.0);
+            report_at(&mut state, 10.0);
+            (turned, state.wheel_angle)
+        };
+        let (turned, unwound) = turn(&[("ratchet", "true")]);
+        assert!(turned.abs() > 20f64.to_radians(), "{}", turned.to_degrees());
+        assert_eq!(unwound, turned);
+        let (turned, unwound) = turn(&[]);
+        assert!(unwound.abs() < turned.abs());
+    }
+
+    #[test]
+    fn rate_limited_reports_keep_only_the_newest_value() {
+        let (mut state, capture) = data_with(&[("rate-limit", "0.001"), ("rate-burst", "2")]);
+        for value in [100, 200, 300, 400] {
+            emit_report(&mut state, value, now_event_time());
+        }
+        assert_eq!(capture.axis_values(), [100, 200]);
+        assert_eq!(state.pending_report, Some(400));
+    }
+
+    #[test]
+    fn a_fast_release_coasts_and_friction_stops_it() {
+        let mut cfg = Config::default();
+        cfg.set("inertia", "true").unwrap();
+        cfg.set("inertia-scale", "0.5").unwrap();
+        let mut motion = Motion::default();
+        let released = frame_at(&cfg, 0, 0);
+        let (first, second) = (full_deflection(&cfg, 0.0), full_deflection(&cfg, 10.0));
+        let turned = wheel_behaviour(&cfg, 0.0, &mut motion, &second, &first, 1.0, 0.01);
+        let spin = 0.5 * turned / 0.01;
+        assert!((motion.velocity - spin).abs() < 1e-9);
+        let coasted = wheel_behaviour(&cfg, turned, &mut motion, &released, &second, 1.0, 0.01);
+        assert!((coasted - (turned + spin * 0.01)).abs() < 1e-9);
+        assert!((motion.velocity - spin * (-cfg.friction * 0.01).exp()).abs() < 1e-9);
+        // friction winds it down until the easing takes over again
+        let mut wheel_angle = coasted;
+        for _ in 0..200 {
+            if motion.velocity.abs() <= INERTIA_REST_VELOCITY {
+                break;
+            }
+            wheel_angle = wheel_behaviour(&cfg, wheel_angle, &mut motion, &released, &released, 1.0, 0.01);
+        }
+        assert!(motion.velocity.abs() <= INERTIA_REST_VELOCITY);
+        assert!(wheel_angle > coasted);
+        let settled = wheel_angle;
+        assert!(wheel_behaviour(&cfg, settled, &mut motion, &released, &released, 1.0, 0.01) < settled);
+    }
+
+    #[test]
+    fn without_inertia_a_release_eases_back() {
+        let cfg = Config::default();
+        let mut motion = Motion::default();
+        let (first, second) = (full_deflection(&cfg, 0.0), full_deflection(&cfg, 10.0));
+        let turned = wheel_behaviour(&cfg, 0.0, &mut motion, &second, &first, 1.0, 0.01);
+        let released = wheel_behaviour(&cfg, turned, &mut motion, &frame_at(&cfg, 0, 0), &second, 1.0, 0.01);
+        assert!(released < turned);
+    }
+
+    // the stick at half deflection, between the deadzone and the grip
+    fn mid_deflection(cfg: &Config, degrees: f64) -> ProcessedFrame {
+        let angle = degrees.to_radians();
+        let half = MAX_MAGNITUDE / 2.0;
+        frame_at(cfg, (half * angle.cos()).round() as i32, (half * angle.sin()).round() as i32)
+    }
+
+    #[test]
+    fn the_mid_band_ignores_tracks_or_fine_steers() {
+        let behave = |mode: &str| {
+            let mut cfg = Config::default();
+            cfg.set("mid-band", mode).unwrap();
+            let (prev, cur) = (mid_deflection(&cfg, 0.0), mid_deflection(&cfg, 20.0));
+            assert_eq!(cur.state, State::Freewheel);
+            wheel_behaviour(&cfg, 0.5, &mut Motion::default(), &cur, &prev, 1.0, 0.1) - 0.5
+        };
+        let defaults = Config::default();
+        assert!(behave("ignore") < 0.0);
+        assert!((behave("track") - defaults.mid_band_gain * 20f64.to_radians()).abs() < 1e-3);
+        let x = (MAX_MAGNITUDE / 2.0 * 20f64.to_radians().cos()).round();
+        assert!((behave("fine") - defaults.mid_band_rate * 0.1 * x / MAX_MAGNITUDE).abs() < 1e-9);
+        assert!(Config::default().set("mid-band", "grip").is_err());
+    }
+
+    #[test]
+    fn a_new_grip_blends_from_centering_into_following() {
+        let mut cfg = Config::default();
+        cfg.set("grip-blend", "100").unwrap();
+        let mut motion = Motion::default();
+        let (prev, cur) = (full_deflection(&cfg, 0.0), full_deflection(&cfg, 10.0));
+        let eased = lerp(0.5, 0.0, cfg.centering_gain * 0.01);
+        let followed = 0.5 + cur.analog_angle.unwrap() - prev.analog_angle.unwrap();
+        // 10ms into the grip: a tenth following, the rest easing
+        let blended = wheel_behaviour(&cfg, 0.5, &mut motion, &cur, &prev, 1.0, 0.01);
+        assert!((blended - lerp(eased, followed, 0.1)).abs() < 1e-9, "{}", blended.to_degrees());
+        // and only following once the blend is over
+        motion.grip_time = 0.1;
+        let followed_only = wheel_behaviour(&cfg, 0.5, &mut motion, &cur, &prev, 1.0, 0.01);
+        assert!((followed_only - followed).abs() < 1e-9);
+    }
+
+    #[test]
+    fn game_gamma_is_the_last_output_stage() {
+        let mut cfg = Config::default();
+        cfg.set("outer-gamma", "2").unwrap();
+        let shaped = shape_output(&cfg, 0.8 * cfg.steering_stop);
+        cfg.set("game-gamma", "3").unwrap();
+        let predistorted = shape_output(&cfg, 0.8 * cfg.steering_stop);
+        let stop = cfg.steering_stop;
+        assert!(((predistorted / stop).powi(3) * stop - shaped).abs() < 1e-9);
+    }
+
+    #[test]
+    fn an_unwritable_center_indicator_is_dropped() {
+        let (mut state, _) = data_with(&[]);
+        state.center_indicator = Some(CenterIndicator::new("/nonexistent/brightness".into()));
+        report(&mut state, 0, 0);
+        assert!(state.center_indicator.is_none());
+    }
+
+    #[test]
+    fn the_center_indicator_follows_the_wheel() {
+        let path = std::env::temp_dir().join(format!("analogstick2wheel-center-indicator-{}", std::process::id()));
+        let (mut state, _) = data_with(&[]);
+        state.center_indicator = Some(CenterIndicator::new(path.clone()));
+        report(&mut state, 0, 0);
+        let centered = fs::read_to_string(&path).unwrap();
+        state.wheel_angle = 30f64.to_radians();
+        report(&mut state, 0, 0);
+        let turned = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((centered.as_str(), turned.as_str()), ("1\n", "0\n"));
+    }
+
+    #[test]
+    fn grip_smoothing_delays_the_grip_but_not_the_angle() {
+        let (mut state, _) = data_with(&[("grip-smoothing", "100")]);
+        report(&mut state, 0, 0);
+        state.last_wheel_report = Instant::now() - Duration::from_millis(10);
+        report(&mut state, 0, -32767);
+        // a tenth of the time constant in, the filtered magnitude is still far from a grip
+        assert_eq!(state.prev.state, State::Freewheel);
+        assert!(state.grip_magnitude > 0.05 && state.grip_magnitude < 0.2, "{}", state.grip_magnitude);
+        assert_eq!(state.prev.analog_angle, Some(-std::f64::consts::FRAC_PI_2));
+        for _ in 0..30 {
+            state.last_wheel_report = Instant::now() - Duration::from_millis(10);
+            report(&mut state, 0, -32767);
+        }
+        assert_eq!(state.prev.state, State::Gripped);
+    }
+
+    #[test]
+    fn a_wheel_at_rest_settles_with_one_exact_center_report() {
+        let (mut state, capture) = data_with(&[("center-settle", "200")]);
+        state.wheel_angle = 0.0001;
+        state.at_rest = false;
+        let data = RwLock::new(state);
+        timer_tick(&data);
+        // not yet resting for --center-settle
+        assert!(capture.axis_values().is_empty());
+        data.write().unwrap().last_wheel_report = Instant::now() - Duration::from_millis(200);
+        timer_tick(&data);
+        timer_tick(&data);
+        let state = data.read().unwrap();
+        assert!(state.at_rest);
+        assert_eq!(state.wheel_angle, 0.0);
+        assert_eq!(capture.axis_values(), [state.config.output_range().1]);
+    }
+
+    #[test]
+    fn each_axis_scales_by_its_own_full_deflection() {
+        let mut cfg = Config::default();
+        cfg.set("max-magnitude-x", "16000").unwrap();
+        cfg.set("max-magnitude-y", "32000").unwrap();
+        let frame = Frame { x: 16000, y: -32000, state: State::Freewheel };
+        let (x, y) = frame.scaled(&cfg);
+        assert!((x - MAX_MAGNITUDE).abs() < 1e-9 && (y + MAX_MAGNITUDE).abs() < 1e-9);
+        // both at full deflection point halfway between the axes
+        let result = frame.analyze(&cfg);
+        assert!((result.angle.unwrap() + std::f64::consts::FRAC_PI_4).abs() < 1e-9);
+        assert_eq!(result.state, State::Gripped);
+        let result = Frame { x: 16000, y: 0, state: State::Freewheel }.analyze(&cfg);
+        assert!((result.raw_magnitude - 1.0).abs() < 1e-9);
+        assert!(cfg.set("max-magnitude-x", "0").is_err());
+    }
+
+    fn micros(time: EventTime) -> i64 {
+        time.seconds() * 1_000_000 + time.microseconds()
+    }
+
+    #[test]
+    fn emitted_timestamps_never_step_backwards() {
+        let mut last = micros(now_event_time());
+        // anchored to the wall clock
+        assert!((last - micros(realtime_event_time())).abs() < 1_000_000);
+        for _ in 0..1000 {
+            let now = micros(now_event_time());
+            assert!(now >= last);
+            last = now;
+        }
+    }
+
+    #[test]
+    fn a_stick_referenced_grip_lines_the_wheel_up_with_the_stick() {
+        let mut cfg = Config::default();
+        cfg.set("grip-reference", "stick").unwrap();
+        let released = frame_at(&cfg, 0, 0);
+        let grab = |wheel_angle: f64, degrees: f64| {
+            let cur = full_deflection(&cfg, degrees);
+            wheel_behaviour(&cfg, wheel_angle, &mut Motion::default(), &cur, &released, 1.0, 0.01).to_degrees()
+        };
+        // stick right reads as a quarter turn right, stick up as center
+        assert!((grab(0.0, 0.0) - 90.0).abs() < 1e-3);
+        assert!(grab(0.0, -90.0).abs() < 1e-3);
+        // on the turn nearest the wheel
+        assert!((grab(350f64.to_radians(), -90.0) - 360.0).abs() < 1e-3);
+        assert!((grab((-200f64).to_radians(), 90.0) + 180.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn a_flick_past_half_a_turn_keeps_its_direction() {
+        let mut cfg = Config::default();
+        let deg = |degrees: f64| degrees.to_radians();
+        // a jump to -150 while turning positive may have been 210 forwards, without a threshold it goes the short way
+        assert!((unwrap_flick(&cfg, deg(-150.0), 0.0, deg(100.0)) - deg(-150.0)).abs() < 1e-9);
+        cfg.set("flick-threshold", "120").unwrap();
+        assert!((unwrap_flick(&cfg, deg(-150.0), 0.0, deg(100.0)) - deg(210.0)).abs() < 1e-9);
+        assert!((unwrap_flick(&cfg, deg(150.0), 0.0, deg(-100.0)) - deg(-210.0)).abs() < 1e-9);
+        // the same jump in the way it was already turning, or a small one against it, is left alone
+        assert!((unwrap_flick(&cfg, deg(-150.0), 0.0, deg(-100.0)) - deg(-150.0)).abs() < 1e-9);
+        assert!((unwrap_flick(&cfg, deg(-90.0), 0.0, deg(100.0)) - deg(-90.0)).abs() < 1e-9);
+    }
+
+    fn decode(bytes: &[u8]) -> Vec<Event> {
+        let mut reader = EventReader::new(bytes, EventLayout::native());
+        std::iter::from_fn(|| reader.next_event().unwrap()).collect()
+    }
+
+    #[test]
+    fn msc_timestamp_goes_between_the_axis_and_the_sync() {
+        let mut cfg = Config::default();
+        let time = EventTime::new(3, 5);
+        let plain = decode(&report_bytes(&cfg, 1234, time));
+        assert!(matches!(plain.as_slice(), [Event::Absolute(x), Event::Synchronize(_)] if x.value == 1234 && x.time == time));
+        cfg.set("msc-timestamp", "true").unwrap();
+        let before = msc_timestamp();
+        let stamped = decode(&report_bytes(&cfg, 1234, time));
+        let [Event::Absolute(_), Event::Misc(misc), Event::Synchronize(_)] = stamped.as_slice() else { panic!("{stamped:?}") };
+        assert_eq!((misc.kind, misc.time), (MiscKind::Timestamp, time));
+        assert!(misc.value >= before);
+    }
+
+    #[test]
+    fn an_already_gripped_first_frame_only_sets_the_reference() {
+        let (mut state, capture) = data_with(&[]);
+        report_at(&mut state, 90.0);
+        assert_eq!(state.prev.state, State::Gripped);
+        assert_eq!(state.wheel_angle, 0.0);
+        assert_eq!(capture.axis_values(), vec![quantize_wheel_angle(&state.config, 0.0)]);
+        report_at(&mut state, 100.0);
+        assert!((state.wheel_angle.abs() - 10f64.to_radians()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn reversing_past_lock_leaves_the_stop_right_away() {
+        let cfg = Config::default();
+        let mut motion = Motion::default();
+        let (mut wheel_angle, mut prev) = (0.0, full_deflection(&cfg, 0.0));
+        let mut step = |wheel_angle: &mut f64, prev: &mut ProcessedFrame, degrees: f64| {
+            let cur = full_deflection(&cfg, degrees);
+            *wheel_angle = wheel_behaviour(&cfg, *wheel_angle, &mut motion, &cur, prev, 1.0, 0.01);
+            *prev = cur;
+        };
+        // a turn and a half past lock, in either direction of rotation
+        let past_lock = (cfg.steering_stop.to_degrees() + 540.0) as i32;
+        for degrees in (0..=past_lock).step_by(20) {
+            step(&mut wheel_angle, &mut prev, degrees as f64);
+        }
+        let stop = wheel_angle;
+        assert_eq!(stop.abs(), cfg.steering_stop);
+        step(&mut wheel_angle, &mut prev, past_lock as f64 - 20.0);
+        assert!((wheel_angle.abs() - (cfg.steering_stop - 20f64.to_radians())).abs() < 1e-3);
+        assert!(wheel_angle * stop > 0.0);
+    }
+
+    #[test]
+    fn reconfiguring_saves_the_calibration_and_rebuilds_the_rate_limit() {
+        let dir = std::env::temp_dir().join(format!("analogstick2wheel-test-{}", std::process::id()));
+        let (mut state, _) = data_with(&[("calibration-dir", dir.to_str().unwrap()), ("center-offset", "12,-3")]);
+        let mut config = Config::default();
+        config.set("rate-limit", "100").unwrap();
+        config.set("steering-range", "90").unwrap();
+        state.wheel_angle = 3.0;
+        state.reconfigure(config);
+        let saved = fs::read_to_string(dir.join("default.toml")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(saved.contains("center-offset = \"12.0,-3.0\""));
+        assert!(state.rate_limit.is_some());
+        assert_eq!(state.wheel_angle, state.config.steering_stop);
+        assert_eq!(state.limiter.position, state.wheel_angle);
+    }
+
+    #[test]
+    fn a_standing_vehicle_doesnt_center_the_wheel() {
+        let (mut state, _) = data_with(&[("speed-axis", "ABS_GAS"), ("spring-threshold", "0.1")]);
+        assert_eq!(state.centering(), 0.0);
+        state.speed = 0.55;
+        assert!(state.centering() > 0.0);
+        let (state, _) = data_with(&[]);
+        assert_eq!(state.centering(), 1.0);
+    }
+
+    #[test]
+    fn the_hold_button_stops_the_centering() {
+        let (mut state, _) = data_with(&[("hold-button", "BTN_TL")]);
+        state.position_held = true;
+        assert_eq!(state.centering(), 0.0);
+        state.position_held = false;
+        assert_eq!(state.centering(), 1.0);
+    }
+
+    #[test]
+    fn push_mode_leaves_nothing_to_center() {
+        let (state, _) = data_with(&[("mode", "push")]);
+        assert_eq!(state.centering(), 0.0);
+    }
+
+    #[test]
+    fn gyro_replace_leaves_nothing_to_center() {
+        let (state, _) = data_with(&[("gyro-axis", "ABS_RX"), ("gyro-mode", "replace")]);
+        assert_eq!(state.centering(), 0.0);
+        let (state, _) = data_with(&[("gyro-axis", "ABS_RX"), ("gyro-mode", "fuse")]);
+        assert_eq!(state.centering(), 1.0);
+    }
+
+    #[test]
+    fn center_writes_right_away() {
+        let (mut state, capture) = data_with(&[]);
+        state.wheel_angle = 1.0;
+        state.center();
+        assert_eq!(capture.axis_values(), vec![quantize_wheel_angle(&state.config, 0.0)]);
+    }
+
+    #[test]
+    fn the_pacer_starts_and_stops_with_the_output_rate() {
+        let (state, capture) = data_with(&[]);
+        let data = Arc::new(RwLock::new(state));
+        data.write().unwrap().last_axis_value = Some(1234);
+        start_pacer(&data);
+        assert!(!data.read().unwrap().pacing);
+        apply_control(&mut data.write().unwrap(), ControlCommand::Set("output-rate".to_string(), "1000".to_string()));
+        start_pacer(&data);
+        assert!(data.read().unwrap().pacing);
+        while capture.axis_values().is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(capture.axis_values()[0], 1234);
+        // e.g. a next-profile to a profile without one
+        data.write().unwrap().config.output_rate = None;
+        while data.read().unwrap().pacing {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let paced = capture.axis_values().len();
+        thread::sleep(Duration::from_millis(5));
+        assert_eq!(capture.axis_values().len(), paced);
+    }
+
+    #[test]
+    fn center_leaves_the_report_to_the_output_rate() {
+        let (mut state, capture) = data_with(&[("output-rate", "100")]);
+        state.wheel_angle = 1.0;
+        state.center();
+        assert!(capture.axis_values().is_empty());
+        assert_eq!(state.last_axis_value, Some(quantize_wheel_angle(&state.config, 0.0)));
+    }
+
+    #[test]
+    fn the_output_margin_keeps_full_lock_off_the_extremes() {
+        let defaults = Config::default();
+        let mut cfg = Config::default();
+        cfg.set("output-margin", "500").unwrap();
+        let (min, center, max) = defaults.output_range();
+        let stop = cfg.steering_stop;
+        assert_eq!(quantize_wheel_angle(&cfg, stop), max - 500);
+        assert_eq!(quantize_wheel_angle(&cfg, -stop), min + 500);
+        assert_eq!(quantize_wheel_angle(&cfg, 0.0), center);
+        cfg.set("output-margin", "40000").unwrap();
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn small_output_changes_wait_until_they_settle() {
+        let (mut state, capture) = data_with(&[("output-hysteresis", "10")]);
+        let sync = SynchronizeEvent::report(now_event_time());
+        report_axis(&mut state, 1000, sync);
+        report_axis(&mut state, 1008, sync);
+        report_axis(&mut state, 1005, sync);
+        // a big change goes straight out
+        report_axis(&mut state, 1100, sync);
+        report_axis(&mut state, 1104, sync);
+        assert_eq!(capture.axis_values(), [1000, 1100]);
+        let data = RwLock::new(state);
+        timer_tick(&data);
+        assert_eq!(capture.axis_values(), [1000, 1100]);
+        let held = data.read().unwrap().held_report.unwrap().0;
+        data.write().unwrap().held_report = Some((held, Instant::now() - HYSTERESIS_SETTLE));
+        timer_tick(&data);
+        assert_eq!(capture.axis_values(), [1000, 1100, 1104]);
+        assert_eq!(data.read().unwrap().held_report, None);
+    }
+
+    #[test]
+    fn the_crash_report_is_stamped_when_built() {
+        let cfg = Config::default();
+        let first = center_report(&cfg);
+        thread::sleep(Duration::from_millis(2));
+        let second = center_report(&cfg);
+        let capture = Capture(Arc::new(Mutex::new(second.clone())));
+        assert_eq!(capture.axis_values(), vec![quantize_wheel_angle(&cfg, 0.0)]);
+        let time = |report: &[u8]| {
+            let time = EventReader::new(report, EventLayout::native()).next_event().unwrap().unwrap().as_event().time;
+            (time.seconds(), time.microseconds())
+        };
+        assert!(time(&second) > time(&first));
+    }
+
+    #[test]
+    fn the_fine_axis_adds_on_top_of_the_wheel() {
+        let (mut state, _) = data_with(&[("fine-axis", "ABS_RX"), ("fine-range", "20")]);
+        state.wheel_angle = 30f64.to_radians();
+        state.fine = 0.5;
+        assert_eq!(state.output_value(), quantize_wheel_angle(&state.config, 40f64.to_radians()));
+        assert_eq!(state.wheel_angle, 30f64.to_radians());
+        // and held at lock rather than taking the output past it
+        state.wheel_angle = state.config.steering_stop;
+        assert_eq!(state.output_value(), state.config.output_range().2);
+        assert!(state.offset_clamped);
+    }
+
+    #[test]
+    fn clamp_input_holds_the_stick_at_full_deflection() {
+        let (mut state, _) = data_with(&[("clamp-input", "true"), ("max-magnitude-y", "20000")]);
+        record_stick(&mut state, AbsoluteAxis::X, -40000, now_event_time());
+        record_stick(&mut state, AbsoluteAxis::Y, 20500, now_event_time());
+        assert_eq!((state.cur.x, state.cur.y), (-32767, 20000));
+        record_stick(&mut state, AbsoluteAxis::Y, -19000, now_event_time());
+        assert_eq!(state.cur.y, -19000);
+        let (mut state, _) = data_with(&[]);
+        record_stick(&mut state, AbsoluteAxis::X, -40000, now_event_time());
+        assert_eq!(state.cur.x, -40000);
+    }
+
+    #[test]
+    fn the_output_chases_the_wheel_under_max_steer_accel() {
+        let (mut state, capture) = data_with(&[("max-steer-accel", "3600")]);
+        state.wheel_angle = 180f64.to_radians();
+        state.position_held = true;
+        state.config.set("hold-button", "BTN_TL").unwrap();
+        state.last_wheel_report = Instant::now() - Duration::from_millis(10);
+        report(&mut state, 0, 0);
+        let center = state.config.output_range().1;
+        let first = capture.axis_values()[0];
+        assert!(first > center && first < quantize_wheel_angle(&state.config, state.wheel_angle), "{first}");
+        assert_eq!(state.wheel_angle, 180f64.to_radians());
+    }
+
+    #[test]
+    fn calibration_learns_the_gate_until_its_duration_runs_out() {
+        let (mut state, _) = data_with(&[("calibrate-duration", "5")]);
+        apply_control(&mut state, ControlCommand::Calibrate);
+        assert!(state.config.calibrate);
+        report(&mut state, 30000, 0);
+        assert!(state.config.gate.as_ref().unwrap().values().contains(&30000.0));
+        let data = RwLock::new(state);
+        timer_tick(&data);
+        assert!(data.read().unwrap().config.calibrate);
+        data.write().unwrap().calibration_started -= Duration::from_secs(5);
+        timer_tick(&data);
+        let state = data.read().unwrap();
+        assert!(!state.config.calibrate);
+        // the learned gate stays in use
+        assert!(state.config.gate.as_ref().unwrap().values().contains(&30000.0));
+    }
+
+    #[test]
+    fn finish_calibration_ends_it_early() {
+        let (mut state, _) = data_with(&[]);
+        apply_control(&mut state, ControlCommand::FinishCalibration);
+        assert!(!state.config.calibrate);
+        apply_control(&mut state, ControlCommand::Calibrate);
+        report(&mut state, 0, 31000);
+        apply_control(&mut state, ControlCommand::FinishCalibration);
+        assert!(!state.config.calibrate);
+        // no more learning afterwards
+        report(&mut state, 0, 32000);
+        assert!(!state.config.gate.as_ref().unwrap().values().contains(&32000.0));
+    }
+
+    #[test]
+    fn input_events_are_restamped_with_the_chosen_clock() {
+        let mut cfg = Config::default();
+        let recorded = EventTime::new(1000, 5);
+        let event = || Event::Absolute(AbsoluteEvent::new(recorded, AbsoluteAxis::X, 7));
+        cfg.set("event-time", "input").unwrap();
+        assert_eq!(restamp(&cfg, event()).as_event().time, recorded);
+        for clock in ["monotonic", "realtime"] {
+            cfg.set("event-time", clock).unwrap();
+            let restamped = restamp(&cfg, event());
+            assert!((micros(restamped.as_event().time) - micros(realtime_event_time())).abs() < 1_000_000);
+            assert!(matches!(restamped, Event::Absolute(event) if event.value == 7));
+        }
+        assert!(cfg.set("event-time", "gps").is_err());
+    }
+
+    #[test]
+    fn the_stick_is_ignored_until_both_axes_reported() {
+        let (mut state, _) = data_with(&[("require-both-axes", "true")]);
+        record_stick(&mut state, AbsoluteAxis::X, 32767, now_event_time());
+        let tick_once = |state: &mut Data| {
+            if let Some(processed) = tick(state, SynchronizeEvent::report(now_event_time())) {
+                state.prev = processed;
+            }
+        };
+        // X alone would point the stick straight right
+        tick_once(&mut state);
+        assert_eq!(state.prev.analog_angle, None);
+        record_stick(&mut state, AbsoluteAxis::Y, 0, now_event_time());
+        tick_once(&mut state);
+        assert_eq!(state.prev.analog_angle, Some(0.0));
+    }
+
+    #[test]
+    fn a_grip_shorter_than_min_grip_doesnt_turn_the_wheel() {
+        let mut cfg = Config::default();
+        cfg.set("min-grip", "50").unwrap();
+        let mut motion = Motion::default();
+        let mut wheel_angle = 0.0;
+        let mut prev = full_deflection(&cfg, 0.0);
+        for step in 1..=4 {
+            let cur = full_deflection(&cfg, step as f64 * 10.0);
+            wheel_angle = wheel_behaviour(&cfg, wheel_angle, &mut motion, &cur, &prev, 1.0, 0.01);
+            prev = cur;
+        }
+        assert_eq!(wheel_angle, 0.0);
+        // from 50ms on the rotation counts
+        for step in 5..=6 {
+            let cur = full_deflection(&cfg, step as f64 * 10.0);
+            wheel_angle = wheel_behaviour(&cfg, wheel_angle, &mut motion, &cur, &prev, 1.0, 0.01);
+            prev = cur;
+        }
+        assert!((wheel_angle.to_degrees() - 20.0).abs() < 0.1, "{}", wheel_angle.to_degrees());
+    }
+
+    #[test]
+    fn an_interpolation_glides_over_its_span() {
+        let started = Instant::now();
+        let glide = Interpolation { from: 1.0, to: 2.0, started, span: Duration::from_millis(100) };
+        assert_eq!(glide.at(started), 1.0);
+        assert!((glide.at(started + Duration::from_millis(25)) - 1.25).abs() < 1e-9);
+        assert_eq!(glide.at(started + Duration::from_secs(1)), 2.0);
+        assert!(Interpolation { started: started - Duration::from_millis(100), ..glide }.done());
+    }
+
+    #[test]
+    fn gripped_input_glides_towards_the_new_angle() {
+        let (mut state, capture) = data_with(&[("interpolate", "1000")]);
+        state.input_period = Duration::from_secs(60);
+        report_at(&mut state, 0.0);
+        report_at(&mut state, 30.0);
+        report_at(&mut state, 60.0);
+        let glide = state.interpolation.unwrap();
+        assert!(glide.from < glide.to && glide.to == state.wheel_angle);
+        // the report shows where the glide is, not yet the new angle
+        let shown = *capture.axis_values().last().unwrap();
+        assert!(shown < quantize_wheel_angle(&state.config, state.wheel_angle));
+        // releasing ends the glide
+        report(&mut state, 0, 0);
+        assert!(state.interpolation.is_none());
+    }
+
+    #[test]
+    fn each_sector_grips_at_its_own_threshold() {
+        let mut cfg = Config::default();
+        cfg.set("grip-thresholds", "0.5,0.95").unwrap();
+        let push = (MAX_MAGNITUDE * 0.7) as i32;
+        // the first sector centered straight down, the second straight up
+        let down = Frame { x: 0, y: push, state: State::Freewheel }.analyze(&cfg);
+        assert_eq!((down.grip_threshold, down.state), (0.5, State::Gripped));
+        let up = Frame { x: 0, y: -push, state: State::Freewheel }.analyze(&cfg);
+        assert_eq!((up.grip_threshold, up.state), (0.95, State::Freewheel));
+        // halfway round, halfway between
+        let right = Frame { x: push, y: 0, state: State::Freewheel }.analyze(&cfg);
+        assert!((right.grip_threshold - 0.725).abs() < 1e-9);
+        assert!(cfg.set("grip-thresholds", "0.5,1.5").is_err());
+    }
+
+    #[test]
+    fn the_heartbeat_repeats_the_last_report_while_idle() {
+        let (mut state, capture) = data_with(&[("heartbeat", "500")]);
+        emit_report(&mut state, 40000, now_event_time());
+        let data = RwLock::new(state);
+        timer_tick(&data);
+        assert_eq!(capture.axis_values(), [40000]);
+        data.write().unwrap().last_written -= Duration::from_millis(500);
+        timer_tick(&data);
+        assert_eq!(capture.axis_values(), [40000, 40000]);
+        // and starts over from the repeat
+        timer_tick(&data);
+        assert_eq!(capture.axis_values(), [40000, 40000]);
+    }
+
+    #[test]
+    fn a_reversal_is_damped_and_the_damping_fades_out() {
+        let mut cfg = Config::default();
+        cfg.set("reversal-damping", "0.8").unwrap();
+        cfg.set("reversal-window", "100").unwrap();
+        let mut motion = Motion::default();
+        let step = 0.1;
+        assert_eq!(reversal_damped(&cfg, &mut motion, step, 0.01), step);
+        assert_eq!(reversal_damped(&cfg, &mut motion, step, 0.01), step);
+        // right after turning back only a fifth of the rotation counts
+        assert!((reversal_damped(&cfg, &mut motion, -step, 0.01) + 0.2 * step).abs() < 1e-12);
+        // halfway through the window, 60% counts
+        for _ in 0..4 {
+            reversal_damped(&cfg, &mut motion, -step, 0.01);
+        }
+        assert!((reversal_damped(&cfg, &mut motion, -step, 0.01) + 0.6 * step).abs() < 1e-9);
+        for _ in 0..5 {
+            reversal_damped(&cfg, &mut motion, -step, 0.01);
+        }
+        assert_eq!(reversal_damped(&cfg, &mut motion, -step, 0.01), -step);
+    }
+
+    #[test]
+    fn levels_quantize_the_output_to_discrete_positions() {
+        let mut cfg = Config::default();
+        cfg.set("levels", "3").unwrap();
+        let (min, center, max) = cfg.output_range();
+        let stop = cfg.steering_stop;
+        assert_eq!(axis_value_for(&cfg, 0.3 * stop, None), (center, Some(1)));
+        assert_eq!(axis_value_for(&cfg, 0.6 * stop, None), (max, Some(2)));
+        // the default hysteresis holds the center level a little past the midpoint
+        assert_eq!(axis_value_for(&cfg, -0.55 * stop, Some(1)), (center, Some(1)));
+        assert_eq!(axis_value_for(&cfg, -0.65 * stop, Some(1)), (min, Some(0)));
+    }
+
+    #[test]
+    fn a_debug_trigger_captures_a_snapshot_when_its_condition_is_met() {
+        let path = std::env::temp_dir().join(format!("analogstick2wheel-debug-trigger-{}", std::process::id()));
+        let (mut state, _) = data_with(&[("debug-trigger", "delta>20")]);
+        state.history = Some(FrameHistory::new(Some(RotatingFile::create(&path, None).unwrap())));
+        report_at(&mut state, 90.0);
+        report_at(&mut state, 95.0);
+        report_at(&mut state, 100.0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        report_at(&mut state, 140.0);
+        let snapshot = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(snapshot.starts_with("debug trigger: Delta("), "{snapshot}");
+        assert!(snapshot.contains("  wheel_angle: "), "{snapshot}");
+        // the frame that fired it and the three before
+        assert_eq!(snapshot.lines().filter(|line| line.starts_with("  frame -")).count(), 4);
+    }
+
+    #[test]
+    fn an_off_midpoint_center_reaches_both_extremes() {
+        let mut cfg = Config::default();
+        cfg.set("output-center", "20000").unwrap();
+        cfg.set("output-min", "1000").unwrap();
+        cfg.set("output-max", "60000").unwrap();
+        assert_eq!(cfg.output_range(), (1000, 20000, 60000));
+        let stop = cfg.steering_stop;
+        assert_eq!(quantize_wheel_angle(&cfg, 0.0), 20000);
+        assert_eq!(quantize_wheel_angle(&cfg, stop), 60000);
+        assert_eq!(quantize_wheel_angle(&cfg, -stop), 1000);
+        // each side scales on its own
+        assert_eq!(quantize_wheel_angle(&cfg, stop / 2.0), 40000);
+        assert_eq!(quantize_wheel_angle(&cfg, -stop / 2.0), 10500);
+        cfg.set("output-center", "60000").unwrap();
+        assert!(cfg.validate().is_err());
+    }
+
+    // the --freewheel-scroll steps emitted so far, on REL_WHEEL
+    fn scroll_steps(capture: &Capture) -> Vec<i32> {
+        decode(&capture.0.lock().unwrap()).into_iter().filter_map(|event| match event {
+            Event::Relative(event) if event.axis == RelativeAxis::Wheel => Some(event.value),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn freewheel_rotation_scrolls_only_while_not_gripped() {
+        let (mut state, capture) = data_with(&[("freewheel-scroll", "15")]);
+        let half = MAX_MAGNITUDE / 2.0;
+        for step in 0..=6 {
+            let angle = (90.0 + 10.0 * step as f64).to_radians();
+            report(&mut state, (half * angle.cos()).round() as i32, (half * angle.sin()).round() as i32);
+        }
+        // 60 degrees of spin is four 15 degree steps
+        assert_eq!(scroll_steps(&capture).iter().sum::<i32>().abs(), 4);
+        assert!(scroll_steps(&capture).iter().all(|&steps| steps == scroll_steps(&capture)[0]));
+        let (mut state, capture) = data_with(&[("freewheel-scroll", "15")]);
+        for step in 0..=6 {
+            report_at(&mut state, 90.0 + 10.0 * step as f64);
+        }
+        assert_eq!(scroll_steps(&capture), []);
+    }
+
+    #[test]
+    fn both_distance_conventions_across_the_seam() {
+        let deg = f64::to_radians;
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        // 170 to -170 is 20 degrees forward across PI, either way it's measured
+        assert!(close(cyclic_signed_distance(deg(-170.0), deg(170.0)), deg(20.0)));
+        assert!(close(cyclic_forward_distance(deg(-170.0), deg(170.0)), deg(20.0)));
+        // backwards across the seam the signed distance stays short, the forward one goes the long way
+        assert!(close(cyclic_signed_distance(deg(170.0), deg(-170.0)), deg(-20.0)));
+        assert!(close(cyclic_forward_distance(deg(170.0), deg(-170.0)), deg(340.0)));
+        // and the same across 0 / TAU
+        assert!(close(cyclic_signed_distance(deg(350.0), deg(10.0)), deg(-20.0)));
+        assert!(close(cyclic_forward_distance(deg(10.0), deg(350.0)), deg(20.0)));
+        // half a turn lands on the closed end of each range
+        assert!(close(cyclic_signed_distance(deg(180.0), 0.0), deg(-180.0)));
+        assert!(close(cyclic_forward_distance(deg(360.0), 0.0), 0.0));
+    }
+
+    #[test]
+    fn release_smoothing_slows_only_the_freewheel_return() {
+        let run = |settings: &[(&str, &str)]| {
+            let (mut state, _) = data_with(settings);
+            for degrees in [90.0, 100.0, 110.0, 120.0] {
+                report_at(&mut state, degrees);
+            }
+            let gripped = state.wheel_angle;
+            // let go with the stick still rattling around just outside the deadzone
+            let mut released = Vec::new();
+            for (x, y) in [(3000, -2000), (-2500, 3500), (2000, 2500), (-3000, -1500)] {
+                state.last_wheel_report = Instant::now() - Duration::from_millis(20);
+                report(&mut state, x, y);
+                released.push(state.wheel_angle);
+            }
+            (gripped, released)
+        };
+        let (gripped, plain) = run(&[]);
+        let (smoothed_gripped, smoothed) = run(&[("release-smoothing", "100")]);
+        assert_eq!(gripped, smoothed_gripped);
+        for (plain, smoothed) in plain.iter().zip(&smoothed) {
+            assert!(smoothed.abs() > plain.abs(), "{} against {}", smoothed.to_degrees(), plain.to_degrees());
+        }
+        // still heading back to center, just more gently
+        assert!(smoothed.windows(2).all(|pair| pair[1].abs() < pair[0].abs()));
+        assert!(smoothed[0].abs() < gripped.abs());
+    }
+
+    #[test]
+    fn drift_correction_follows_a_slowly_drifting_rest() {
+        let rest = |settings: &[(&str, &str)]| {
+            let (mut state, _) = data_with(settings);
+            state.resting_since = Some(Instant::now() - DRIFT_REST);
+            let mut frame = Frame { x: 0, y: 0, state: State::Freewheel };
+            for step in 0..=100 {
+                frame = Frame { x: step * 15, y: -step * 8, state: State::Freewheel };
+                state.last_wheel_report = Instant::now() - Duration::from_millis(50);
+                report(&mut state, frame.x, frame.y);
+            }
+            ProcessedFrame::new(frame, &state.config).raw_magnitude
+        };
+        let uncorrected = rest(&[]);
+        let corrected = rest(&[("drift-correction", "200")]);
+        assert!(uncorrected > 0.045, "{uncorrected}");
+        assert!(corrected < 0.005, "{corrected}");
+        // but never further off than --drift-limit
+        let bounded = rest(&[("drift-correction", "200"), ("drift-limit", "0.02")]);
+        let limit = 0.02 * MAX_MAGNITUDE;
+        assert!((bounded - (1500.0 - limit).hypot(800.0 - limit) / MAX_MAGNITUDE).abs() < 1e-3, "{bounded}");
+    }
+
+    #[test]
+    fn the_wheel_holds_center_until_calibration_completes() {
+        let center = quantize_wheel_angle(&Config::default(), 0.0);
+        let (mut state, capture) = data_with(&[("hold-while-calibrating", "true")]);
+        apply_control(&mut state, ControlCommand::Calibrate);
+        for degrees in [90.0, 120.0, 150.0, 180.0] {
+            report_at(&mut state, degrees);
+        }
+        assert!(capture.axis_values().iter().all(|&value| value == center), "{:?}", capture.axis_values());
+        apply_control(&mut state, ControlCommand::FinishCalibration);
+        for degrees in [180.0, 210.0] {
+            report_at(&mut state, degrees);
+        }
+        assert_ne!(*capture.axis_values().last().unwrap(), center);
+    }
+
+    fn syncs(capture: &Capture) -> usize {
+        decode(&capture.0.lock().unwrap()).iter().filter(|event| matches!(event, Event::Synchronize(_))).count()
+    }
+
+    #[test]
+    fn sync_on_change_drops_redundant_syncs_but_keeps_the_heartbeat() {
+        let (mut state, capture) = data_with(&[("sync-on-change", "true")]);
+        emit_report(&mut state, 40000, now_event_time());
+        emit_report(&mut state, 40000, now_event_time());
+        state.emit_sync(&SynchronizeEvent::report(now_event_time()));
+        assert_eq!((capture.axis_values(), syncs(&capture)), (vec![40000], 1));
+        // a passed through event makes the next sync worth sending
+        state.emit(AbsoluteEvent::new(now_event_time(), AbsoluteAxis::Z, 5).into_event().as_bytes());
+        state.emit_sync(&SynchronizeEvent::report(now_event_time()));
+        assert_eq!(syncs(&capture), 2);
+        emit_report(&mut state, 40001, now_event_time());
+        assert_eq!((capture.axis_values(), syncs(&capture)), (vec![40000, 40001], 3));
+        let data = RwLock::new(state);
+        timer_tick(&data);
+        assert_eq!(syncs(&capture), 3);
+        data.write().unwrap().last_written -= Duration::from_secs(1);
+        timer_tick(&data);
+        assert_eq!((capture.axis_values(), syncs(&capture)), (vec![40000, 40001, 40001], 4));
+
+        let (mut state, capture) = data_with(&[]);
+        emit_report(&mut state, 40000, now_event_time());
+        emit_report(&mut state, 40000, now_event_time());
+        state.emit_sync(&SynchronizeEvent::report(now_event_time()));
+        assert_eq!(syncs(&capture), 3);
+    }
+
+    // output writes that wait for the test to let go of the gate
+    struct Gated {
+        out: Capture,
+        gate: Arc<Mutex<()>>,
+    }
+
+    impl Write for Gated {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _open = self.gate.lock().unwrap();
+            self.out.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn the_watchdog_centers_the_wheel_once_a_stalled_writer_recovers() {
+        let (gate, capture) = (Arc::new(Mutex::new(())), Capture::default());
+        let output = Output::new(Gated { out: capture.clone(), gate: gate.clone() }, false);
+        let queue = OutputQueue::spawn(output, 16, crate::output::Backpressure::DropOldest);
+        let data = Arc::new(RwLock::new(Data::new(Config::default(), OutputHandle::Queued(queue.clone()))));
+        data.write().unwrap().wheel_angle = 90f64.to_radians();
+        let stuck = gate.lock().unwrap();
+        let at_lock = quantize_wheel_angle(&Config::default(), 90f64.to_radians());
+        emit_report(&mut data.write().unwrap(), at_lock, now_event_time());
+        watch_writer(data.clone(), queue.clone(), Duration::from_millis(20));
+        thread::sleep(Duration::from_millis(60));
+        // nothing to do while it's stuck, the game already has the frozen value
+        assert_eq!(data.read().unwrap().wheel_angle, 90f64.to_radians());
+        drop(stuck);
+        let started = Instant::now();
+        while data.read().unwrap().wheel_angle != 0.0 {
+            assert!(started.elapsed() < Duration::from_secs(2), "the wheel wasn't centered after the writer recovered");
+            thread::sleep(Duration::from_millis(5));
+        }
+        queue.drain();
+        assert_eq!(capture.axis_values(), [at_lock, quantize_wheel_angle(&Config::default(), 0.0)]);
+    }
+
+    #[test]
+    fn only_the_steering_sector_grips() {
+        let mut cfg = Config::default();
+        cfg.set("steering-sector", "-90,90").unwrap();
+        // x right, y down: the top half of the stick
+        assert_eq!(frame_at(&cfg, 0, -32767).state, State::Gripped);
+        assert_eq!(frame_at(&cfg, 32767, 0).state, State::Gripped);
+        assert_eq!(frame_at(&cfg, -23170, -23170).state, State::Gripped);
+        assert_eq!(frame_at(&cfg, 0, 32767).state, State::Freewheel);
+        assert_eq!(frame_at(&cfg, 23170, 23170).state, State::Freewheel);
+        // a sector going the other way round wraps through down
+        cfg.set("steering-sector", "90,-90").unwrap();
+        assert_eq!(frame_at(&cfg, 0, 32767).state, State::Gripped);
+        assert_eq!(frame_at(&cfg, 0, -32767).state, State::Freewheel);
+        // and a filtered grip decision respects it too
+        let mut frame = frame_at(&cfg, 0, -32767);
+        frame.regrip(1.0, &cfg);
+        assert_eq!(frame.state, State::Freewheel);
+
+        let (mut state, _) = data_with(&[("steering-sector", "-90,90")]);
+        for degrees in [-90.0, -60.0, -30.0] {
+            report_at(&mut state, degrees);
+        }
+        let steered = state.wheel_angle;
+        assert!(steered.abs() > 50f64.to_radians(), "{}", steered.to_degrees());
+        // rotating through the bottom half leaves the wheel to center instead
+        for degrees in [120.0, 150.0] {
+            report_at(&mut state, degrees);
+        }
+        assert!(state.wheel_angle.abs() < steered.abs());
+        assert_eq!(state.prev.state, State::Freewheel);
+    }
+
+    #[test]
+    fn millions_of_tiny_deltas_add_up_to_the_analytic_sum() {
+        let cfg = Config::default();
+        let mut angle = 0.0;
+        for step in 0..2_000_000 {
+            // forth a little more than back, 1e-7 radians net every two steps
+            let delta = if step % 2 == 0 { 3e-7 } else { -2e-7 };
+            angle = renormalize(&cfg, angle + delta);
+        }
+        assert!((angle - 0.1).abs() < 1e-9, "{angle}");
+        // well below one axis unit
+        assert_eq!(quantize_wheel_angle(&cfg, angle), quantize_wheel_angle(&cfg, 0.1));
+    }
+
+    #[test]
+    fn renormalizing_clears_residue_and_poison_and_keeps_the_stops() {
+        let cfg = Config::default();
+        assert_eq!(renormalize(&cfg, ANGLE_EPSILON / 2.0), 0.0);
+        assert_eq!(renormalize(&cfg, f64::NAN), 0.0);
+        assert_eq!(renormalize(&cfg, f64::NEG_INFINITY), 0.0);
+        assert_eq!(renormalize(&cfg, 100.0), cfg.steering_stop);
+        assert_eq!(renormalize(&cfg, -100.0), -cfg.steering_stop);
+        assert_eq!(renormalize(&cfg, 0.5), 0.5);
+    }
+
+    #[test]
+    fn reanchoring_snaps_to_the_axis_unit_the_game_has() {
+        let cfg = Config::default();
+        for angle in [0.123456, -0.987654, 1.5, -0.0001] {
+            let snapped = snap_to_axis_grid(&cfg, angle);
+            assert!(snapped.abs() <= angle.abs());
+            assert!((angle - snapped).abs() < cfg.steering_stop / 32767.0);
+            assert_eq!(snap_to_axis_grid(&cfg, snapped), snapped);
+        }
+    }
+
+    // (input, wheel angle in degrees, axis value) for each row of the summary
+    fn summary_rows(summary: &str) -> Vec<(f64, f64, i32)> {
+        summary.lines().skip(2).map(|row| {
+            let fields: Vec<&str> = row.split_whitespace().collect();
+            (fields[0].trim_end_matches(['°', '%']).parse().unwrap(), fields[1].trim_end_matches('°').parse().unwrap(), fields[2].parse().unwrap())
+        }).collect()
+    }
+
+    #[test]
+    fn the_response_summary_matches_the_pipeline() {
+        let mut cfg = Config::default();
+        cfg.set("steering-range", "900").unwrap();
+        cfg.set("outer-gamma", "1.5").unwrap();
+        let rows = summary_rows(&response_summary(&cfg));
+        assert_eq!(rows.len(), 7);
+        let stop = cfg.steering_stop;
+        for (&(rotation, wheel_angle, axis_value), fraction) in rows.iter().zip([0.0, 0.125, 0.25, 0.5, 0.75, 1.0, 1.25]) {
+            assert!((rotation - (fraction * stop).to_degrees()).abs() < 0.05);
+            // past the stop the wheel stays at lock
+            assert!((wheel_angle - (fraction * stop).min(stop).to_degrees()).abs() < 0.05);
+            assert_eq!(axis_value, axis_value_for(&cfg, (fraction * stop).min(stop), None).0);
+        }
+        assert_eq!(rows[0].2, quantize_wheel_angle(&cfg, 0.0));
+        assert_eq!(rows[5].2, cfg.output_range().2);
+        // and an actual stick turned as far as the second row comes out the same
+        let (mut state, capture) = data_with(&[("steering-range", "900"), ("outer-gamma", "1.5")]);
+        let mut degrees = 90.0;
+        report_at(&mut state, degrees);
+        while degrees < 90.0 + rows[1].0 {
+            degrees = (degrees + 7.5f64).min(90.0 + rows[1].0);
+            report_at(&mut state, degrees);
+        }
+        // give or take the stick positions being whole units
+        assert!((*capture.axis_values().last().unwrap() - rows[1].2).abs() <= 5, "{:?} against {}", capture.axis_values(), rows[1].2);
+
+        let mut cfg = Config::default();
+        cfg.set("mode", "push").unwrap();
+        let rows = summary_rows(&response_summary(&cfg));
+        assert_eq!(rows.iter().map(|row| row.0).collect::<Vec<_>>(), [0.0, 10.0, 25.0, 50.0, 75.0, 100.0]);
+        assert_eq!(rows[5].2, cfg.output_range().2);
+    }
+
+    #[test]
+    fn staggered_axis_updates_are_detected_and_interpolated() {
+        let at = |millis: i64| EventTime::new(100, millis * 1000);
+        let stagger = |settings: &[(&str, &str)], y_time: i64| {
+            let (mut state, _) = data_with(settings);
+            record_stick(&mut state, AbsoluteAxis::X, 10000, at(0));
+            record_stick(&mut state, AbsoluteAxis::Y, 0, at(0));
+            record_stick(&mut state, AbsoluteAxis::X, 12000, at(10));
+            record_stick(&mut state, AbsoluteAxis::Y, 5000, at(y_time));
+            check_axis_skew(&mut state, Duration::from_millis(20));
+            (state.skew_warned, state.cur.x)
+        };
+        // within the limit nothing happens
+        assert_eq!(stagger(&[("axis-skew-action", "interpolate")], 25), (false, 12000));
+        // 30ms behind: x is carried along its slope, at most one sample interval ahead
+        assert_eq!(stagger(&[("axis-skew-action", "interpolate")], 40), (true, 14000));
+        assert_eq!(stagger(&[("axis-skew-action", "warn")], 40), (true, 12000));
+        // an axis at rest isn't behind however long it stays quiet
+        let (mut state, _) = data_with(&[("axis-skew-action", "interpolate")]);
+        record_stick(&mut state, AbsoluteAxis::X, 12000, at(0));
+        record_stick(&mut state, AbsoluteAxis::X, 12000, at(10));
+        record_stick(&mut state, AbsoluteAxis::Y, 0, at(0));
+        record_stick(&mut state, AbsoluteAxis::Y, 5000, at(40));
+        check_axis_skew(&mut state, Duration::from_millis(20));
+        assert_eq!((state.skew_warned, state.cur.x), (false, 12000));
+    }
+
+    #[test]
+    fn a_wide_output_resolution_maps_the_endpoints() {
+        let mut cfg = Config::default();
+        cfg.set("output-resolution", "2147483647").unwrap();
+        let stop = cfg.steering_stop;
+        assert_eq!(quantize_wheel_angle(&cfg, 0.0), 1073741823);
+        assert_eq!(quantize_wheel_angle(&cfg, -stop), 0);
+        assert_eq!(quantize_wheel_angle(&cfg, stop), 2147483646);
+        assert_eq!(quantize_wheel_angle(&cfg, stop / 2.0), 1073741823 + 536870911);
+        // and the virtual device declares the same range
+        let description = cfg.description();
+        let wheel_axis = &description[description.find("ABS_X:").unwrap()..];
+        for line in ["MIN: 0", "MAX: 2147483647", "VALUE: 1073741823"] {
+            assert!(wheel_axis.lines().take(5).any(|l| l.trim() == line), "{line} missing from {wheel_axis}");
+        }
+        assert!(cfg.set("output-resolution", "254").is_err());
+    }
+
+    #[test]
+    fn the_startup_sweep_reaches_both_extremes_and_returns_to_center() {
+        let (mut state, capture) = data_with(&[]);
+        let (min, center, max) = state.config.output_range();
+        // a leg in about ten reports
+        let speed = state.config.steering_stop * 10.0;
+        startup_sweep(&mut state, speed);
+        let values = capture.axis_values();
+        let left = values.iter().position(|&value| value == min).unwrap();
+        let right = values.iter().position(|&value| value == max).unwrap();
+        assert!(left < right && left > 0, "{values:?}");
+        assert_eq!(*values.last().unwrap(), center);
+        // steadily out to the left, across to the right and back
+        assert!(values[..=left].windows(2).all(|pair| pair[1] < pair[0]), "{values:?}");
+        assert!(values[left..=right].windows(2).all(|pair| pair[1] > pair[0]), "{values:?}");
+        assert!(values[right..].windows(2).all(|pair| pair[1] < pair[0]), "{values:?}");
+        assert!(values.len() > 6);
+    }
+
+    #[test]
+    fn speed_gain_turns_quickly_near_center_and_resists_near_lock() {
+        let mut cfg = Config::default();
+        let (prev, cur) = (full_deflection(&cfg, 90.0), full_deflection(&cfg, 100.0));
+        let turn = |cfg: &Config, wheel_angle: f64, d_t: f64| {
+            wheel_behaviour(cfg, wheel_angle, &mut Motion::default(), &cur, &prev, 1.0, d_t) - wheel_angle
+        };
+        let plain = turn(&cfg, 0.0, 0.01);
+        assert!((plain.abs() - 10f64.to_radians()).abs() < 1e-3);
+        cfg.set("speed-gain", "2,0.5,500").unwrap();
+        let stop = cfg.steering_stop;
+        // 10 degrees in 10ms is past the full-effect speed
+        assert!((turn(&cfg, 0.0, 0.01) - 2.0 * plain).abs() < 1e-9);
+        let near_lock = 0.8 * stop * plain.signum();
+        assert!((turn(&cfg, near_lock, 0.01) - (2.0 - 1.5 * 0.8) * plain).abs() < 1e-9);
+        // turning back from near lock gets the center gain
+        assert!((turn(&cfg, -near_lock, 0.01) - 2.0 * plain).abs() < 1e-9);
+        // at half the full-effect speed, half the extra gain
+        assert!((turn(&cfg, 0.0, 0.04) - 1.5 * plain).abs() < 1e-5);
+    }
+
+    #[test]
+    fn a_grip_without_a_direction_keeps_the_last_reference_angle() {
+        let turned = |settings: &[(&str, &str)]| {
+            let (mut state, _) = data_with(settings);
+            state.grip_magnitude = 1.0;
+            // gripped exactly on +x, then the raw stick dips through the
+            // deadzone while the smoothed magnitude holds the grip
+            report(&mut state, 32767, 0);
+            report(&mut state, 0, 0);
+            assert_eq!(state.prev.state, State::Gripped);
+            let held = state.wheel_angle;
+            report_at(&mut state, 10.0);
+            (state.prev.analog_angle, (state.wheel_angle - held).abs().to_degrees())
+        };
+        let (angle, fallback) = turned(&[("grip-smoothing", "10000"), ("grip-angle-fallback", "true")]);
+        assert!(angle.is_some());
+        assert!((fallback - 10.0).abs() < 0.01, "{fallback}");
+        // without it the first rotation after the dip is dropped
+        let (_, dropped) = turned(&[("grip-smoothing", "10000")]);
+        assert!(dropped < 0.01, "{dropped}");
+    }
+
+    #[test]
+    fn the_return_to_center_is_faster_further_out() {
+        let returned = |cfg: &Config, from: f64| {
+            let released = frame_at(cfg, 0, 0);
+            let eased = wheel_behaviour(cfg, from.to_radians(), &mut Motion::default(), &released, &released, 1.0, 0.01);
+            from - eased.to_degrees()
+        };
+        let mut cfg = Config::default();
+        let (far, near) = (returned(&cfg, 360.0), returned(&cfg, 30.0));
+        assert!(far > near);
+        // the same share of the way either way, so it settles gently near center
+        assert!((far / 360.0 - near / 30.0).abs() < 1e-9);
+        assert!((near - 30.0 * cfg.centering_gain * 0.01).abs() < 1e-9);
+        cfg.set("centering-gain", "5").unwrap();
+        assert!(returned(&cfg, 30.0) > near);
+    }
+
+    #[test]
+    fn the_fine_adjustment_never_takes_the_output_past_lock() {
+        let (mut state, _) = data_with(&[("fine-axis", "ABS_RX"), ("fine-range", "90")]);
+        let (min, _, max) = state.config.output_range();
+        let stop = state.config.steering_stop;
+        state.wheel_angle = stop - 10f64.to_radians();
+        state.fine = 1.0;
+        assert_eq!(state.output_value(), max);
+        assert!(state.offset_clamped);
+        state.wheel_angle = -stop;
+        state.fine = -1.0;
+        assert_eq!(state.output_value(), min);
+        // short of lock the adjustment adds on as usual
+        state.wheel_angle = 0.0;
+        state.fine = 0.5;
+        assert_eq!(state.output_value(), quantize_wheel_angle(&state.config, 45f64.to_radians()));
+        // a fine range reaching lock on its own is rejected
+        let mut cfg = state.config.clone();
+        cfg.set("fine-range", &stop.to_degrees().to_string()).unwrap();
+        assert!(cfg.validate().unwrap_err().starts_with("fine range"));
+    }
 }
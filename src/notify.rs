@@ -0,0 +1,126 @@
+use input_linux::EventTime;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    os::unix::{fs::FileTypeExt, net::UnixStream},
+    path::{Path, PathBuf},
+};
+
+use crate::{State, rotate::{Rotation, RotatingFile}};
+
+// line based grip notifications for external tools, one line per transition:
+// `<seconds>.<microseconds> <state> <wheel angle in degrees>`
+pub struct StateNotifier {
+    sink: Box<dyn Write + Send + Sync>,
+    last: State,
+}
+
+impl StateNotifier {
+    // sockets get connected to, regular files appended to and rotated,
+    // anything else (usually a fifo) is opened for writing
+    pub fn open(path: &Path, rotation: Option<Rotation>) -> io::Result<Self> {
+        let file_type = path.metadata()?.file_type();
+        let sink: Box<dyn Write + Send + Sync> = if file_type.is_socket() {
+            Box::new(UnixStream::connect(path)?)
+        } else if file_type.is_file() {
+            Box::new(RotatingFile::append(path, rotation)?)
+        } else {
+            Box::new(OpenOptions::new().append(true).open(path)?)
+        };
+        Ok(Self { sink, last: State::Freewheel })
+    }
+
+    pub fn update(&mut self, state: State, time: EventTime, wheel_angle: f64) {
+        if state == self.last {
+            return;
+        }
+        self.last = state;
+        let line = format!("{}.{:06} {:?} {:.6}\n",
+                           time.seconds(), time.microseconds(), state, wheel_angle.to_degrees());
+        if let Err(e) = self.sink.write_all(line.as_bytes()).and_then(|_| self.sink.flush()) {
+            eprintln!("state notification failed: {e}");
+        }
+    }
+}
+
+// mirrors "wheel is centered" into a file such as an LED's sysfs brightness
+#[derive(Clone, Debug)]
+pub struct CenterIndicator {
+    path: PathBuf,
+    lit: Option<bool>,
+}
+
+impl CenterIndicator {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, lit: None }
+    }
+
+    // returns false once the indicator can't be written, so the caller can drop it
+    pub fn update(&mut self, centered: bool) -> bool {
+        if self.lit == Some(centered) {
+            return true;
+        }
+        self.lit = Some(centered);
+        match fs::write(&self.path, if centered { "1\n" } else { "0\n" }) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("warning: center indicator {} unavailable, disabling it: {e}", self.path.display());
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("analogstick2wheel-{name}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn only_transitions_are_notified() {
+        let path = scratch("notify-file");
+        fs::write(&path, "").unwrap();
+        let mut notifier = StateNotifier::open(&path, None).unwrap();
+        notifier.update(State::Freewheel, EventTime::new(1, 0), 0.0);
+        notifier.update(State::Gripped, EventTime::new(2, 5), 90f64.to_radians());
+        notifier.update(State::Gripped, EventTime::new(3, 0), 180f64.to_radians());
+        notifier.update(State::Freewheel, EventTime::new(4, 250000), (-45f64).to_radians());
+        let lines = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(lines, "2.000005 Gripped 90.000000\n4.250000 Freewheel -45.000000\n");
+    }
+
+    #[test]
+    fn sockets_are_connected_to() {
+        let path = scratch("notify-socket");
+        let _ = fs::remove_file(&path);
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let mut notifier = StateNotifier::open(&path, None).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        notifier.update(State::Gripped, EventTime::new(7, 0), 0.0);
+        drop(notifier);
+        let mut received = String::new();
+        io::Read::read_to_string(&mut peer, &mut received).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(received, "7.000000 Gripped 0.000000\n");
+    }
+
+    #[test]
+    fn the_center_indicator_writes_only_changes() {
+        let path = scratch("indicator");
+        let mut indicator = CenterIndicator::new(path.clone());
+        assert!(indicator.update(true));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1\n");
+        // something else changing the file in between isn't overwritten with the same state
+        fs::write(&path, "x").unwrap();
+        assert!(indicator.update(true));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "x");
+        assert!(indicator.update(false));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "0\n");
+        fs::remove_file(&path).unwrap();
+        assert!(!CenterIndicator::new(path.join("missing")).update(true));
+    }
+}
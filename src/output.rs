@@ -0,0 +1,527 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::rotate::{Rotation, RotatingFile};
+
+// every emitted byte goes to stdout (the uinput pipe) or our own device, and to any mirrors;
+// a failing mirror is reported and dropped without affecting the rest
+pub struct Output {
+    // None once the pipe went away and keep_running let us carry on without it
+    primary: Option<Box<dyn Write + Send + Sync>>,
+    mirrors: Vec<Mirror>,
+    keep_running: bool,
+    batch: Option<Batch>,
+}
+
+// bytes held back to go out in fewer, larger writes
+struct Batch {
+    buffer: Vec<u8>,
+    max_bytes: usize,
+    max_delay: Duration,
+    // when the oldest byte in the buffer arrived
+    started: Instant,
+}
+
+struct Mirror {
+    name: String,
+    writer: Box<dyn Write + Send + Sync>,
+}
+
+impl Output {
+    pub fn stdout(keep_running: bool) -> Self {
+        Self::new(io::stdout(), keep_running)
+    }
+
+    pub fn new(primary: impl Write + Send + Sync + 'static, keep_running: bool) -> Self {
+        Self { primary: Some(Box::new(primary)), mirrors: Vec::new(), keep_running, batch: None }
+    }
+
+    // writes happen once max_bytes piled up or on the first flush max_delay
+    // after the oldest of them, so nothing waits longer than that plus the timer period
+    pub fn batched(mut self, max_bytes: usize, max_delay: Duration) -> Self {
+        self.batch = Some(Batch { buffer: Vec::with_capacity(max_bytes), max_bytes, max_delay, started: Instant::now() });
+        self
+    }
+
+    pub fn mirror_to_file(&mut self, path: &Path, rotation: Option<Rotation>) -> io::Result<()> {
+        let file = RotatingFile::create(path, rotation)?;
+        self.mirror(path.display().to_string(), file);
+        Ok(())
+    }
+
+    pub fn mirror(&mut self, name: String, writer: impl Write + Send + Sync + 'static) {
+        self.mirrors.push(Mirror { name, writer: Box::new(writer) });
+    }
+
+    fn each_mirror(&mut self, mut op: impl FnMut(&mut dyn Write) -> io::Result<()>) {
+        self.mirrors.retain_mut(|mirror| match op(&mut mirror.writer) {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("error: output mirror {} failed, no longer mirroring: {e}", mirror.name);
+                false
+            }
+        });
+    }
+
+    fn on_primary(&mut self, op: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+        let Some(primary) = self.primary.as_mut() else { return Ok(()) };
+        match op(primary) {
+            Err(e) if self.keep_running => {
+                eprintln!("warning: {}", explain_write_error(&e));
+                eprintln!("warning: continuing without output");
+                self.primary = None;
+                Ok(())
+            },
+            result => result,
+        }
+    }
+
+    pub fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let Some(batch) = self.batch.as_mut() else { return self.write_through(bytes) };
+        if batch.buffer.is_empty() {
+            batch.started = Instant::now();
+        }
+        batch.buffer.extend_from_slice(bytes);
+        if batch.buffer.len() >= batch.max_bytes {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+
+    fn write_through(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.each_mirror(|w| w.write_all(bytes));
+        self.on_primary(|w| w.write_all(bytes))
+    }
+
+    fn write_batch(&mut self) -> io::Result<()> {
+        let Some(batch) = self.batch.as_mut() else { return Ok(()) };
+        if batch.buffer.is_empty() {
+            return Ok(());
+        }
+        let bytes = std::mem::take(&mut batch.buffer);
+        self.write_through(&bytes)
+    }
+
+    // a batch younger than its delay stays buffered
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.batch.as_ref().is_some_and(|batch| batch.started.elapsed() < batch.max_delay) {
+            return Ok(());
+        }
+        self.flush_all()
+    }
+
+    pub fn flush_all(&mut self) -> io::Result<()> {
+        self.write_batch()?;
+        self.each_mirror(|w| w.flush());
+        self.on_primary(|w| w.flush())
+    }
+}
+
+// stdout normally feeds interception-tools' uinput, which exits when it can't
+// create the virtual device; point at the usual causes instead of a bare errno
+pub fn explain_write_error(e: &io::Error) -> String {
+    const UINPUT_HINT: &str = "if it feeds `uinput`, check that the uinput module is loaded (`modprobe uinput`) \
+        and that /dev/uinput is writable by this user, e.g. through a udev rule or the input group";
+    match e.kind() {
+        io::ErrorKind::BrokenPipe => format!("writing output failed, the reading end of the pipe closed: {UINPUT_HINT}"),
+        io::ErrorKind::PermissionDenied => format!("writing output failed, permission denied: {UINPUT_HINT}"),
+        _ if e.raw_os_error() == Some(libc::ENODEV) => format!("writing output failed, no such device: {UINPUT_HINT}"),
+        _ => format!("writing output failed: {e}"),
+    }
+}
+
+// refills `rate` tokens per second up to `capacity`, one token per report
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: f64, rate: f64) -> Self {
+        Self { capacity, rate, tokens: capacity, last_refill: Instant::now() }
+    }
+
+    pub fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backpressure {
+    // stall the producer until the writer catches up
+    Block,
+    // discard the oldest wheel report a newer one supersedes, the newest value always
+    // gets through; passed through events are never dropped, with no report to spare it blocks
+    DropOldest,
+}
+
+// bounded handoff between the event threads and the single writer thread
+pub struct OutputQueue {
+    inner: Mutex<Queued>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    idle: Condvar,
+    capacity: usize,
+    policy: Backpressure,
+}
+
+struct Queued {
+    chunks: VecDeque<Chunk>,
+    // when the writer thread took the chunks it is writing out
+    writing: Option<Instant>,
+}
+
+struct Chunk {
+    bytes: Vec<u8>,
+    // a wheel axis report, which only matters until the next one
+    report: bool,
+}
+
+impl Queued {
+    // the oldest queued report, if a newer one (queued or the incoming chunk) makes it stale
+    fn superseded(&self, report: bool) -> Option<usize> {
+        let mut reports = self.chunks.iter().enumerate().filter(|(_, chunk)| chunk.report).map(|(i, _)| i);
+        let oldest = reports.next()?;
+        (report || reports.next().is_some()).then_some(oldest)
+    }
+}
+
+impl OutputQueue {
+    pub fn spawn(mut output: Output, capacity: usize, policy: Backpressure) -> Arc<Self> {
+        let queue = Arc::new(Self {
+            inner: Mutex::new(Queued { chunks: VecDeque::with_capacity(capacity), writing: None }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            idle: Condvar::new(),
+            capacity,
+            policy,
+        });
+        let writer_queue = queue.clone();
+        thread::spawn(move || loop {
+            let chunks: Vec<Chunk> = {
+                let mut inner = writer_queue.not_empty
+                    .wait_while(writer_queue.inner.lock().unwrap(), |q| q.chunks.is_empty())
+                    .unwrap();
+                inner.writing = Some(Instant::now());
+                inner.chunks.drain(..).collect()
+            };
+            writer_queue.not_full.notify_all();
+            if let Err(e) = chunks.iter().try_for_each(|c| output.write_all(&c.bytes)).and_then(|_| output.flush_all()) {
+                eprintln!("error: {}", explain_write_error(&e));
+                std::process::exit(1);
+            }
+            writer_queue.inner.lock().unwrap().writing = None;
+            writer_queue.idle.notify_all();
+        });
+        queue
+    }
+
+    // events passed through from the input
+    pub fn push(&self, bytes: &[u8]) {
+        self.enqueue(Chunk { bytes: bytes.to_vec(), report: false });
+    }
+
+    // a wheel axis report, which Backpressure::DropOldest may drop for a newer one
+    pub fn push_report(&self, bytes: &[u8]) {
+        self.enqueue(Chunk { bytes: bytes.to_vec(), report: true });
+    }
+
+    fn enqueue(&self, chunk: Chunk) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.chunks.len() >= self.capacity {
+            match inner.superseded(chunk.report) {
+                Some(stale) if self.policy == Backpressure::DropOldest => {
+                    inner.chunks.remove(stale);
+                },
+                _ => {
+                    inner = self.not_full.wait_while(inner, |q| q.chunks.len() >= self.capacity).unwrap();
+                },
+            }
+        }
+        inner.chunks.push_back(chunk);
+        self.not_empty.notify_one();
+    }
+
+    // wait until everything queued so far has been written out
+    pub fn drain(&self) {
+        let _idle = self.idle
+            .wait_while(self.inner.lock().unwrap(), |q| !q.chunks.is_empty() || q.writing.is_some())
+            .unwrap();
+    }
+
+    // how long the write in progress has been going, if there is one
+    pub fn busy_for(&self) -> Option<Duration> {
+        self.inner.lock().unwrap().writing.map(|since| since.elapsed())
+    }
+}
+
+#[derive(Clone)]
+pub enum OutputHandle {
+    Direct(Arc<Mutex<Output>>),
+    Queued(Arc<OutputQueue>),
+}
+
+impl OutputHandle {
+    pub fn write_all(&self, bytes: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Direct(output) => output.lock().unwrap().write_all(bytes),
+            Self::Queued(queue) => {
+                queue.push(bytes);
+                Ok(())
+            },
+        }
+    }
+
+    // like write_all, for wheel axis reports a full queue may drop once superseded
+    pub fn write_report(&self, bytes: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Direct(output) => output.lock().unwrap().write_all(bytes),
+            Self::Queued(queue) => {
+                queue.push_report(bytes);
+                Ok(())
+            },
+        }
+    }
+
+    // the writer thread flushes on its own after every batch
+    pub fn flush(&self) -> io::Result<()> {
+        match self {
+            Self::Direct(output) => output.lock().unwrap().flush(),
+            Self::Queued(_) => Ok(()),
+        }
+    }
+
+    // like flush, but also writes out any batch and waits for the writer thread to catch up
+    pub fn sync(&self) -> io::Result<()> {
+        match self {
+            Self::Direct(output) => output.lock().unwrap().flush_all(),
+            Self::Queued(queue) => {
+                queue.drain();
+                Ok(())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a writer the test keeps a handle on
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn bytes(&self) -> Vec<u8> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    // a writer that always fails with the given error
+    struct Broken(io::ErrorKind);
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(self.0.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(self.0.into())
+        }
+    }
+
+    #[test]
+    fn mirrors_get_a_copy_and_a_failing_one_is_dropped() {
+        let (primary, mirror) = (Shared::default(), Shared::default());
+        let mut output = Output::new(primary.clone(), false);
+        output.mirror("copy".to_string(), mirror.clone());
+        output.mirror("broken".to_string(), Broken(io::ErrorKind::BrokenPipe));
+        output.write_all(b"one").unwrap();
+        assert_eq!(output.mirrors.len(), 1);
+        output.write_all(b"two").unwrap();
+        output.flush_all().unwrap();
+        assert_eq!(primary.bytes(), b"onetwo");
+        assert_eq!(mirror.bytes(), b"onetwo");
+    }
+
+    #[test]
+    fn tee_mirrors_to_a_file() {
+        let path = std::env::temp_dir().join(format!("analogstick2wheel-tee-{}", std::process::id()));
+        let mut output = Output::new(Shared::default(), false);
+        output.mirror_to_file(&path, None).unwrap();
+        output.write_all(b"events").unwrap();
+        output.flush_all().unwrap();
+        drop(output);
+        let written = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, b"events");
+    }
+
+    #[test]
+    fn the_token_bucket_allows_a_burst_then_refills() {
+        let mut bucket = TokenBucket::new(2.0, 10.0);
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        // well within the next token's 100ms
+        bucket.last_refill = Instant::now();
+        assert!(!bucket.try_take());
+        bucket.last_refill -= Duration::from_secs(1);
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        // refilled only up to the capacity
+        assert!(!bucket.try_take());
+    }
+
+    // writes wait for the test to let go of the gate
+    struct Gated {
+        out: Shared,
+        gate: Arc<Mutex<()>>,
+    }
+
+    impl Write for Gated {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _open = self.gate.lock().unwrap();
+            self.out.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // a queue whose writer thread is stuck writing `first` while the gate is held
+    fn stalled_queue(gate: &Arc<Mutex<()>>, capacity: usize, policy: Backpressure) -> (Arc<OutputQueue>, Shared) {
+        let out = Shared::default();
+        let queue = OutputQueue::spawn(Output::new(Gated { out: out.clone(), gate: gate.clone() }, false), capacity, policy);
+        queue.push(b"first ");
+        while queue.busy_for().is_none() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        (queue, out)
+    }
+
+    #[test]
+    fn a_full_queue_drops_the_oldest_report() {
+        let gate = Arc::new(Mutex::new(()));
+        let closed = gate.lock().unwrap();
+        let (queue, out) = stalled_queue(&gate, 2, Backpressure::DropOldest);
+        for chunk in [b"a ", b"b ", b"c "] {
+            queue.push_report(chunk);
+        }
+        drop(closed);
+        queue.drain();
+        assert_eq!(out.bytes(), b"first b c ");
+    }
+
+    #[test]
+    fn a_full_queue_never_drops_passed_through_events() {
+        let gate = Arc::new(Mutex::new(()));
+        let closed = gate.lock().unwrap();
+        let (queue, out) = stalled_queue(&gate, 3, Backpressure::DropOldest);
+        queue.push_report(b"r1 ");
+        queue.push(b"press ");
+        queue.push(b"release ");
+        // full, so each report takes the place of the one before it
+        queue.push_report(b"r2 ");
+        queue.push_report(b"r3 ");
+        drop(closed);
+        queue.drain();
+        assert_eq!(out.bytes(), b"first press release r3 ");
+    }
+
+    #[test]
+    fn a_full_queue_blocks_until_the_writer_catches_up() {
+        let gate = Arc::new(Mutex::new(()));
+        let closed = gate.lock().unwrap();
+        let (queue, out) = stalled_queue(&gate, 1, Backpressure::Block);
+        queue.push(b"a ");
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(b"b "))
+        };
+        thread::sleep(Duration::from_millis(20));
+        assert!(!producer.is_finished());
+        drop(closed);
+        producer.join().unwrap();
+        queue.drain();
+        assert_eq!(out.bytes(), b"first a b ");
+        assert_eq!(queue.busy_for(), None);
+    }
+
+    #[test]
+    fn write_errors_point_at_uinput() {
+        let broken = explain_write_error(&io::ErrorKind::BrokenPipe.into());
+        assert!(broken.starts_with("writing output failed, the reading end of the pipe closed: "));
+        assert!(broken.contains("modprobe uinput"));
+        assert!(explain_write_error(&io::Error::from_raw_os_error(libc::ENODEV)).starts_with("writing output failed, no such device: "));
+        assert_eq!(explain_write_error(&io::Error::other("disk on fire")), "writing output failed: disk on fire");
+    }
+
+    #[test]
+    fn keep_running_carries_on_for_the_mirrors() {
+        let mirror = Shared::default();
+        let mut output = Output::new(Broken(io::ErrorKind::BrokenPipe), true);
+        output.mirror("copy".to_string(), mirror.clone());
+        output.write_all(b"one").unwrap();
+        assert!(output.primary.is_none());
+        output.write_all(b"two").unwrap();
+        output.flush_all().unwrap();
+        assert_eq!(mirror.bytes(), b"onetwo");
+        // without it the failure is the caller's to handle
+        let mut output = Output::new(Broken(io::ErrorKind::BrokenPipe), false);
+        assert_eq!(output.write_all(b"one").unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn batches_go_out_whole_on_size_delay_or_shutdown() {
+        let primary = Shared::default();
+        let mut output = Output::new(primary.clone(), false).batched(8, Duration::from_secs(3600));
+        output.write_all(b"abc").unwrap();
+        output.write_all(b"def").unwrap();
+        // younger than its delay, so a plain flush leaves it buffered
+        output.flush().unwrap();
+        assert_eq!(primary.bytes(), b"");
+        output.write_all(b"gh").unwrap();
+        assert_eq!(primary.bytes(), b"abcdefgh");
+        output.write_all(b"ij").unwrap();
+        let handle = OutputHandle::Direct(Arc::new(Mutex::new(output)));
+        handle.sync().unwrap();
+        assert_eq!(primary.bytes(), b"abcdefghij");
+
+        let primary = Shared::default();
+        let mut output = Output::new(primary.clone(), false).batched(4096, Duration::ZERO);
+        output.write_all(b"abc").unwrap();
+        assert_eq!(primary.bytes(), b"");
+        output.flush().unwrap();
+        assert_eq!(primary.bytes(), b"abc");
+    }
+}
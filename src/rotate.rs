@@ -0,0 +1,81 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+// size limit for file sinks: past max_size the file is renamed to PATH.1,
+// older generations shift up to PATH.<keep> and the oldest one is deleted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rotation {
+    pub max_size: u64,
+    pub keep: usize,
+}
+
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    rotation: Option<Rotation>,
+}
+
+impl RotatingFile {
+    pub fn create(path: &Path, rotation: Option<Rotation>) -> io::Result<Self> {
+        Ok(Self { path: path.to_owned(), file: File::create(path)?, written: 0, rotation })
+    }
+
+    // keeps what's already in the file, counting it towards the limit
+    pub fn append(path: &Path, rotation: Option<Rotation>) -> io::Result<Self> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self { path: path.to_owned(), file, written, rotation })
+    }
+
+    fn generation(&self, n: usize) -> PathBuf {
+        let mut name = OsString::from(self.path.as_os_str());
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self, keep: usize) -> io::Result<()> {
+        self.file.flush()?;
+        if keep == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            match fs::remove_file(self.generation(keep)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => (),
+            }
+            for n in (1..keep).rev() {
+                match fs::rename(self.generation(n), self.generation(n + 1)) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => (),
+                }
+            }
+            fs::rename(&self.path, self.generation(1))?;
+            self.file = File::create(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    // rotates between writes, never in the middle of one, so callers handing
+    // over whole events don't get them split across files
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(Rotation { max_size, keep }) = self.rotation
+            && self.written > 0
+            && self.written + buf.len() as u64 > max_size {
+            self.rotate(keep)?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
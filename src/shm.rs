@@ -0,0 +1,234 @@
+use input_linux::EventTime;
+use std::{
+    f64::consts::TAU,
+    fs::{File, OpenOptions},
+    io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicU32, Ordering, fence},
+};
+
+use crate::State;
+
+// latest analysis published into a shared memory segment for overlays that
+// mmap it and poll. fixed native endian layout, 64 bytes:
+//
+//   0  u32 sequence, odd while an update is being written
+//   4  u32 state, 0 freewheel, 1 gripped
+//   8  i32 stick x
+//  12  i32 stick y
+//  16  f64 stick angle in radians, NaN inside the deadzone
+//  24  f64 normalized stick magnitude
+//  32  f64 wheel angle in radians
+//  40  i64 report time in microseconds
+//  48  i32 whole wheel turns from center, truncated towards zero
+//  52  u32 reserved, 0
+//  56  f64 rest of the turn in (-1, 1), same sign as the wheel angle
+//
+// e.g. -450 degrees reads as -1 turns and -0.25 for steering position indicators.
+// readers load the sequence, copy the rest, and retry if the sequence was odd
+// or changed in the meantime
+pub const SEGMENT_SIZE: usize = 64;
+
+pub struct Snapshot {
+    pub time: EventTime,
+    pub state: State,
+    pub x: i32,
+    pub y: i32,
+    pub angle: Option<f64>,
+    pub magnitude: f64,
+    pub wheel_angle: f64,
+}
+
+pub struct SharedState {
+    // the segment mapped MAP_SHARED, SEGMENT_SIZE bytes accessed as u32 words
+    // so every store is atomic and readers in this process are well defined too
+    map: NonNull<AtomicU32>,
+}
+
+// the mapping is only written through the atomics, and only by its owner
+unsafe impl Send for SharedState {}
+
+impl SharedState {
+    // bare names live in /dev/shm like shm_open(3) segments, anything with a slash is a path
+    pub fn create(name: &str) -> io::Result<Self> {
+        let path = if name.contains('/') {
+            PathBuf::from(name)
+        } else {
+            Path::new("/dev/shm").join(name)
+        };
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path)?;
+        file.set_len(SEGMENT_SIZE as u64)?;
+        let map = map_segment(&file, libc::PROT_READ | libc::PROT_WRITE)?;
+        Ok(Self { map })
+    }
+
+    fn words(&self) -> &[AtomicU32] {
+        unsafe { std::slice::from_raw_parts(self.map.as_ptr(), SEGMENT_SIZE / 4) }
+    }
+
+    pub fn publish(&mut self, snapshot: &Snapshot) {
+        let mut payload = Vec::with_capacity(SEGMENT_SIZE - 4);
+        payload.extend_from_slice(&(snapshot.state as u32).to_ne_bytes());
+        payload.extend_from_slice(&snapshot.x.to_ne_bytes());
+        payload.extend_from_slice(&snapshot.y.to_ne_bytes());
+        payload.extend_from_slice(&snapshot.angle.unwrap_or(f64::NAN).to_ne_bytes());
+        payload.extend_from_slice(&snapshot.magnitude.to_ne_bytes());
+        payload.extend_from_slice(&snapshot.wheel_angle.to_ne_bytes());
+        let micros = snapshot.time.seconds() * 1_000_000 + snapshot.time.microseconds();
+        payload.extend_from_slice(&micros.to_ne_bytes());
+        let turns = snapshot.wheel_angle / TAU;
+        payload.extend_from_slice(&(turns.trunc() as i32).to_ne_bytes());
+        payload.extend_from_slice(&0u32.to_ne_bytes());
+        payload.extend_from_slice(&turns.fract().to_ne_bytes());
+
+        let (sequence, fields) = self.words().split_first().unwrap();
+        let current = sequence.load(Ordering::Relaxed);
+        sequence.store(current.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        for (field, bytes) in fields.iter().zip(payload.chunks_exact(4)) {
+            field.store(u32::from_ne_bytes(bytes.try_into().unwrap()), Ordering::Relaxed);
+        }
+        sequence.store(current.wrapping_add(2), Ordering::Release);
+    }
+}
+
+impl Drop for SharedState {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.map.as_ptr().cast(), SEGMENT_SIZE) };
+    }
+}
+
+fn map_segment(file: &File, protection: libc::c_int) -> io::Result<NonNull<AtomicU32>> {
+    let map = unsafe { libc::mmap(ptr::null_mut(), SEGMENT_SIZE, protection, libc::MAP_SHARED, file.as_raw_fd(), 0) };
+    if map == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(NonNull::new(map.cast()).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::{Arc, atomic::AtomicBool}, thread};
+
+    // the reader side of the seqlock, the way an overlay maps the segment
+    struct Reader {
+        map: NonNull<AtomicU32>,
+    }
+
+    impl Reader {
+        fn open(path: &Path) -> Self {
+            Self { map: map_segment(&File::open(path).unwrap(), libc::PROT_READ).unwrap() }
+        }
+
+        fn read(&self) -> Vec<u8> {
+            let words = unsafe { std::slice::from_raw_parts(self.map.as_ptr().cast_const(), SEGMENT_SIZE / 4) };
+            loop {
+                let before = words[0].load(Ordering::Acquire);
+                if before % 2 == 1 {
+                    continue;
+                }
+                let bytes: Vec<u8> = words.iter().flat_map(|word| word.load(Ordering::Relaxed).to_ne_bytes()).collect();
+                fence(Ordering::Acquire);
+                if words[0].load(Ordering::Relaxed) == before {
+                    return bytes;
+                }
+            }
+        }
+    }
+
+    impl Drop for Reader {
+        fn drop(&mut self) {
+            unsafe { libc::munmap(self.map.as_ptr().cast(), SEGMENT_SIZE) };
+        }
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn i32_at(bytes: &[u8], offset: usize) -> i32 {
+        i32::from_ne_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn f64_at(bytes: &[u8], offset: usize) -> f64 {
+        f64::from_ne_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn segment(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("analogstick2wheel-{name}-{}", std::process::id()))
+    }
+
+    fn snapshot(x: i32, wheel_angle: f64) -> Snapshot {
+        Snapshot {
+            time: EventTime::new(12, 345678),
+            state: State::Gripped,
+            x,
+            y: -x,
+            angle: Some(1.5),
+            magnitude: 0.75,
+            wheel_angle,
+        }
+    }
+
+    #[test]
+    fn a_published_snapshot_reads_back() {
+        let path = segment("shm-round-trip");
+        let mut shared = SharedState::create(path.to_str().unwrap()).unwrap();
+        shared.publish(&snapshot(100, (-450.0f64).to_radians()));
+        let bytes = Reader::open(&path).read();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(u32_at(&bytes, 0), 2);
+        assert_eq!(u32_at(&bytes, 4), 1);
+        assert_eq!((i32_at(&bytes, 8), i32_at(&bytes, 12)), (100, -100));
+        assert_eq!((f64_at(&bytes, 16), f64_at(&bytes, 24)), (1.5, 0.75));
+        assert_eq!(f64_at(&bytes, 32), (-450.0f64).to_radians());
+        assert_eq!(i64::from_ne_bytes(bytes[40..48].try_into().unwrap()), 12_345_678);
+        assert_eq!(i32_at(&bytes, 48), -1);
+        assert!((f64_at(&bytes, 56) + 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn readers_never_see_a_torn_snapshot() {
+        let path = segment("shm-torn");
+        let mut shared = SharedState::create(path.to_str().unwrap()).unwrap();
+        let reader = Reader::open(&path);
+        let done = Arc::new(AtomicBool::new(false));
+        let writer = {
+            let done = done.clone();
+            thread::spawn(move || {
+                for x in 1..100_000 {
+                    shared.publish(&snapshot(x, x as f64));
+                }
+                done.store(true, Ordering::SeqCst);
+            })
+        };
+        while !done.load(Ordering::SeqCst) {
+            let bytes = reader.read();
+            let x = i32_at(&bytes, 8);
+            assert_eq!(i32_at(&bytes, 12), -x);
+            assert_eq!(f64_at(&bytes, 32), x as f64);
+        }
+        writer.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn the_turn_indicator_follows_the_wheel_across_turns() {
+        let path = segment("shm-turns");
+        let mut shared = SharedState::create(path.to_str().unwrap()).unwrap();
+        let reader = Reader::open(&path);
+        for (degrees, turns, rest) in [(0.0, 0, 0.0), (90.0, 0, 0.25), (405.0, 1, 0.125), (-720.0, -2, 0.0), (-810.0, -2, -0.25), (1260.0, 3, 0.5)] {
+            shared.publish(&snapshot(0, f64::to_radians(degrees)));
+            let bytes = reader.read();
+            assert_eq!(i32_at(&bytes, 48), turns, "{degrees}");
+            assert!((f64_at(&bytes, 56) - rest).abs() < 1e-9, "{degrees}: {}", f64_at(&bytes, 56));
+            // whole and fractional turns add back up to the wheel angle
+            assert!(((i32_at(&bytes, 48) as f64 + f64_at(&bytes, 56)) * 360.0 - degrees).abs() < 1e-6);
+        }
+        drop(reader);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
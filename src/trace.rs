@@ -0,0 +1,152 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use crate::rotate::RotatingFile;
+
+const HISTORY: usize = 16;
+
+// a --debug-trigger condition, checked once per wheel report
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    // the wheel moved more than this many radians in one report
+    Delta(f64),
+    // the stick went from freewheel to gripped or back
+    Transition,
+}
+
+impl Trigger {
+    // `delta>DEGREES` or `transition`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "transition" => Ok(Trigger::Transition),
+            term => match term.strip_prefix("delta>").map(|v| v.trim().parse::<f64>()) {
+                Some(Ok(v)) if v.is_finite() && v >= 0.0 => Ok(Trigger::Delta(v.to_radians())),
+                _ => Err(format!("unknown debug trigger '{term}', expected delta>DEGREES or transition")),
+            },
+        }
+    }
+
+    pub fn fires(self, wheel_delta: f64, transition: bool) -> bool {
+        match self {
+            Trigger::Delta(limit) => wheel_delta.abs() > limit,
+            Trigger::Transition => transition,
+        }
+    }
+}
+
+// how the per-frame line goes to stderr
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugStyle {
+    Compact,
+    // one field per line, frames separated by a blank line
+    Multiline,
+    // nothing on stderr, --debug-trigger snapshots still get compact lines
+    Off,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugField {
+    X,
+    Y,
+    Angle,
+    Magnitude,
+    State,
+    Wheel,
+}
+
+impl DebugField {
+    pub const ALL: [DebugField; 6] = [DebugField::X, DebugField::Y, DebugField::Angle, DebugField::Magnitude, DebugField::State, DebugField::Wheel];
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "x" => Ok(DebugField::X),
+            "y" => Ok(DebugField::Y),
+            "angle" => Ok(DebugField::Angle),
+            "magnitude" => Ok(DebugField::Magnitude),
+            "state" => Ok(DebugField::State),
+            "wheel" => Ok(DebugField::Wheel),
+            field => Err(format!("unknown debug field '{field}', expected x, y, angle, magnitude, state or wheel")),
+        }
+    }
+}
+
+// the last few frame lines, dumped along with the wheel state when a trigger
+// fires so intermittent glitches can be looked at after the fact
+#[derive(Clone)]
+pub struct FrameHistory {
+    frames: VecDeque<String>,
+    // stderr when None
+    sink: Option<Arc<Mutex<RotatingFile>>>,
+}
+
+impl FrameHistory {
+    pub fn new(sink: Option<RotatingFile>) -> Self {
+        Self { frames: VecDeque::with_capacity(HISTORY), sink: sink.map(|file| Arc::new(Mutex::new(file))) }
+    }
+
+    pub fn record(&mut self, line: String) {
+        if self.frames.len() == HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(line);
+    }
+
+    pub fn dump(&self, reason: &str, state: &str) {
+        let mut snapshot = format!("debug trigger: {reason}\n  {state}\n");
+        for (age, frame) in self.frames.iter().rev().enumerate() {
+            snapshot += &format!("  frame -{age}: {frame}\n");
+        }
+        let result = match &self.sink {
+            Some(file) => {
+                let mut file = file.lock().unwrap();
+                file.write_all(snapshot.as_bytes()).and_then(|_| file.flush())
+            },
+            None => io::stderr().write_all(snapshot.as_bytes()),
+        };
+        if let Err(e) = result {
+            eprintln!("warning: writing debug snapshot failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{env, fs};
+
+    #[test]
+    fn triggers_parse_and_fire() {
+        assert_eq!(Trigger::parse("transition"), Ok(Trigger::Transition));
+        assert_eq!(Trigger::parse(" delta>10 "), Ok(Trigger::Delta(10f64.to_radians())));
+        assert!(Trigger::parse("delta>-1").is_err());
+        assert!(Trigger::parse("angle>10").is_err());
+        let delta = Trigger::Delta(10f64.to_radians());
+        assert!(delta.fires(-11f64.to_radians(), false));
+        assert!(!delta.fires(9f64.to_radians(), true));
+        assert!(Trigger::Transition.fires(0.0, true));
+        assert!(!Trigger::Transition.fires(1.0, false));
+    }
+
+    #[test]
+    fn a_snapshot_holds_the_state_and_the_last_frames_newest_first() {
+        let path = env::temp_dir().join(format!("analogstick2wheel-debug-snapshot-{}", std::process::id()));
+        let mut history = FrameHistory::new(Some(RotatingFile::create(&path, None).unwrap()));
+        for frame in 0..HISTORY + 4 {
+            history.record(format!("frame {frame}"));
+        }
+        history.dump("Transition", "wheel_angle: 12.5");
+        let snapshot = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = snapshot.lines().collect();
+        assert_eq!(lines[0], "debug trigger: Transition");
+        assert_eq!(lines[1], "  wheel_angle: 12.5");
+        assert_eq!(lines[2], format!("  frame -0: frame {}", HISTORY + 3));
+        // only the last HISTORY frames are kept
+        assert_eq!(lines.len(), 2 + HISTORY);
+        assert_eq!(lines[lines.len() - 1], "  frame -15: frame 4");
+    }
+}
@@ -0,0 +1,167 @@
+use input_linux::{
+    AbsoluteInfo, AbsoluteInfoSetup, EventKind, ForceFeedbackKind, InputId, Key, MiscKind, RelativeAxis, UInputHandle, sys,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    sync::Arc,
+};
+
+use crate::config::{parse_axis, parse_key, parse_relative_axis};
+
+// room for as many effects as the game likes to keep uploaded at once
+const FF_EFFECTS_MAX: u32 = 16;
+
+// the subset of interception-tools' uinput yaml our layouts use
+#[derive(Debug, Default)]
+pub struct Description {
+    pub name: String,
+    pub id: InputId,
+    pub keys: Vec<Key>,
+    pub relative: Vec<RelativeAxis>,
+    pub misc: Vec<MiscKind>,
+    pub force_feedback: Vec<ForceFeedbackKind>,
+    pub axes: Vec<AbsoluteInfoSetup>,
+}
+
+impl Description {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut desc = Self::default();
+        desc.id.bustype = sys::BUS_VIRTUAL;
+        for (n, line) in text.lines().enumerate() {
+            let content = line.trim();
+            if content.is_empty() || content.starts_with('#') {
+                continue;
+            }
+            let at = |e: String| format!("line {}: {e}", n + 1);
+            let (key, value) = content.split_once(':').ok_or_else(|| at("expected KEY: VALUE".to_string()))?;
+            let value = value.trim();
+            let indent = line.len() - line.trim_start().len();
+            match (indent, key) {
+                (0, "NAME") => desc.name = value.to_string(),
+                (0, "PRODUCT") => desc.id.product = parse_number(key, value).map_err(at)?,
+                (0, "VENDOR") => desc.id.vendor = parse_number(key, value).map_err(at)?,
+                (0, "BUSTYPE") => desc.id.bustype = parse_bus(value).map_err(at)?,
+                // evdev reports its own version, there's nothing to set this on
+                (0, "DRIVER_VERSION") | (0, "EVENTS") | (2, "EV_ABS") | (2, "EV_SYN") => (),
+                (2, kind) => desc.add_codes(kind, value).map_err(at)?,
+                (4, axis) => desc.axes.push(AbsoluteInfoSetup {
+                    axis: parse_axis(axis).map_err(at)?,
+                    info: AbsoluteInfo::default(),
+                }),
+                (6, field) => {
+                    let info = &mut desc.axes.last_mut().ok_or_else(|| at(format!("{field} outside of an axis")))?.info;
+                    let value = value.parse::<i32>().map_err(|_| at(format!("{field} must be a whole number")))?;
+                    match field {
+                        "VALUE" => info.value = value,
+                        "MIN" => info.minimum = value,
+                        "MAX" => info.maximum = value,
+                        "FUZZ" => info.fuzz = value,
+                        "FLAT" => info.flat = value,
+                        "RESOLUTION" => info.resolution = value,
+                        _ => return Err(at(format!("unknown axis property '{field}'"))),
+                    }
+                },
+                _ => return Err(at(format!("unexpected '{key}'"))),
+            }
+        }
+        Ok(desc)
+    }
+
+    // `[CODE, CODE, ...]` under one of the EV_ kinds
+    fn add_codes(&mut self, kind: &str, list: &str) -> Result<(), String> {
+        let list = list.strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+            .ok_or_else(|| format!("{kind} must be a [list] of codes"))?;
+        for code in list.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            match kind {
+                "EV_KEY" => self.keys.push(parse_key(code)?),
+                "EV_REL" => self.relative.push(parse_relative_axis(code)?),
+                "EV_MSC" => self.misc.push(find_named(MiscKind::iter(), "MSC_", code)?),
+                "EV_FF" => self.force_feedback.push(find_named(ForceFeedbackKind::iter(), "FF_", code)?),
+                _ => return Err(format!("unsupported event kind {kind}")),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn create(&self) -> io::Result<UInputHandle<File>> {
+        let file = OpenOptions::new().read(true).write(true).open("/dev/uinput")?;
+        let handle = UInputHandle::new(file);
+        handle.set_evbit(EventKind::Synchronize)?;
+        if !self.keys.is_empty() {
+            handle.set_evbit(EventKind::Key)?;
+        }
+        for key in &self.keys {
+            handle.set_keybit(*key)?;
+        }
+        if !self.axes.is_empty() {
+            handle.set_evbit(EventKind::Absolute)?;
+        }
+        for setup in &self.axes {
+            handle.set_absbit(setup.axis)?;
+        }
+        if !self.relative.is_empty() {
+            handle.set_evbit(EventKind::Relative)?;
+        }
+        for axis in &self.relative {
+            handle.set_relbit(*axis)?;
+        }
+        if !self.misc.is_empty() {
+            handle.set_evbit(EventKind::Misc)?;
+        }
+        for misc in &self.misc {
+            handle.set_mscbit(*misc)?;
+        }
+        let effects = if self.force_feedback.is_empty() {
+            0
+        } else {
+            handle.set_evbit(EventKind::ForceFeedback)?;
+            for kind in &self.force_feedback {
+                handle.set_ffbit(*kind)?;
+            }
+            FF_EFFECTS_MAX
+        };
+        handle.create(&self.id, self.name.as_bytes(), effects, &self.axes)?;
+        Ok(handle)
+    }
+}
+
+fn parse_number(key: &str, value: &str) -> Result<u16, String> {
+    value.parse::<u16>().map_err(|_| format!("{key} must be a number between 0 and {}", u16::MAX))
+}
+
+fn parse_bus(value: &str) -> Result<u16, String> {
+    const BUSES: [(&str, u16); 5] = [
+        ("BUS_PCI", sys::BUS_PCI),
+        ("BUS_USB", sys::BUS_USB),
+        ("BUS_BLUETOOTH", sys::BUS_BLUETOOTH),
+        ("BUS_VIRTUAL", sys::BUS_VIRTUAL),
+        ("BUS_HOST", sys::BUS_HOST),
+    ];
+    BUSES.iter()
+        .find(|(name, _)| *name == value)
+        .map(|(_, bus)| *bus)
+        .or_else(|| value.parse().ok())
+        .ok_or_else(|| format!("unknown bus type '{value}'"))
+}
+
+// kernel names against the Debug names input_linux gives them, e.g. FF_RUMBLE and Rumble
+fn find_named<T: std::fmt::Debug>(mut kinds: impl Iterator<Item = T>, prefix: &str, value: &str) -> Result<T, String> {
+    let name = value.trim_start_matches(prefix).replace('_', "").to_uppercase();
+    kinds.find(|kind| format!("{kind:?}").to_uppercase() == name)
+        .ok_or_else(|| format!("unknown code '{value}'"))
+}
+
+// shares one uinput device between the output writer and the force feedback thread
+pub struct DeviceWriter(pub Arc<UInputHandle<File>>);
+
+impl io::Write for DeviceWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(&mut self.0.as_inner(), buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
@@ -0,0 +1,170 @@
+// recorded stick sessions replayed through the binary, with the wheel axis
+// values it emits compared against the checked in goldens. after an intended
+// change in behaviour, `REGENERATE_GOLDEN=1 cargo test --test replay`
+// rewrites the goldens from the current output
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+    thread,
+};
+
+// native struct input_event with 64-bit time, as interception-tools passes it on
+const EVENT_SIZE: usize = 24;
+const EV_ABS: u16 = 3;
+const ABS_X: u16 = 0;
+const ABS_Y: u16 = 1;
+const EV_SYN: u16 = 0;
+
+// one native input_event at time zero, which the binary restamps anyway
+fn event(kind: u16, code: u16, value: i32) -> Vec<u8> {
+    let mut bytes = vec![0; 16];
+    bytes.extend_from_slice(&kind.to_ne_bytes());
+    bytes.extend_from_slice(&code.to_ne_bytes());
+    bytes.extend_from_slice(&value.to_ne_bytes());
+    bytes
+}
+
+// the stick moved to x, y on whichever axes are given, then a SYN_REPORT
+fn stick(x: Option<i32>, y: Option<i32>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    if let Some(x) = x {
+        bytes.extend(event(EV_ABS, ABS_X, x));
+    }
+    if let Some(y) = y {
+        bytes.extend(event(EV_ABS, ABS_Y, y));
+    }
+    bytes.extend(event(EV_SYN, 0, 0));
+    bytes
+}
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+}
+
+fn replay(recording: &str, args: &[&str]) -> Vec<i32> {
+    run(recording, fs::read(fixture(recording)).unwrap(), args)
+}
+
+fn run(name: &str, input: Vec<u8>, args: &[&str]) -> Vec<i32> {
+    execute(name, input, args).stdout.chunks_exact(EVENT_SIZE)
+        .filter(|event| u16::from_ne_bytes([event[16], event[17]]) == EV_ABS && u16::from_ne_bytes([event[18], event[19]]) == ABS_X)
+        .map(|event| i32::from_ne_bytes([event[20], event[21], event[22], event[23]]))
+        .collect()
+}
+
+fn execute(name: &str, input: Vec<u8>, args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_analogstick2wheel"))
+        .args(["--debug-format", "off"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // fed from its own thread so a full output pipe can't stall the replay
+    let mut stdin = child.stdin.take().unwrap();
+    let feeder = thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().unwrap();
+    feeder.join().unwrap().unwrap();
+    assert!(output.status.success(), "{name}: exited with {}", output.status);
+    output
+}
+
+fn check(name: &str, args: &[&str]) {
+    let actual: String = replay(&format!("{name}.events"), args).iter().map(|value| format!("{value}\n")).collect();
+    let golden = fixture(&format!("{name}.golden"));
+    if env::var_os("REGENERATE_GOLDEN").is_some() {
+        fs::write(&golden, &actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden)
+        .unwrap_or_else(|e| panic!("{}: {e}, REGENERATE_GOLDEN=1 creates it", golden.display()));
+    // only the first divergence, everything after it follows from it
+    for (frame, (expected, actual)) in expected.lines().zip(actual.lines()).enumerate() {
+        assert_eq!(expected, actual, "{name}: frame {frame} diverges from {}", golden.display());
+    }
+    assert_eq!(expected.lines().count(), actual.lines().count(), "{name}: a different number of reports than {}", golden.display());
+}
+
+// a gripped stick turned a few degrees a report, through three quarters of a turn
+#[test]
+fn slow_turn() {
+    check("slow_turn", &[]);
+}
+
+// consecutive reports up to more than half a turn apart, kept going forward by --flick-threshold
+#[test]
+fn fast_flick() {
+    check("fast_flick", &["--flick-threshold", "120"]);
+}
+
+// no input at all: the centered report at exit, and with --initial-report one at startup too
+#[test]
+fn initial_report() {
+    assert_eq!(run("no input", Vec::new(), &[]), [32767]);
+    assert_eq!(run("no input", Vec::new(), &["--initial-report"]), [32767, 32767]);
+}
+
+// a grip let go and retaken on a controller syncing after every axis: the
+// half-updated frame in between is released, and with --split-regrip the
+// rotation across it doesn't count
+#[test]
+fn split_regrip() {
+    let input: Vec<u8> = [
+        stick(Some(0), Some(32767)),
+        stick(Some(-16384), Some(28378)),
+        stick(None, Some(0)),
+        stick(Some(-32767), None),
+    ].concat();
+    let args = ["--coalesce-axes", "--coalesce-timeout", "1000"];
+    let plain = run("regrip", input.clone(), &args);
+    let split = run("regrip", input, &[&args[..], &["--split-regrip"]].concat());
+    // the last value is the centered report at exit
+    let turned = plain[1] - plain[0];
+    assert!(turned > 0);
+    // 30 degrees of gripped turn, then 60 more across the release unless it's split
+    assert!((plain[2] - plain[0] - 3 * turned).abs() <= 1, "{plain:?}");
+    assert_eq!(split[..3], [plain[0], plain[1], plain[1]]);
+}
+
+// the same events from a producer with 32-bit timevals
+fn narrow(events: &[u8]) -> Vec<u8> {
+    events.chunks_exact(EVENT_SIZE).flat_map(|event| [&[0; 8], &event[16..]].concat()).collect()
+}
+
+// read with --input-time-bits 32 they steer exactly like the native ones
+#[test]
+fn input_time_bits() {
+    let input: Vec<u8> = [
+        stick(Some(0), Some(32767)),
+        stick(Some(-16384), Some(28378)),
+        stick(Some(-28378), Some(16384)),
+    ].concat();
+    let native = run("64-bit input", input.clone(), &["--input-time-bits", "64"]);
+    assert_eq!(native.len(), 4);
+    assert_eq!(run("32-bit input", narrow(&input), &["--input-time-bits", "32"]), native);
+}
+
+// grips and releases logged by --log-transitions, each with what decided it
+#[test]
+fn log_transitions() {
+    let input: Vec<u8> = [
+        stick(Some(0), Some(32767)),
+        stick(Some(0), Some(16000)),
+        stick(Some(0), Some(0)),
+        stick(Some(32767), Some(0)),
+    ].concat();
+    let output = execute("transitions", input, &["--log-transitions"]);
+    let log: Vec<String> = String::from_utf8(output.stderr).unwrap().lines()
+        .filter(|line| line.starts_with("transition: "))
+        .map(str::to_string)
+        .collect();
+    // staying released through the center isn't a transition
+    assert_eq!(log, [
+        "transition: Freewheel -> Gripped at 0.000000, magnitude 1.000 against threshold 0.920, angle 90.0",
+        "transition: Gripped -> Freewheel at 0.000000, magnitude 0.488 against threshold 0.920, angle 90.0",
+        "transition: Freewheel -> Gripped at 0.000000, magnitude 1.000 against threshold 0.920, angle 0.0",
+    ]);
+}